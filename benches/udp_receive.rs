@@ -0,0 +1,92 @@
+//! Compares the UDP receive paths: one recv_from call per datagram against batches
+//! read with recvmmsg (Linux only).
+//!
+//! cargo bench --bench udp_receive [-- datagrams [batch_size]]
+
+use std::net::UdpSocket as StdUdpSocket;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use jon_listen::listener::udp_server::UdpService;
+use jon_listen::settings::{BackpressurePolicy, Settings};
+use jon_listen::writer::backpressure::BackpressureAwareSender;
+use jon_listen::writer::file_writer::FileWriterCommand;
+use tokio::net::UdpSocket;
+use tokio::sync::{broadcast, mpsc};
+
+const PAYLOAD: &[u8] =
+    b"<14>Oct 11 22:14:15 host app: a benchmark message of about 100 bytes......";
+
+fn main() {
+    let mut args = std::env::args().skip(1).filter(|arg| arg != "--bench");
+    let datagrams: usize = args.next().map_or(200_000, |n| n.parse().unwrap());
+    let batch_size: usize = args.next().map_or(32, |n| n.parse().unwrap());
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        run("recv_from", None, datagrams).await;
+        if cfg!(target_os = "linux") {
+            run("recvmmsg", Some(batch_size), datagrams).await;
+        }
+    });
+}
+
+async fn run(name: &str, batch_size: Option<usize>, datagrams: usize) {
+    let mut settings = Settings::load().expect("config/default.toml");
+    settings.debug = false;
+    settings.server.udp_batch_size = batch_size;
+    let settings = Arc::new(settings);
+
+    let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(64 * 1024);
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr = socket.local_addr().unwrap();
+    let mut service = UdpService::new(
+        socket,
+        sender.into(),
+        0,
+        settings.clone(),
+        settings.server.clone(),
+    );
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+    let service = tokio::spawn(async move { service.run(shutdown_rx).await });
+
+    let start = Instant::now();
+    let client = std::thread::spawn(move || {
+        let socket = StdUdpSocket::bind("127.0.0.1:0").unwrap();
+        for _ in 0..datagrams {
+            let _ = socket.send_to(PAYLOAD, addr);
+        }
+    });
+
+    // Datagrams the kernel drops never arrive, stop once they stop coming
+    let mut received = 0;
+    let mut last = start;
+    while let Ok(Some(command)) = tokio::time::timeout(Duration::from_millis(500), rx.recv()).await
+    {
+        received += match command {
+            FileWriterCommand::WriteBatch(messages) => messages.len(),
+            _ => 1,
+        };
+        last = Instant::now();
+        if received == datagrams {
+            break;
+        }
+    }
+    client.join().unwrap();
+    let _ = shutdown_tx.send(());
+    let _ = service.await;
+
+    let elapsed = last.duration_since(start);
+    println!(
+        "{:<10} received {}/{} datagrams in {:?} ({:.0} datagrams/s)",
+        name,
+        received,
+        datagrams,
+        elapsed,
+        received as f64 / elapsed.as_secs_f64()
+    );
+}
@@ -38,9 +38,18 @@ pub enum FileWriterError {
         source: io::Error,
     },
 
+    #[error("Failed to sync file {path} to disk: {source}")]
+    SyncError { path: PathBuf, source: io::Error },
+
+    #[error("Spill queue {path} failed: {source}")]
+    SpillError { path: PathBuf, source: io::Error },
+
     #[error("Channel closed")]
     ChannelClosed,
 
+    #[error("Invalid prefix template: {0}")]
+    InvalidPrefix(String),
+
     #[error("Other error: {0}")]
     OtherError(String),
 }
@@ -67,6 +76,55 @@ pub enum RotationError {
     ChannelSendError(String),
 }
 
+/// Syslog parsing errors
+#[derive(Error, Debug, PartialEq)]
+pub enum SyslogError {
+    #[error("Missing or malformed PRI part")]
+    InvalidPri,
+
+    #[error("PRI value out of range: {0}")]
+    PriOutOfRange(u32),
+
+    #[error("Malformed RFC 5424 header: {0}")]
+    InvalidHeader(String),
+
+    #[error("Malformed structured data: {0}")]
+    InvalidStructuredData(String),
+}
+
+/// Routing configuration errors
+#[derive(Error, Debug, PartialEq)]
+pub enum RoutingError {
+    #[error("Route refers to unknown writer '{0}'")]
+    UnknownWriter(String),
+
+    #[error("Writer name '{0}' is reserved for [filewriter]")]
+    ReservedWriter(String),
+
+    #[error("Invalid peer_cidr '{0}'")]
+    InvalidCidr(String),
+
+    #[error("Invalid pattern '{pattern}': {reason}")]
+    InvalidPattern { pattern: String, reason: String },
+}
+
+/// Listener allow/deny list errors
+#[derive(Error, Debug, PartialEq)]
+pub enum AccessListError {
+    #[error("Invalid network '{0}' in allow/deny list")]
+    InvalidCidr(String),
+}
+
+/// Rate limit configuration errors
+#[derive(Error, Debug, PartialEq)]
+pub enum RateLimitError {
+    #[error("Invalid rate limit override cidr '{0}'")]
+    InvalidCidr(String),
+
+    #[error("Invalid rate limit: rate must be positive and burst at least 1, got rate {rate} and burst {burst}")]
+    InvalidRate { rate: f64, burst: f64 },
+}
+
 // Implement From traits for RotationError
 impl From<PatternError> for RotationError {
     fn from(error: PatternError) -> Self {
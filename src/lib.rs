@@ -1,149 +1,324 @@
 pub mod error;
 pub mod listener;
 pub mod metrics;
+pub mod reload;
+pub mod router;
 pub mod settings;
+mod time;
 pub mod writer;
 
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{Context, Result};
-use listener::Listener;
-use log::info;
+use anyhow::{bail, Context, Result};
+use error::RoutingError;
+use listener::access_list::AccessList;
+use listener::tcp_server::ConnectionLimit;
+use listener::{Listener, ListenerControls};
+use log::{error, info, warn};
+use reload::Reload;
+use router::{Router, DEFAULT_WRITER};
 use settings::Settings;
-use tokio::sync::broadcast;
-use writer::file_writer::FileWriter;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use writer::backpressure::BackpressureAwareSender;
+use writer::file_writer::{FileWriter, FileWriterCommand};
 
 // use std::borrow::Borrow; // not needed
 
 pub struct App;
 
+/// Requests a running app accepts besides shutdown
+#[derive(Debug)]
+pub enum Control {
+    /// Apply a freshly loaded configuration as far as possible without a restart
+    Reload(Box<Settings>),
+    /// Reopen every output file, e.g. after an external logrotate moved them
+    Reopen,
+}
+
+/// Handles used to apply a reloaded configuration to the running components
+struct LiveComponents {
+    writers: HashMap<String, (mpsc::Sender<FileWriterCommand>, BackpressureAwareSender)>,
+    listeners: Vec<(ConnectionLimit, AccessList)>,
+}
+
 impl App {
     pub async fn start_up(
         settings: Arc<Settings>,
         shutdown_rx: broadcast::Receiver<()>,
     ) -> Result<()> {
-        let mut file_writer = FileWriter::new(settings.buffer_bound, settings.filewriter.clone())
-            .await
-            .context("Failed to create FileWriter")?;
-
-        // Clone the sender before moving file_writer and wrap with backpressure-aware sender
-        use crate::writer::backpressure::BackpressureAwareSender;
-        let file_writer_tx = BackpressureAwareSender::new(
-            file_writer.tx.clone(),
-            settings.filewriter.backpressure_policy.clone(),
+        // Nothing ever sends on this channel, so the app is never reloaded nor reopened
+        let (_, control_rx) = mpsc::channel(1);
+        Self::start_up_with_control(settings, shutdown_rx, control_rx).await
+    }
+
+    /// Start the app, handling every request received on `control_rx` while it runs
+    pub async fn start_up_with_control(
+        settings: Arc<Settings>,
+        shutdown_rx: broadcast::Receiver<()>,
+        mut control_rx: mpsc::Receiver<Control>,
+    ) -> Result<()> {
+        // A named writer would silently take the place of [filewriter]
+        if settings.writers.contains_key(DEFAULT_WRITER) {
+            return Err(RoutingError::ReservedWriter(DEFAULT_WRITER.to_string()))
+                .context("Invalid routing configuration");
+        }
+        let mut writers = vec![(DEFAULT_WRITER.to_string(), settings.filewriter.clone())];
+        writers.extend(
+            settings
+                .writers
+                .iter()
+                .map(|(name, config)| (name.clone(), config.clone())),
         );
-        let settings_clone = settings.clone();
+        let mut file_paths = HashSet::new();
+        for (name, config) in &writers {
+            if !file_paths.insert(config.filedir.join(&config.filename)) {
+                bail!(
+                    "Writer '{}' writes to {:?}, which is already used by another writer",
+                    name,
+                    config.filedir.join(&config.filename)
+                );
+            }
+        }
 
-        // Clone shutdown receiver for each component
-        let listener_shutdown = shutdown_rx.resubscribe();
-        let file_writer_shutdown = shutdown_rx.resubscribe();
-        let rotation_shutdown = shutdown_rx.resubscribe();
         let mut shutdown_rx = shutdown_rx;
+        // Writers only stop once the listeners are done, to drain what they queued
+        let (drain_tx, _) = broadcast::channel(1);
+        let drain_timeout = Duration::from_millis(settings.drain_timeout_ms);
 
-        // Spawn listener as a concurrent task
-        let mut listener_handle = tokio::spawn(async move {
-            Listener::start(settings_clone, file_writer_tx, listener_shutdown)
-                .await
-                .context("Listener failed")?;
-            Ok::<(), anyhow::Error>(())
-        });
-
-        // Spawn file writer as a concurrent task
-        let mut file_writer_handle = tokio::spawn(async move {
-            file_writer
-                .start(file_writer_shutdown, rotation_shutdown)
+        // Create every FileWriter and keep a backpressure-aware sender for each of them
+        let mut writer_handles = JoinSet::new();
+        let mut senders = HashMap::new();
+        let mut live = LiveComponents {
+            writers: HashMap::new(),
+            listeners: Vec::new(),
+        };
+        for (name, config) in writers {
+            let mut file_writer = FileWriter::new(settings.buffer_bound, config.clone())
                 .await
-                .context("FileWriter failed")?;
-            Ok::<(), anyhow::Error>(())
-        });
+                .with_context(|| format!("Failed to create FileWriter '{}'", name))?;
+            file_writer.set_drain_timeout(drain_timeout);
+            file_writer.set_name(&name);
+            let sender = BackpressureAwareSender::new(
+                file_writer.tx.clone(),
+                config.backpressure_policy.clone(),
+            )
+            .with_spill_queue(file_writer.spill_queue())
+            .with_command_queue(file_writer.command_queue());
+            live.writers
+                .insert(name.clone(), (file_writer.tx.clone(), sender.clone()));
+            senders.insert(name.clone(), sender);
 
-        // Wait for shutdown signal or component failure
-        let shutdown_received = tokio::select! {
-            result = &mut listener_handle => {
-                match result {
-                    Ok(_) => {
-                        info!("Listener task completed unexpectedly");
-                        false // Component completed, not a graceful shutdown
-                    }
-                    Err(e) => {
-                        eprintln!("Listener task failed: {:#}", e);
-                        false // Component failed, not a graceful shutdown
+            // Rotation stops right away, the writer when it's told to drain
+            let file_writer_shutdown = drain_tx.subscribe();
+            let rotation_shutdown = shutdown_rx.resubscribe();
+            writer_handles.spawn(async move {
+                file_writer
+                    .start(file_writer_shutdown, rotation_shutdown)
+                    .await
+                    .with_context(|| format!("FileWriter '{}' failed", name))?;
+                Ok::<(), anyhow::Error>(())
+            });
+        }
+
+        let default_sender = senders
+            .remove(DEFAULT_WRITER)
+            .expect("default writer is always created");
+        let router = Router::new(default_sender, &senders, &settings.routes)
+            .context("Invalid routing configuration")?;
+        info!(
+            "Started {} file writer(s) with {} route(s)",
+            writer_handles.len(),
+            settings.routes.len()
+        );
+
+        // Spawn one task per configured listener, all sharing the same router
+        let mut listener_handles = JoinSet::new();
+        for server in settings.all_listeners() {
+            let settings_clone = settings.clone();
+            let router = router.clone();
+            let controls = ListenerControls::for_server(&server, shutdown_rx.resubscribe())
+                .with_context(|| {
+                    format!(
+                        "Invalid allow/deny lists or rate limit for {:?} listener on {}:{}",
+                        server.protocol, server.host, server.port
+                    )
+                })?;
+            live.listeners.push((
+                controls.connection_limit.clone(),
+                controls.access_list.clone(),
+            ));
+            listener_handles.spawn(async move {
+                let name = format!(
+                    "{:?} listener on {}:{}",
+                    server.protocol, server.host, server.port
+                );
+                Listener::start(settings_clone, server, router, controls)
+                    .await
+                    .with_context(|| format!("{} failed", name))?;
+                Ok::<(), anyhow::Error>(())
+            });
+        }
+        info!("Started {} listener(s)", listener_handles.len());
+
+        // Wait for shutdown signal or component failure, handling control requests meanwhile
+        let mut running = (*settings).clone();
+        let shutdown_received = loop {
+            tokio::select! {
+                Some(result) = listener_handles.join_next() => {
+                    match result {
+                        Ok(Ok(())) => info!("Listener task completed unexpectedly"),
+                        Ok(Err(e)) => eprintln!("Listener task failed: {:#}", e),
+                        Err(e) => eprintln!("Listener task panicked: {:#}", e),
                     }
+                    break false; // Component completed or failed, not a graceful shutdown
                 }
-            }
-            result = &mut file_writer_handle => {
-                match result {
-                    Ok(_) => {
-                        info!("FileWriter task completed unexpectedly");
-                        false // Component completed, not a graceful shutdown
-                    }
-                    Err(e) => {
-                        eprintln!("FileWriter task failed: {:#}", e);
-                        false // Component failed, not a graceful shutdown
+                Some(result) = writer_handles.join_next() => {
+                    match result {
+                        Ok(Ok(())) => info!("FileWriter task completed unexpectedly"),
+                        Ok(Err(e)) => eprintln!("FileWriter task failed: {:#}", e),
+                        Err(e) => eprintln!("FileWriter task panicked: {:#}", e),
                     }
+                    break false; // Component completed or failed, not a graceful shutdown
+                }
+                Some(control) = control_rx.recv() => match control {
+                    Control::Reopen => live.reopen().await,
+                    Control::Reload(loaded) => match Reload::plan(&running, *loaded) {
+                        Ok(reload) => {
+                            live.apply(&reload).await;
+                            running = reload.settings;
+                        }
+                        Err(e) => {
+                            error!("Configuration reload rejected, keeping the running configuration: {:#}", e);
+                            metrics::config::reload_failed();
+                        }
+                    },
+                },
+                _ = shutdown_rx.recv() => {
+                    info!("Shutdown signal received in App");
+                    break true; // Graceful shutdown initiated
                 }
-            }
-            _ = shutdown_rx.recv() => {
-                info!("Shutdown signal received in App");
-                true // Graceful shutdown initiated
             }
         };
 
-        // If shutdown was received, wait for both tasks to complete gracefully
+        // If shutdown was received, stop the components in phases: listeners first, then
+        // the writers drain whatever the listeners queued before they stopped
         if shutdown_received {
-            info!("Waiting for components to shut down gracefully...");
-
-            // Give components a moment to process shutdown signal
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-
-            // Wait for tasks to complete, with timeout
-            let shutdown_timeout = tokio::time::Duration::from_secs(5);
-            let start = tokio::time::Instant::now();
-
-            // Wait for listener to complete (if not already done)
-            if !listener_handle.is_finished() {
-                tokio::select! {
-                    result = &mut listener_handle => {
-                        match result {
-                            Ok(_) => info!("Listener task completed gracefully"),
-                            Err(e) => eprintln!("Listener task join error: {:#}", e),
-                        }
-                    }
-                    _ = tokio::time::sleep(shutdown_timeout) => {
-                        eprintln!("Warning: Listener shutdown timeout reached");
-                    }
-                }
-            } else {
-                // Task already completed, just get the result
-                match listener_handle.await {
-                    Ok(_) => info!("Listener task already completed"),
-                    Err(e) => eprintln!("Listener task join error: {:#}", e),
-                }
+            info!("Waiting for listeners to shut down...");
+            join_until(
+                &mut listener_handles,
+                Instant::now() + drain_timeout,
+                "Listener",
+            )
+            .await;
+
+            info!("Draining file writers...");
+            let _ = drain_tx.send(());
+            // Writers stop draining at the timeout on their own, leave them time to close
+            join_until(
+                &mut writer_handles,
+                Instant::now() + drain_timeout + WRITER_CLOSE_GRACE,
+                "FileWriter",
+            )
+            .await;
+        }
+
+        Ok(())
+    }
+}
+
+impl LiveComponents {
+    async fn reopen(&self) {
+        for (name, (tx, _)) in &self.writers {
+            if tx.send(FileWriterCommand::Reopen).await.is_err() {
+                warn!(
+                    "FileWriter '{}' is not running, can't reopen its file",
+                    name
+                );
             }
+        }
+        info!(
+            "Requested {} writer(s) to reopen their files",
+            self.writers.len()
+        );
+    }
 
-            // Wait for file writer to complete (if not already done)
-            let remaining_timeout = shutdown_timeout.saturating_sub(start.elapsed());
-            if !file_writer_handle.is_finished() {
-                tokio::select! {
-                    result = &mut file_writer_handle => {
-                        match result {
-                            Ok(_) => info!("FileWriter task completed gracefully"),
-                            Err(e) => eprintln!("FileWriter task join error: {:#}", e),
-                        }
-                    }
-                    _ = tokio::time::sleep(remaining_timeout) => {
-                        eprintln!("Warning: FileWriter shutdown timeout reached");
-                    }
-                }
-            } else {
-                // Task already completed, just get the result
-                match file_writer_handle.await {
-                    Ok(_) => info!("FileWriter task already completed"),
-                    Err(e) => eprintln!("FileWriter task join error: {:#}", e),
-                }
+    async fn apply(&self, reload: &Reload) {
+        for name in &reload.writers {
+            let config = match name.as_str() {
+                DEFAULT_WRITER => &reload.settings.filewriter,
+                _ => &reload.settings.writers[name],
+            };
+            let (tx, sender) = &self.writers[name];
+            sender.set_backpressure_policy(config.backpressure_policy.clone());
+            if tx
+                .send(FileWriterCommand::Reconfigure(Box::new(config.clone())))
+                .await
+                .is_err()
+            {
+                warn!("FileWriter '{}' is not running, can't reconfigure it", name);
+                continue;
+            }
+            info!("Applied reloaded settings of writer '{}'", name);
+        }
+        let listeners = reload.settings.all_listeners();
+        for &index in &reload.listeners {
+            let server = &listeners[index];
+            let (connection_limit, access_list) = &self.listeners[index];
+            connection_limit.set(server.max_connections);
+            // Already validated by Reload::plan
+            if let Err(e) = access_list.set(&server.allow, &server.deny) {
+                warn!("Keeping the running allow/deny lists: {}", e);
             }
+            info!(
+                "Applied reloaded settings of {:?} listener on {}:{}: {} max connections, {} allowed and {} denied network(s)",
+                server.protocol,
+                server.host,
+                server.port,
+                server.max_connections,
+                server.allow.len(),
+                server.deny.len()
+            );
         }
+        for change in &reload.restart_required {
+            warn!("Change to {} requires a restart to take effect", change);
+        }
+        info!(
+            "Configuration reloaded: {} writer(s) and {} listener(s) updated, {} change(s) pending a restart",
+            reload.writers.len(),
+            reload.listeners.len(),
+            reload.restart_required.len()
+        );
+        metrics::config::reloaded();
+    }
+}
 
-        Ok(())
+/// Time a writer gets after its drain timeout to flush, sync and close its file
+const WRITER_CLOSE_GRACE: Duration = Duration::from_secs(1);
+
+/// Wait for every task in the set, aborting the ones still running at the deadline
+async fn join_until(tasks: &mut JoinSet<Result<()>>, deadline: Instant, kind: &str) {
+    loop {
+        tokio::select! {
+            result = tasks.join_next() => {
+                match result {
+                    Some(Ok(_)) => info!("{} task completed gracefully", kind),
+                    Some(Err(e)) => eprintln!("{} task join error: {:#}", kind, e),
+                    None => break,
+                }
+            }
+            _ = tokio::time::sleep_until(deadline) => {
+                eprintln!(
+                    "Warning: {} shutdown timeout reached ({} still running)",
+                    kind,
+                    tasks.len()
+                );
+                tasks.abort_all();
+                break;
+            }
+        }
     }
 }
@@ -0,0 +1,63 @@
+use std::io;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock};
+
+use ipnet::IpNet;
+
+use crate::error::AccessListError;
+use crate::router::parse_net;
+use crate::settings::ServerConfig;
+
+/// Peers a listener accepts, replaceable while it runs. A peer is accepted when it
+/// matches no denied network and, if any network is allowed, one of them.
+#[derive(Debug, Clone)]
+pub struct AccessList(Arc<RwLock<Rules>>);
+
+#[derive(Debug)]
+struct Rules {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+}
+
+impl Rules {
+    fn parse(allow: &[String], deny: &[String]) -> Result<Self, AccessListError> {
+        let parse_all = |cidrs: &[String]| {
+            cidrs
+                .iter()
+                .map(|cidr| {
+                    parse_net(cidr).ok_or_else(|| AccessListError::InvalidCidr(cidr.clone()))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(Rules {
+            allow: parse_all(allow)?,
+            deny: parse_all(deny)?,
+        })
+    }
+}
+
+impl AccessList {
+    pub fn new(allow: &[String], deny: &[String]) -> Result<Self, AccessListError> {
+        let rules = Rules::parse(allow, deny)?;
+        Ok(AccessList(Arc::new(RwLock::new(rules))))
+    }
+
+    /// Access list of a listener, from its `allow` and `deny` settings
+    pub fn for_server(server: &ServerConfig) -> Result<Self, io::Error> {
+        Self::new(&server.allow, &server.deny)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Replace the lists, keeping the current ones if the new ones are invalid
+    pub fn set(&self, allow: &[String], deny: &[String]) -> Result<(), AccessListError> {
+        let rules = Rules::parse(allow, deny)?;
+        *self.0.write().unwrap() = rules;
+        Ok(())
+    }
+
+    pub fn permits(&self, peer: IpAddr) -> bool {
+        let rules = self.0.read().unwrap();
+        !rules.deny.iter().any(|net| net.contains(&peer))
+            && (rules.allow.is_empty() || rules.allow.iter().any(|net| net.contains(&peer)))
+    }
+}
@@ -0,0 +1,241 @@
+use std::io;
+
+use bytes::{Bytes, BytesMut};
+use tokio_util::codec::{Decoder, LinesCodecError};
+
+use crate::listener::metrics;
+use crate::settings::{OversizedPolicy, ServerConfig, DEFAULT_MAX_MESSAGE_SIZE};
+
+/// Appended to the messages cut by the Truncate policy
+pub const TRUNCATED_MARKER: &[u8] = b"...[truncated]";
+
+/// Largest message a listener accepts as is, and what it does with bigger ones
+#[derive(Debug, Clone)]
+pub struct SizeLimit {
+    pub max: usize,
+    pub policy: OversizedPolicy,
+}
+
+impl Default for SizeLimit {
+    fn default() -> Self {
+        SizeLimit {
+            max: DEFAULT_MAX_MESSAGE_SIZE,
+            policy: OversizedPolicy::Truncate,
+        }
+    }
+}
+
+impl SizeLimit {
+    /// Size limit of a listener, from its `max_message_size` and `oversized_policy` settings
+    pub fn for_server(server: &ServerConfig) -> Result<Self, io::Error> {
+        if server.max_message_size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "max_message_size must be greater than 0",
+            ));
+        }
+        Ok(SizeLimit {
+            max: server.max_message_size,
+            policy: server.oversized_policy.clone(),
+        })
+    }
+
+    /// Messages to forward for a message received whole, e.g. a UDP datagram. Split
+    /// parts share the buffer of the payload.
+    pub fn apply(&self, payload: Bytes) -> Parts {
+        if payload.len() <= self.max {
+            return Parts::whole(payload);
+        }
+        self.oversized();
+        match self.policy {
+            OversizedPolicy::Truncate => Parts::whole(truncated(&payload[..self.max])),
+            OversizedPolicy::Split => Parts::split(payload, self.max),
+            OversizedPolicy::Drop => Parts::none(),
+        }
+    }
+
+    pub(crate) fn oversized(&self) {
+        metrics::message_size::oversized(&self.policy);
+    }
+}
+
+/// Messages to forward for a payload: the payload itself, its parts of at most `max`
+/// bytes, or nothing
+#[derive(Debug)]
+pub struct Parts {
+    rest: Option<Bytes>,
+    max: usize,
+}
+
+impl Parts {
+    fn new(rest: Option<Bytes>, max: usize) -> Self {
+        Parts { rest, max }
+    }
+
+    fn whole(payload: Bytes) -> Self {
+        Self::new(Some(payload), usize::MAX)
+    }
+
+    fn split(payload: Bytes, max: usize) -> Self {
+        Self::new(Some(payload), max)
+    }
+
+    fn none() -> Self {
+        Self::new(None, usize::MAX)
+    }
+}
+
+impl Iterator for Parts {
+    type Item = Bytes;
+
+    fn next(&mut self) -> Option<Bytes> {
+        let rest = self.rest.as_mut()?;
+        if rest.len() <= self.max {
+            return self.rest.take();
+        }
+        Some(rest.split_to(self.max))
+    }
+}
+
+pub(crate) fn truncated(kept: &[u8]) -> Bytes {
+    let mut message = BytesMut::with_capacity(kept.len() + TRUNCATED_MARKER.len());
+    message.extend_from_slice(kept);
+    message.extend_from_slice(TRUNCATED_MARKER);
+    message.freeze()
+}
+
+/// What is being done with the line at the start of the buffer
+#[derive(Debug, Default, PartialEq)]
+enum LineState {
+    #[default]
+    Normal,
+    /// Oversized line sent in parts, the rest is the next part
+    Splitting,
+    /// Oversized line truncated or dropped, the rest is skipped up to its LF
+    Skipping,
+}
+
+/// Frames terminated by LF, at most `max` bytes long without their line ending. Oversized
+/// lines are handled by the limit's policy as their bytes arrive, so that they never
+/// take more than `max` bytes of memory.
+#[derive(Debug)]
+pub struct LineFramer {
+    limit: SizeLimit,
+    // Index to resume the LF search from, to avoid rescanning partial lines
+    next_index: usize,
+    state: LineState,
+}
+
+impl LineFramer {
+    pub fn new(limit: SizeLimit) -> Self {
+        LineFramer {
+            limit,
+            next_index: 0,
+            state: LineState::Normal,
+        }
+    }
+
+    /// Whether part of a line was already read from the buffer
+    pub fn in_progress(&self) -> bool {
+        self.next_index > 0 || self.state != LineState::Normal
+    }
+
+    /// Next line, without its line ending, once it is complete or known to be oversized.
+    /// Lines are split off the buffer, without copying them.
+    pub fn decode(&mut self, buf: &mut BytesMut) -> Option<Bytes> {
+        loop {
+            // An LF right after `max` bytes still ends a line within the limit
+            let read_to = buf.len().min(self.limit.max + 1);
+            match buf[self.next_index..read_to]
+                .iter()
+                .position(|b| *b == b'\n')
+            {
+                Some(offset) => {
+                    let mut line = buf.split_to(self.next_index + offset + 1);
+                    self.next_index = 0;
+                    let state = std::mem::take(&mut self.state);
+                    line.truncate(line.len() - 1);
+                    if line.last() == Some(&b'\r') {
+                        line.truncate(line.len() - 1);
+                    }
+                    // Nothing is left of a split line that was a multiple of `max` long
+                    if state == LineState::Skipping
+                        || (state == LineState::Splitting && line.is_empty())
+                    {
+                        continue;
+                    }
+                    return Some(line.freeze());
+                }
+                None if buf.len() > self.limit.max => {
+                    let part = buf.split_to(self.limit.max);
+                    self.next_index = 0;
+                    if self.state == LineState::Skipping {
+                        continue;
+                    }
+                    if self.state == LineState::Normal {
+                        self.limit.oversized();
+                    }
+                    match self.limit.policy {
+                        OversizedPolicy::Split => {
+                            self.state = LineState::Splitting;
+                            return Some(part.freeze());
+                        }
+                        OversizedPolicy::Truncate => {
+                            self.state = LineState::Skipping;
+                            return Some(truncated(&part));
+                        }
+                        OversizedPolicy::Drop => self.state = LineState::Skipping,
+                    }
+                }
+                None => {
+                    self.next_index = buf.len();
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Last line of a stream, which may lack its LF
+    pub fn decode_eof(&mut self, buf: &mut BytesMut) -> Option<Bytes> {
+        if let Some(line) = self.decode(buf) {
+            return Some(line);
+        }
+        self.next_index = 0;
+        let skipping = std::mem::take(&mut self.state) == LineState::Skipping;
+        let mut line = buf.split();
+        if skipping || line.is_empty() {
+            return None;
+        }
+        if line.last() == Some(&b'\r') {
+            line.truncate(line.len() - 1);
+        }
+        Some(line.freeze())
+    }
+}
+
+/// Framing for raw messages over TCP: one message per line, kept as received
+#[derive(Debug)]
+pub struct RawCodec {
+    lines: LineFramer,
+}
+
+impl RawCodec {
+    pub fn new(limit: SizeLimit) -> Self {
+        RawCodec {
+            lines: LineFramer::new(limit),
+        }
+    }
+}
+
+impl Decoder for RawCodec {
+    type Item = Bytes;
+    type Error = LinesCodecError;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, LinesCodecError> {
+        Ok(self.lines.decode(buf))
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, LinesCodecError> {
+        Ok(self.lines.decode_eof(buf))
+    }
+}
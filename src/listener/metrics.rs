@@ -1,4 +1,4 @@
-use metrics::{counter, gauge};
+use metrics::{counter, gauge, histogram};
 
 /// Track TCP connection metrics
 pub mod tcp {
@@ -15,6 +15,25 @@ pub mod tcp {
     pub fn connection_rejected() {
         counter!("tcp_connections_rejected", 1);
     }
+
+    /// A connection closed by its idle, read or lifetime timeout
+    pub fn connection_timed_out(reason: &'static str) {
+        counter!("tcp_connections_timed_out", 1, "reason" => reason);
+    }
+
+    /// A connection from a peer not permitted by the allow/deny lists
+    pub fn connection_denied() {
+        counter!("tcp_connections_denied_total", 1);
+    }
+}
+
+/// Track TLS metrics
+pub mod tls {
+    use super::*;
+
+    pub fn handshake_failed() {
+        counter!("tls_handshake_failures_total", 1);
+    }
 }
 
 /// Track UDP metrics
@@ -24,4 +43,73 @@ pub mod udp {
     pub fn datagram_received() {
         counter!("udp_datagrams_received_total", 1);
     }
+
+    /// A datagram from a peer not permitted by the allow/deny lists
+    pub fn datagram_denied() {
+        counter!("udp_datagrams_denied_total", 1);
+    }
+
+    /// Datagrams received by a single recvmmsg call
+    pub fn batch_received(datagrams: usize) {
+        histogram!("udp_recv_batch_size", datagrams as f64);
+    }
+
+    /// Datagrams the kernel dropped for a socket since the last collection
+    pub fn kernel_drops(socket: &str, count: u64) {
+        counter!("udp_kernel_drops_total", count, "socket" => socket.to_string());
+    }
+
+    /// Bytes waiting in the receive queue of a socket
+    pub fn rx_queue(socket: &str, bytes: u64) {
+        gauge!("udp_rx_queue_bytes", bytes as f64, "socket" => socket.to_string());
+    }
+
+    /// Size of the receive buffer of a socket, the most the queue can hold
+    pub fn recv_buffer(socket: &str, bytes: usize) {
+        gauge!("udp_recv_buffer_bytes", bytes as f64, "socket" => socket.to_string());
+    }
+}
+
+/// Track syslog parsing metrics
+pub mod syslog {
+    use super::*;
+
+    pub fn parsed() {
+        counter!("syslog_messages_parsed_total", 1);
+    }
+
+    pub fn parse_error() {
+        counter!("syslog_parse_errors_total", 1);
+    }
+}
+
+/// Track per-peer rate limiting
+pub mod rate_limit {
+    use super::*;
+    use crate::settings::RateLimitAction;
+
+    /// A message above the rate limit of its peer, dropped or delayed
+    pub fn limited(listener: &str, peer: String, action: &RateLimitAction) {
+        let action = match action {
+            RateLimitAction::Drop => "drop",
+            RateLimitAction::Delay => "delay",
+        };
+        counter!("rate_limited_messages_total", 1, "listener" => listener.to_string(), "peer" => peer, "action" => action);
+    }
+}
+
+/// Track messages over the maximum message size
+pub mod message_size {
+    use super::*;
+    use crate::settings::OversizedPolicy;
+
+    /// An oversized message, truncated, split or dropped
+    pub fn oversized(policy: &OversizedPolicy) {
+        let policy = match policy {
+            OversizedPolicy::Truncate => "truncate",
+            OversizedPolicy::Split => "split",
+            OversizedPolicy::Drop => "drop",
+        };
+        counter!("oversized_messages_total", 1, "policy" => policy);
+    }
 }
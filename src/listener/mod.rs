@@ -1,26 +1,84 @@
-use crate::listener::tcp_server::TcpServer;
+use crate::listener::access_list::AccessList;
+use crate::listener::rate_limit::RateLimiter;
+use crate::listener::tcp_server::{ConnectionLimit, TcpServer};
 use crate::listener::udp_server::UdpServer;
-use crate::settings::{ProtocolType, Settings};
-use crate::writer::backpressure::BackpressureAwareSender;
+use crate::router::Router;
+use crate::settings::{MessageFormat, ProtocolType, ServerConfig, Settings};
+use crate::writer::file_writer::{FileWriterCommand, Message};
 use std::io;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::broadcast;
 
+pub mod access_list;
+pub mod message_size;
 pub mod metrics;
+pub mod rate_limit;
+#[cfg(target_os = "linux")]
+pub mod recvmmsg;
+pub mod syslog;
 pub mod tcp_server;
+pub mod tls;
 pub mod udp_server;
+#[cfg(target_os = "linux")]
+pub mod udp_stats;
+
+/// Everything a listener can be controlled with from outside while it runs
+#[derive(Debug)]
+pub struct ListenerControls {
+    /// Maximum concurrent connections, UDP listeners have none and ignore it
+    pub connection_limit: ConnectionLimit,
+    pub access_list: AccessList,
+    pub rate_limiter: Option<RateLimiter>,
+    pub shutdown_rx: broadcast::Receiver<()>,
+}
+
+impl ListenerControls {
+    /// Controls as configured for `server`
+    pub fn for_server(
+        server: &ServerConfig,
+        shutdown_rx: broadcast::Receiver<()>,
+    ) -> Result<Self, io::Error> {
+        Ok(ListenerControls {
+            connection_limit: ConnectionLimit::new(server.max_connections),
+            access_list: AccessList::for_server(server)?,
+            rate_limiter: RateLimiter::for_server(server)?,
+            shutdown_rx,
+        })
+    }
+}
 
 pub struct Listener;
 
 impl Listener {
     pub async fn start(
         settings: Arc<Settings>,
-        sender: BackpressureAwareSender,
-        shutdown_rx: broadcast::Receiver<()>,
+        server: ServerConfig,
+        router: Router,
+        controls: ListenerControls,
     ) -> Result<(), io::Error> {
-        match settings.server.protocol {
-            ProtocolType::TCP => TcpServer::start(settings, sender, shutdown_rx).await,
-            ProtocolType::UDP => UdpServer::start(settings, sender, shutdown_rx).await,
+        match server.protocol {
+            ProtocolType::TCP => TcpServer::start(settings, server, router, controls).await,
+            ProtocolType::UDP => UdpServer::start(settings, server, router, controls).await,
         }
     }
 }
+
+/// Build the FileWriter command for a message received in the given format
+pub(crate) fn command_for(format: &MessageFormat, message: Message) -> FileWriterCommand {
+    FileWriterCommand::Write(parsed(format, message))
+}
+
+/// Parse a message received in the given format
+pub(crate) fn parsed(format: &MessageFormat, message: Message) -> Message {
+    match format {
+        MessageFormat::Raw => message,
+        MessageFormat::Syslog => syslog::parsed(message),
+    }
+}
+
+/// Address of a peer as every other component sees it. Peers on a dual-stack socket
+/// show up as IPv4-mapped IPv6 addresses, they are turned back into IPv4 ones.
+pub(crate) fn canonical_peer(peer: SocketAddr) -> SocketAddr {
+    SocketAddr::new(peer.ip().to_canonical(), peer.port())
+}
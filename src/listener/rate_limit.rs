@@ -0,0 +1,190 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use ipnet::IpNet;
+use tokio::time::Instant;
+
+use crate::error::RateLimitError;
+use crate::listener::metrics;
+use crate::router::parse_net;
+use crate::settings::{RateLimitAction, RateLimitConfig, ServerConfig};
+
+/// Peers tracked at most, the least recently seen one is forgotten to make room
+const MAX_TRACKED_PEERS: usize = 10_000;
+
+/// Limited peers of a listener with a metrics series of their own, the others share one
+const MAX_PEER_SERIES: usize = 100;
+
+/// Token buckets of the peers of a listener, shared by all its connections
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    /// Address of the listener, as configured, labelling its metrics
+    listener: Arc<str>,
+    action: RateLimitAction,
+    limit: Limit,
+    overrides: Vec<(IpNet, Limit)>,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limit {
+    rate: f64,
+    burst: f64,
+}
+
+impl Limit {
+    fn new(rate: f64, burst: Option<f64>) -> Result<Self, RateLimitError> {
+        let burst = burst.unwrap_or(rate.max(1.0));
+        // Negated so that NaN is rejected too
+        if !(rate > 0.0 && burst >= 1.0) {
+            return Err(RateLimitError::InvalidRate { rate, burst });
+        }
+        Ok(Limit { rate, burst })
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: Limit,
+    /// Below zero when messages are waiting for their turn with the Delay action
+    tokens: f64,
+    updated: Instant,
+    /// Position of the peer in `Buckets::by_use`
+    used: u64,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst);
+        self.updated = now;
+    }
+}
+
+/// Buckets by peer, along with the order the peers were last seen in
+#[derive(Debug, Default)]
+struct Buckets {
+    by_peer: HashMap<IpAddr, Bucket>,
+    by_use: BTreeMap<u64, IpAddr>,
+    uses: u64,
+    /// Peers labelling their own series of the rate limiting metrics
+    labelled: HashSet<IpAddr>,
+}
+
+impl Buckets {
+    /// Label of `peer` in the rate limiting metrics: its address for the first
+    /// MAX_PEER_SERIES peers limited, "other" for the later ones
+    fn peer_label(&mut self, peer: IpAddr) -> String {
+        if self.labelled.contains(&peer)
+            || (self.labelled.len() < MAX_PEER_SERIES && self.labelled.insert(peer))
+        {
+            peer.to_string()
+        } else {
+            "other".to_string()
+        }
+    }
+
+    /// Bucket of `peer`, created with `limit` when the peer is new, marked as the most
+    /// recently used one
+    fn touch(&mut self, peer: IpAddr, limit: impl FnOnce() -> Limit, now: Instant) -> &mut Bucket {
+        self.uses += 1;
+        let used = self.uses;
+        if !self.by_peer.contains_key(&peer) && self.by_peer.len() >= MAX_TRACKED_PEERS {
+            if let Some((_, oldest)) = self.by_use.pop_first() {
+                self.by_peer.remove(&oldest);
+            }
+        }
+        self.by_use.insert(used, peer);
+        let bucket = self.by_peer.entry(peer).or_insert_with(|| {
+            let limit = limit();
+            Bucket {
+                limit,
+                tokens: limit.burst,
+                updated: now,
+                used,
+            }
+        });
+        if bucket.used != used {
+            self.by_use.remove(&bucket.used);
+            bucket.used = used;
+        }
+        bucket
+    }
+}
+
+impl RateLimiter {
+    pub fn new(listener: &str, config: &RateLimitConfig) -> Result<Self, RateLimitError> {
+        let overrides = config
+            .overrides
+            .iter()
+            .map(|o| {
+                let net = parse_net(&o.cidr)
+                    .ok_or_else(|| RateLimitError::InvalidCidr(o.cidr.clone()))?;
+                Ok((net, Limit::new(o.rate, o.burst)?))
+            })
+            .collect::<Result<_, RateLimitError>>()?;
+        Ok(RateLimiter {
+            listener: listener.into(),
+            action: config.action.clone(),
+            limit: Limit::new(config.rate, config.burst)?,
+            overrides,
+            buckets: Arc::new(Mutex::new(Buckets::default())),
+        })
+    }
+
+    /// Rate limiter of a listener, if it has a `rate_limit` section
+    pub fn for_server(server: &ServerConfig) -> Result<Option<Self>, io::Error> {
+        server
+            .rate_limit
+            .as_ref()
+            .map(|config| {
+                let listener = format!("{}:{}", server.host, server.port);
+                Self::new(&listener, config)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+            })
+            .transpose()
+    }
+
+    /// Take a token for a message from `peer`. Returns `false` when the message must be
+    /// dropped; with the Delay action, waits for a token instead and always returns `true`.
+    pub async fn admit(&self, peer: IpAddr) -> bool {
+        let (wait, peer_label) = {
+            let now = Instant::now();
+            let mut buckets = self.buckets.lock().unwrap();
+            let bucket = buckets.touch(peer, || self.limit_for(peer), now);
+            bucket.refill(now);
+            if bucket.tokens >= 1.0 {
+                bucket.tokens -= 1.0;
+                return true;
+            }
+            let wait = match self.action {
+                RateLimitAction::Drop => None,
+                RateLimitAction::Delay => {
+                    // Reserve the next token, later messages wait behind this one
+                    let wait = (1.0 - bucket.tokens) / bucket.limit.rate;
+                    bucket.tokens -= 1.0;
+                    Some(Duration::from_secs_f64(wait))
+                }
+            };
+            (wait, buckets.peer_label(peer))
+        };
+        metrics::rate_limit::limited(&self.listener, peer_label, &self.action);
+        match wait {
+            Some(wait) => {
+                tokio::time::sleep(wait).await;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn limit_for(&self, peer: IpAddr) -> Limit {
+        self.overrides
+            .iter()
+            .find(|(net, _)| net.contains(&peer))
+            .map_or(self.limit, |(_, limit)| *limit)
+    }
+}
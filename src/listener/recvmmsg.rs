@@ -0,0 +1,110 @@
+use std::io;
+use std::mem;
+use std::net::SocketAddr;
+use std::os::fd::AsRawFd;
+use std::ptr;
+
+use bytes::{Bytes, BytesMut};
+use socket2::SockAddr;
+
+/// Largest number of datagrams received with a single call
+pub const MAX_BATCH_SIZE: usize = 1024;
+
+/// Receives up to a batch of datagrams with a single recvmmsg(2) call. Each datagram is
+/// split off the receive buffer, which is only replaced once all its slots are used.
+pub struct RecvBatch {
+    // One slot of `slot_size` bytes per datagram, split off as they are taken
+    buf: BytesMut,
+    slot_size: usize,
+    addrs: Vec<libc::sockaddr_storage>,
+    iovecs: Vec<libc::iovec>,
+    headers: Vec<libc::mmsghdr>,
+    received: usize,
+}
+
+// The raw pointers only point into the heap buffers owned by the batch itself, which
+// are only written through `&mut self`
+unsafe impl Send for RecvBatch {}
+unsafe impl Sync for RecvBatch {}
+
+impl RecvBatch {
+    pub fn new(batch_size: usize, slot_size: usize) -> Self {
+        // SAFETY: all-zero is a valid sockaddr_storage, iovec and mmsghdr
+        let mut addrs = vec![unsafe { mem::zeroed::<libc::sockaddr_storage>() }; batch_size];
+        let mut iovecs = vec![unsafe { mem::zeroed::<libc::iovec>() }; batch_size];
+        let headers = iovecs
+            .iter_mut()
+            .zip(addrs.iter_mut())
+            .map(|(iovec, addr)| {
+                let mut header: libc::mmsghdr = unsafe { mem::zeroed() };
+                header.msg_hdr.msg_name = (addr as *mut libc::sockaddr_storage).cast();
+                header.msg_hdr.msg_iov = iovec;
+                header.msg_hdr.msg_iovlen = 1;
+                header
+            })
+            .collect();
+        RecvBatch {
+            buf: BytesMut::zeroed(batch_size * slot_size),
+            slot_size,
+            addrs,
+            iovecs,
+            headers,
+            received: 0,
+        }
+    }
+
+    /// Receive the datagrams waiting on the socket, up to a batch. Fails with
+    /// `WouldBlock` when there are none, as expected by `UdpSocket::async_io`.
+    pub fn recv(&mut self, socket: &impl AsRawFd) -> io::Result<usize> {
+        self.received = 0;
+        if self.buf.len() < self.slot_size {
+            // The previous buffer is still shared by the datagrams split off it
+            self.buf = BytesMut::zeroed(self.headers.len() * self.slot_size);
+        }
+        // Only as many datagrams as there are slots left in the buffer
+        let slots = self.headers.len().min(self.buf.len() / self.slot_size);
+        for (iovec, slot) in self
+            .iovecs
+            .iter_mut()
+            .zip(self.buf.chunks_exact_mut(self.slot_size))
+        {
+            iovec.iov_base = slot.as_mut_ptr().cast();
+            iovec.iov_len = slot.len();
+        }
+        for header in &mut self.headers[..slots] {
+            // Both are overwritten by the previous call
+            header.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as _;
+            header.msg_hdr.msg_flags = 0;
+            header.msg_len = 0;
+        }
+        // SAFETY: the first `slots` headers point to their own slot of `buf` and entry
+        // of `addrs`, neither of which moves or is resized before the call returns
+        let received = unsafe {
+            libc::recvmmsg(
+                socket.as_raw_fd(),
+                self.headers.as_mut_ptr(),
+                slots as _,
+                libc::MSG_DONTWAIT as _,
+                ptr::null_mut(),
+            )
+        };
+        if received < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        self.received = received as usize;
+        Ok(self.received)
+    }
+
+    /// Split the datagrams received by the last call off the buffer, with their sender
+    pub fn take(&mut self) -> impl Iterator<Item = (Bytes, Option<SocketAddr>)> + '_ {
+        let received = mem::take(&mut self.received);
+        (0..received).map(move |index| {
+            let header = &self.headers[index];
+            let mut payload = self.buf.split_to(self.slot_size);
+            payload.truncate(header.msg_len as usize);
+            // SAFETY: the kernel filled the address and set its length
+            let peer = unsafe { SockAddr::new(self.addrs[index], header.msg_hdr.msg_namelen) };
+            (payload.freeze(), peer.as_socket())
+        })
+    }
+}
@@ -0,0 +1,453 @@
+use bytes::{Buf, Bytes, BytesMut};
+use log::debug;
+use std::io;
+use tokio_util::codec::{Decoder, LinesCodecError};
+
+use crate::error::SyslogError;
+use crate::listener::message_size::{truncated, LineFramer, SizeLimit};
+use crate::listener::metrics;
+use crate::settings::OversizedPolicy;
+use crate::writer::file_writer::{FileWriterCommand, Message};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const UTF8_BOM: &str = "\u{feff}";
+// An octet count larger than 9 digits can't be a sane frame length
+const MAX_OCTET_COUNT_DIGITS: usize = 9;
+
+/// A syslog message parsed from RFC 3164 or RFC 5424 format
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyslogMessage {
+    pub facility: u8,
+    pub severity: u8,
+    /// Protocol version, `Some(1)` for RFC 5424 and `None` for RFC 3164
+    pub version: Option<u8>,
+    pub timestamp: Option<String>,
+    pub hostname: Option<String>,
+    pub app_name: Option<String>,
+    pub proc_id: Option<String>,
+    pub msg_id: Option<String>,
+    pub structured_data: Vec<StructuredDataElement>,
+    pub message: String,
+}
+
+/// An RFC 5424 SD-ELEMENT, e.g. `[exampleSDID@32473 iut="3" eventSource="App"]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructuredDataElement {
+    pub id: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl SyslogMessage {
+    /// Parse a single syslog message, detecting RFC 5424 by its `1` version field
+    /// and falling back to RFC 3164 otherwise.
+    pub fn parse(raw: &[u8]) -> Result<Self, SyslogError> {
+        let text = String::from_utf8_lossy(raw);
+        let text = text.trim_end_matches(['\r', '\n']);
+        let (pri, rest) = parse_pri(text)?;
+
+        let mut message = match rest.strip_prefix("1 ") {
+            Some(header) => parse_rfc5424(header)?,
+            None => parse_rfc3164(rest),
+        };
+        message.facility = (pri / 8) as u8;
+        message.severity = (pri % 8) as u8;
+        Ok(message)
+    }
+
+    fn empty() -> Self {
+        SyslogMessage {
+            facility: 0,
+            severity: 0,
+            version: None,
+            timestamp: None,
+            hostname: None,
+            app_name: None,
+            proc_id: None,
+            msg_id: None,
+            structured_data: vec![],
+            message: String::new(),
+        }
+    }
+}
+
+/// Build the FileWriter command for a received syslog message.
+/// Messages that can't be parsed are still written, as plain lines.
+pub fn into_command(message: Message) -> FileWriterCommand {
+    FileWriterCommand::Write(parsed(message))
+}
+
+/// Attach the parsed syslog fields to a received message, when it can be parsed
+pub fn parsed(mut message: Message) -> Message {
+    match SyslogMessage::parse(&message.payload) {
+        Ok(parsed) => {
+            metrics::syslog::parsed();
+            message.syslog = Some(parsed);
+        }
+        Err(e) => {
+            debug!("Failed to parse syslog message, writing it as raw: {}", e);
+            metrics::syslog::parse_error();
+        }
+    }
+    message
+}
+
+fn parse_pri(text: &str) -> Result<(u32, &str), SyslogError> {
+    let rest = text.strip_prefix('<').ok_or(SyslogError::InvalidPri)?;
+    let end = rest.find('>').ok_or(SyslogError::InvalidPri)?;
+    let digits = &rest[..end];
+    if digits.is_empty() || digits.len() > 3 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(SyslogError::InvalidPri);
+    }
+    let pri: u32 = digits.parse().map_err(|_| SyslogError::InvalidPri)?;
+    if pri > 191 {
+        return Err(SyslogError::PriOutOfRange(pri));
+    }
+    Ok((pri, &rest[end + 1..]))
+}
+
+fn nil_or(value: &str) -> Option<String> {
+    if value == "-" {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+fn parse_rfc5424(header: &str) -> Result<SyslogMessage, SyslogError> {
+    let mut fields = header.splitn(6, ' ');
+    let mut next_field = |name: &str| {
+        fields
+            .next()
+            .filter(|f| !f.is_empty())
+            .ok_or_else(|| SyslogError::InvalidHeader(format!("missing {}", name)))
+    };
+    let timestamp = next_field("TIMESTAMP")?;
+    let hostname = next_field("HOSTNAME")?;
+    let app_name = next_field("APP-NAME")?;
+    let proc_id = next_field("PROCID")?;
+    let msg_id = next_field("MSGID")?;
+    let rest = next_field("STRUCTURED-DATA")?;
+
+    let (structured_data, msg) = parse_structured_data(rest)?;
+    let msg = msg.strip_prefix(UTF8_BOM).unwrap_or(msg);
+
+    let mut message = SyslogMessage::empty();
+    message.version = Some(1);
+    message.timestamp = nil_or(timestamp);
+    message.hostname = nil_or(hostname);
+    message.app_name = nil_or(app_name);
+    message.proc_id = nil_or(proc_id);
+    message.msg_id = nil_or(msg_id);
+    message.structured_data = structured_data;
+    message.message = msg.to_string();
+    Ok(message)
+}
+
+/// Parse the STRUCTURED-DATA part, returning the elements and the remaining MSG
+fn parse_structured_data(input: &str) -> Result<(Vec<StructuredDataElement>, &str), SyslogError> {
+    if let Some(msg) = input.strip_prefix('-') {
+        return Ok((vec![], msg.strip_prefix(' ').unwrap_or(msg)));
+    }
+
+    let mut elements = vec![];
+    let mut rest = input;
+    while let Some(element) = rest.strip_prefix('[') {
+        let (parsed, remaining) = parse_sd_element(element)?;
+        elements.push(parsed);
+        rest = remaining;
+    }
+    if elements.is_empty() {
+        return Err(SyslogError::InvalidStructuredData(
+            "expected '-' or '['".to_string(),
+        ));
+    }
+    Ok((elements, rest.strip_prefix(' ').unwrap_or(rest)))
+}
+
+/// Parse one SD-ELEMENT after its opening '[', returning it and the input after ']'
+fn parse_sd_element(input: &str) -> Result<(StructuredDataElement, &str), SyslogError> {
+    let id_end = input
+        .find([' ', ']'])
+        .ok_or_else(|| SyslogError::InvalidStructuredData("unterminated element".to_string()))?;
+    let id = input[..id_end].to_string();
+    if id.is_empty() {
+        return Err(SyslogError::InvalidStructuredData(
+            "empty SD-ID".to_string(),
+        ));
+    }
+
+    let mut params = vec![];
+    let mut rest = &input[id_end..];
+    loop {
+        if let Some(after) = rest.strip_prefix(']') {
+            return Ok((StructuredDataElement { id, params }, after));
+        }
+        rest = rest.strip_prefix(' ').ok_or_else(|| {
+            SyslogError::InvalidStructuredData(format!("unexpected input in element {}", id))
+        })?;
+
+        let name_end = rest.find("=\"").ok_or_else(|| {
+            SyslogError::InvalidStructuredData(format!("malformed param in element {}", id))
+        })?;
+        let name = rest[..name_end].to_string();
+        rest = &rest[name_end + 2..];
+
+        // PARAM-VALUE escapes '"', '\' and ']' with a backslash
+        let mut value = String::new();
+        let mut chars = rest.char_indices();
+        let end = loop {
+            match chars.next() {
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, c @ ('"' | '\\' | ']'))) => value.push(c),
+                    Some((_, c)) => {
+                        value.push('\\');
+                        value.push(c);
+                    }
+                    None => break None,
+                },
+                Some((i, '"')) => break Some(i),
+                Some((_, c)) => value.push(c),
+                None => break None,
+            }
+        };
+        let end = end.ok_or_else(|| {
+            SyslogError::InvalidStructuredData(format!("unterminated value for {}", name))
+        })?;
+        params.push((name, value));
+        rest = &rest[end + 1..];
+    }
+}
+
+/// RFC 3164 is loosely specified, so anything after the PRI that doesn't look
+/// like `TIMESTAMP HOSTNAME TAG: MSG` is kept as the message content.
+fn parse_rfc3164(rest: &str) -> SyslogMessage {
+    let mut message = SyslogMessage::empty();
+
+    let content = match rfc3164_timestamp(rest) {
+        Some(timestamp) => {
+            message.timestamp = Some(timestamp.to_string());
+            let after = &rest[timestamp.len() + 1..];
+            match after.split_once(' ') {
+                Some((hostname, content)) if !hostname.is_empty() => {
+                    message.hostname = Some(hostname.to_string());
+                    content
+                }
+                _ => after,
+            }
+        }
+        None => rest,
+    };
+
+    // TAG is alphanumeric (plus a few common symbols) and ends at '[', ':' or ' '
+    let tag_end = content
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "-_./".contains(c)))
+        .unwrap_or(content.len());
+    let (tag, after_tag) = content.split_at(tag_end);
+    let (proc_id, after_tag) = match after_tag.strip_prefix('[').and_then(|s| s.split_once(']')) {
+        Some((pid, after)) => (Some(pid.to_string()), after),
+        None => (None, after_tag),
+    };
+    match after_tag.strip_prefix(':') {
+        Some(msg) if !tag.is_empty() => {
+            message.app_name = Some(tag.to_string());
+            message.proc_id = proc_id;
+            message.message = msg.strip_prefix(' ').unwrap_or(msg).to_string();
+        }
+        _ => message.message = content.to_string(),
+    }
+    message
+}
+
+/// Return the `Mmm dd hh:mm:ss` timestamp at the start of `input`, if present
+fn rfc3164_timestamp(input: &str) -> Option<&str> {
+    let candidate = input.get(..15)?;
+    let bytes = candidate.as_bytes();
+    let valid = candidate
+        .get(..3)
+        .is_some_and(|month| MONTHS.contains(&month))
+        && bytes[3] == b' '
+        && (bytes[4] == b' ' || bytes[4].is_ascii_digit())
+        && bytes[5].is_ascii_digit()
+        && bytes[6] == b' '
+        && bytes[9] == b':'
+        && bytes[12] == b':'
+        && [7, 8, 10, 11, 13, 14]
+            .iter()
+            .all(|&i| bytes[i].is_ascii_digit())
+        && input.as_bytes().get(15) == Some(&b' ');
+    if valid {
+        Some(candidate)
+    } else {
+        None
+    }
+}
+
+/// Framing for syslog over TCP (RFC 6587).
+///
+/// Each frame is detected independently: frames starting with a digit use
+/// octet counting (`MSG-LEN SP SYSLOG-MSG`), any other frame is treated as
+/// non-transparent framing terminated by LF. Frames over the size limit are
+/// handled by its policy with either framing.
+#[derive(Debug)]
+pub struct SyslogCodec {
+    limit: SizeLimit,
+    lines: LineFramer,
+    // Rest of an oversized octet-counted frame
+    oversized: Option<OversizedFrame>,
+}
+
+/// Octets left of an oversized octet-counted frame, and what to do with them
+#[derive(Debug)]
+enum OversizedFrame {
+    Split(usize),
+    Truncate(usize),
+    Skip(usize),
+}
+
+impl Default for SyslogCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyslogCodec {
+    pub fn new() -> Self {
+        Self::with_limit(SizeLimit::default())
+    }
+
+    pub fn with_limit(limit: SizeLimit) -> Self {
+        SyslogCodec {
+            lines: LineFramer::new(limit.clone()),
+            limit,
+            oversized: None,
+        }
+    }
+
+    fn invalid_frame(reason: &str) -> LinesCodecError {
+        LinesCodecError::Io(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid syslog frame: {}", reason),
+        ))
+    }
+
+    /// Next part of an oversized octet-counted frame, once its bytes arrived
+    fn decode_oversized(&mut self, frame: OversizedFrame, buf: &mut BytesMut) -> Option<Bytes> {
+        let max = self.limit.max;
+        match frame {
+            OversizedFrame::Split(remaining) => {
+                let len = remaining.min(max);
+                if buf.len() < len {
+                    buf.reserve(len - buf.len());
+                    self.oversized = Some(OversizedFrame::Split(remaining));
+                    return None;
+                }
+                if remaining > len {
+                    self.oversized = Some(OversizedFrame::Split(remaining - len));
+                }
+                Some(buf.split_to(len).freeze())
+            }
+            OversizedFrame::Truncate(remaining) => {
+                if buf.len() < max {
+                    buf.reserve(max - buf.len());
+                    self.oversized = Some(OversizedFrame::Truncate(remaining));
+                    return None;
+                }
+                self.oversized = Some(OversizedFrame::Skip(remaining - max));
+                Some(truncated(&buf.split_to(max)))
+            }
+            OversizedFrame::Skip(remaining) => {
+                let len = remaining.min(buf.len());
+                buf.advance(len);
+                if remaining > len {
+                    self.oversized = Some(OversizedFrame::Skip(remaining - len));
+                }
+                None
+            }
+        }
+    }
+}
+
+impl Decoder for SyslogCodec {
+    type Item = Bytes;
+    type Error = LinesCodecError;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, LinesCodecError> {
+        loop {
+            if let Some(frame) = self.oversized.take() {
+                if let Some(part) = self.decode_oversized(frame, buf) {
+                    return Ok(Some(part));
+                }
+                if self.oversized.is_some() {
+                    return Ok(None);
+                }
+                continue;
+            }
+            if self.lines.in_progress() {
+                return Ok(self.lines.decode(buf));
+            }
+
+            // Skip trailers left between frames by senders mixing both framings
+            while matches!(buf.first(), Some(b'\n') | Some(b'\r')) {
+                buf.advance(1);
+            }
+            let first = match buf.first() {
+                Some(b) => *b,
+                None => return Ok(None),
+            };
+            if !first.is_ascii_digit() {
+                return Ok(self.lines.decode(buf));
+            }
+
+            let digits = buf
+                .iter()
+                .take(MAX_OCTET_COUNT_DIGITS + 1)
+                .position(|b| !b.is_ascii_digit());
+            let digits = match digits {
+                Some(n) => n,
+                None if buf.len() > MAX_OCTET_COUNT_DIGITS => {
+                    return Err(Self::invalid_frame("octet count too long"))
+                }
+                None => return Ok(None),
+            };
+            if buf[digits] != b' ' {
+                return Err(Self::invalid_frame("octet count not followed by a space"));
+            }
+            let len: usize = std::str::from_utf8(&buf[..digits])
+                .ok()
+                .and_then(|d| d.parse().ok())
+                .ok_or_else(|| Self::invalid_frame("bad octet count"))?;
+            if len > self.limit.max {
+                buf.advance(digits + 1);
+                self.limit.oversized();
+                self.oversized = Some(match self.limit.policy {
+                    OversizedPolicy::Split => OversizedFrame::Split(len),
+                    OversizedPolicy::Truncate => OversizedFrame::Truncate(len),
+                    OversizedPolicy::Drop => OversizedFrame::Skip(len),
+                });
+                continue;
+            }
+            if buf.len() < digits + 1 + len {
+                buf.reserve(digits + 1 + len - buf.len());
+                return Ok(None);
+            }
+            buf.advance(digits + 1);
+            return Ok(Some(buf.split_to(len).freeze()));
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, LinesCodecError> {
+        if let Some(frame) = self.decode(buf)? {
+            return Ok(Some(frame));
+        }
+        let truncated_frame = self.oversized.take().is_some()
+            || (!self.lines.in_progress() && buf.first().is_some_and(u8::is_ascii_digit));
+        if truncated_frame {
+            // An octet-counted frame cut short can't be recovered
+            buf.clear();
+            return Ok(None);
+        }
+        Ok(self.lines.decode_eof(buf))
+    }
+}
@@ -1,49 +1,108 @@
 use std::net::SocketAddr;
+use std::pin::Pin;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
-use crate::settings::Settings;
+use crate::settings::{MessageFormat, ProtocolType, ServerConfig, Settings};
+use crate::writer::file_writer::Message;
+use bytes::Bytes;
 use futures::StreamExt;
-use tokio::net::{TcpListener, TcpStream};
-use tokio_util::codec::{FramedRead, LinesCodec};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::codec::{Decoder, FramedRead, LinesCodecError};
 
-use crate::listener::metrics;
-use crate::writer::backpressure::BackpressureAwareSender;
-use crate::writer::file_writer::FileWriterCommand;
+use crate::listener::message_size::{RawCodec, SizeLimit};
+use crate::listener::rate_limit::RateLimiter;
+use crate::listener::syslog::SyslogCodec;
+use crate::listener::{canonical_peer, command_for, metrics, tls, ListenerControls};
+use crate::router::Router;
+use crate::time;
 use tokio::sync::broadcast;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
 
 use log::{debug, info, warn};
 use std::io;
 
+// Keeps a client that never completes the handshake from holding a connection slot
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Maximum number of concurrent connections of a TCP listener, adjustable while it runs
+#[derive(Debug, Clone)]
+pub struct ConnectionLimit(Arc<AtomicUsize>);
+
+impl ConnectionLimit {
+    pub fn new(max_connections: usize) -> Self {
+        ConnectionLimit(Arc::new(AtomicUsize::new(max_connections)))
+    }
+
+    pub fn get(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, max_connections: usize) {
+        self.0.store(max_connections, Ordering::Relaxed);
+    }
+}
+
 pub struct TcpServer;
 
 impl TcpServer {
     pub async fn start(
         settings: Arc<Settings>,
-        sender: BackpressureAwareSender,
-        shutdown_rx: broadcast::Receiver<()>,
+        server: ServerConfig,
+        router: Router,
+        controls: ListenerControls,
     ) -> Result<(), io::Error> {
-        let addr = format!("{}:{}", settings.server.host, settings.server.port)
+        let ListenerControls {
+            connection_limit,
+            access_list,
+            rate_limiter,
+            mut shutdown_rx,
+        } = controls;
+        let addr = format!("{}:{}", server.host, server.port)
             .parse::<SocketAddr>()
             .unwrap();
 
-        info!("Listening at {} via TCP...", addr);
-        info!("Maximum connections: {}", settings.server.max_connections);
+        SizeLimit::for_server(&server)?;
+        let tls_acceptor: Option<TlsAcceptor> = match &server.tls {
+            Some(tls_config) => Some(tls::build_acceptor(tls_config)?),
+            None => None,
+        };
+
+        info!(
+            "Listening at {} via TCP{}...",
+            addr,
+            if tls_acceptor.is_some() { " (TLS)" } else { "" }
+        );
+        info!("Maximum connections: {}", connection_limit.get());
 
         let listener = TcpListener::bind(addr).await?;
-        let mut shutdown_rx = shutdown_rx;
+        let local_addr = listener.local_addr()?;
         let connection_count = Arc::new(AtomicUsize::new(0));
+        let mut clients = JoinSet::new();
 
         loop {
             tokio::select! {
                 res = listener.accept() => {
                     match res {
                         Ok((stream, peer)) => {
+                            let peer = canonical_peer(peer);
+                            if !access_list.permits(peer.ip()) {
+                                debug!("Rejecting connection from {}, not permitted by the allow/deny lists", peer);
+                                metrics::tcp::connection_denied();
+                                drop(stream);
+                                continue;
+                            }
                             let current_connections = connection_count.load(Ordering::Relaxed);
-                            if current_connections >= settings.server.max_connections {
+                            let max_connections = connection_limit.get();
+                            if current_connections >= max_connections {
                                 warn!(
                                     "Max connections ({}) reached, rejecting connection from {}",
-                                    settings.server.max_connections,
+                                    max_connections,
                                     peer
                                 );
                                 metrics::tcp::connection_rejected();
@@ -58,13 +117,43 @@ impl TcpServer {
                             metrics::tcp::connection_active(connection_count.load(Ordering::Relaxed));
 
                             // Clone here - they're cheap (Arc and Sender are just pointers)
-                            let svc = TcpListenerService::new(0, sender.clone(), settings.clone());
+                            let svc = TcpListenerService::new(
+                                0,
+                                router.clone(),
+                                peer,
+                                local_addr,
+                                settings.clone(),
+                                server.clone(),
+                                rate_limiter.clone(),
+                            );
                             // Create a shutdown receiver for this client
                             let client_shutdown = shutdown_rx.resubscribe();
                             let connection_count_clone = connection_count.clone();
+                            let tls_acceptor = tls_acceptor.clone();
 
-                            tokio::spawn(async move {
-                                handle_client(stream, svc, client_shutdown).await;
+                            clients.spawn(async move {
+                                match tls_acceptor {
+                                    Some(acceptor) => {
+                                        let handshake = tokio::time::timeout(
+                                            TLS_HANDSHAKE_TIMEOUT,
+                                            acceptor.accept(stream),
+                                        );
+                                        match handshake.await {
+                                            Ok(Ok(tls_stream)) => {
+                                                serve_client(tls_stream, svc, client_shutdown).await
+                                            }
+                                            Ok(Err(e)) => {
+                                                warn!("TLS handshake with {} failed: {}", peer, e);
+                                                metrics::tls::handshake_failed();
+                                            }
+                                            Err(_) => {
+                                                warn!("TLS handshake with {} timed out", peer);
+                                                metrics::tls::handshake_failed();
+                                            }
+                                        }
+                                    }
+                                    None => serve_client(stream, svc, client_shutdown).await,
+                                }
                                 // Decrement connection count when client disconnects
                                 let new_count = connection_count_clone.fetch_sub(1, Ordering::Relaxed) - 1;
                                 metrics::tcp::connection_active(new_count);
@@ -75,6 +164,8 @@ impl TcpServer {
                         }
                     }
                 }
+                // Reap the tasks of closed connections
+                Some(_) = clients.join_next(), if !clients.is_empty() => {}
                 _ = shutdown_rx.recv() => {
                     info!("TCP server received shutdown signal");
                     break;
@@ -82,22 +173,142 @@ impl TcpServer {
             }
         }
 
+        // Clients stop on the signal as well, wait until they sent what they read so
+        // that the writers only drain once nothing more is coming
+        let drain_timeout = Duration::from_millis(settings.drain_timeout_ms);
+        let joined = tokio::time::timeout(drain_timeout, async {
+            while clients.join_next().await.is_some() {}
+        })
+        .await;
+        if joined.is_err() {
+            warn!(
+                "{} TCP connection(s) still open after {:?}, closing them",
+                clients.len(),
+                drain_timeout
+            );
+            clients.shutdown().await;
+        }
         info!("TCP server shutting down gracefully");
         Ok(())
     }
 }
 
-async fn handle_client(
-    stream: TcpStream,
+/// Read frames from a plaintext or TLS stream using the codec for the listener format
+async fn serve_client<S>(
+    stream: S,
+    service: TcpListenerService,
+    shutdown_rx: broadcast::Receiver<()>,
+) where
+    S: AsyncRead + Unpin,
+{
+    let size_limit = service.size_limit.clone();
+    match service.server.format {
+        MessageFormat::Raw => {
+            handle_client(stream, RawCodec::new(size_limit), service, shutdown_rx).await
+        }
+        MessageFormat::Syslog => {
+            let codec = SyslogCodec::with_limit(size_limit);
+            handle_client(stream, codec, service, shutdown_rx).await
+        }
+    }
+}
+
+/// Idle, per-line read and lifetime limits of a connection
+#[derive(Debug, Clone, Copy)]
+struct ConnectionTimeouts {
+    idle: Option<Duration>,
+    read: Option<Duration>,
+    lifetime: Option<Duration>,
+}
+
+impl ConnectionTimeouts {
+    fn for_server(server: &ServerConfig) -> Self {
+        ConnectionTimeouts {
+            idle: server.idle_timeout_ms.map(Duration::from_millis),
+            read: server.read_timeout_ms.map(Duration::from_millis),
+            lifetime: server.max_connection_lifetime_ms.map(Duration::from_millis),
+        }
+    }
+
+    /// Earliest time the connection must be closed at, with the timeout that closes it
+    fn deadline<S>(
+        &self,
+        connected_at: Instant,
+        stream: &TrackedStream<S>,
+    ) -> Option<(Instant, &'static str)> {
+        [
+            self.idle.map(|idle| (stream.last_read + idle, "idle")),
+            self.read
+                .zip(stream.line_started)
+                .map(|(read, started)| (started + read, "read")),
+            self.lifetime
+                .map(|lifetime| (connected_at + lifetime, "lifetime")),
+        ]
+        .into_iter()
+        .flatten()
+        .min_by_key(|(at, _)| *at)
+    }
+}
+
+/// Stream that remembers when data was last read and when the line being read started
+struct TrackedStream<S> {
+    inner: S,
+    last_read: Instant,
+    line_started: Option<Instant>,
+}
+
+impl<S> TrackedStream<S> {
+    fn new(inner: S) -> Self {
+        TrackedStream {
+            inner,
+            last_read: Instant::now(),
+            line_started: None,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for TrackedStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if matches!(result, Poll::Ready(Ok(()))) && buf.filled().len() > filled {
+            let now = Instant::now();
+            this.last_read = now;
+            this.line_started.get_or_insert(now);
+        }
+        result
+    }
+}
+
+async fn handle_client<S, D>(
+    stream: S,
+    decoder: D,
     service: TcpListenerService,
     mut shutdown_rx: broadcast::Receiver<()>,
-) {
-    let mut reader = FramedRead::new(stream, LinesCodec::new());
+) where
+    S: AsyncRead + Unpin,
+    D: Decoder<Item = Bytes, Error = LinesCodecError>,
+{
+    let timeouts = ConnectionTimeouts::for_server(&service.server);
+    let connected_at = Instant::now();
+    let mut reader = FramedRead::new(TrackedStream::new(stream), decoder);
     loop {
+        let deadline = timeouts.deadline(connected_at, reader.get_ref());
         tokio::select! {
+            // Lines already received are handled before any timeout closes the connection
+            biased;
             line = reader.next() => {
                 match line {
                     Some(Ok(l)) => {
+                        // Whatever is left in the read buffer starts the next line
+                        let last_read = reader.get_ref().last_read;
+                        reader.get_mut().line_started =
+                            (!reader.read_buffer().is_empty()).then_some(last_read);
                         let _ = service.handle(l).await;
                     }
                     Some(Err(e)) => {
@@ -110,6 +321,16 @@ async fn handle_client(
                     }
                 }
             }
+            _ = time::sleep_until(deadline.map(|(at, _)| at)), if deadline.is_some() => {
+                // Data may have arrived since the deadline was computed
+                if let Some((at, reason)) = timeouts.deadline(connected_at, reader.get_ref()) {
+                    if at <= Instant::now() {
+                        info!("Closing connection from {}: {} timeout", service.peer, reason);
+                        metrics::tcp::connection_timed_out(reason);
+                        break;
+                    }
+                }
+            }
             _ = shutdown_rx.recv() => {
                 info!("Client handler received shutdown signal, closing connection");
                 break;
@@ -122,25 +343,52 @@ async fn handle_client(
 struct TcpListenerService {
     pub id: i32,
     pub name: String,
-    pub tx_file_writer: BackpressureAwareSender,
+    pub router: Router,
+    peer: SocketAddr,
+    local_addr: SocketAddr,
     settings: Arc<Settings>,
+    server: ServerConfig,
+    rate_limiter: Option<RateLimiter>,
+    size_limit: SizeLimit,
 }
 
 impl TcpListenerService {
-    pub fn new(id: i32, tx_file_writer: BackpressureAwareSender, settings: Arc<Settings>) -> Self {
+    pub fn new(
+        id: i32,
+        router: Router,
+        peer: SocketAddr,
+        local_addr: SocketAddr,
+        settings: Arc<Settings>,
+        server: ServerConfig,
+        rate_limiter: Option<RateLimiter>,
+    ) -> Self {
+        // Validated by TcpServer::start
+        let size_limit = SizeLimit::for_server(&server).unwrap_or_default();
         TcpListenerService {
             id,
             name: format!("server-tcp-{}", id),
-            tx_file_writer,
+            router,
+            peer,
+            local_addr,
             settings,
+            server,
+            rate_limiter,
+            size_limit,
         }
     }
 
-    pub async fn handle(&self, req: String) -> Result<(), io::Error> {
+    pub async fn handle(&self, req: Bytes) -> Result<(), io::Error> {
         debug!("Received a log line in {}", self.name);
         crate::metrics::messages::received();
-        self.tx_file_writer
-            .send(FileWriterCommand::Write(req.into_bytes()))
+        // Delaying stops reading the connection, which slows the peer down
+        if let Some(rate_limiter) = &self.rate_limiter {
+            if !rate_limiter.admit(self.peer.ip()).await {
+                return Ok(());
+            }
+        }
+        let message = Message::new(req, self.peer, self.local_addr, ProtocolType::TCP);
+        self.router
+            .send(command_for(&self.server.format, message))
             .await
             .map_err(|e| io::Error::other(format!("send error: {}", e)))
     }
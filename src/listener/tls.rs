@@ -0,0 +1,73 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use log::info;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::settings::TlsConfig;
+
+/// Build a TLS acceptor from the configured certificate chain and private key.
+/// When `client_ca_path` is set, clients must present a certificate signed by that CA.
+pub fn build_acceptor(config: &TlsConfig) -> Result<TlsAcceptor, io::Error> {
+    let certs = load_certs(&config.cert_path)?;
+    let key = load_private_key(&config.key_path)?;
+    let provider = Arc::new(ring::default_provider());
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| io::Error::other(format!("invalid TLS protocol versions: {}", e)))?;
+
+    let builder = match &config.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots.add(cert).map_err(|e| {
+                    io::Error::other(format!("invalid client CA in {:?}: {}", ca_path, e))
+                })?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|e| io::Error::other(format!("invalid client verifier: {}", e)))?;
+            info!("TLS client certificate verification enabled");
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let server_config = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| io::Error::other(format!("invalid TLS certificate or key: {}", e)))?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
+
+fn open(path: &Path) -> Result<BufReader<File>, io::Error> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to open {:?}: {}", path, e)))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, io::Error> {
+    let certs = rustls_pemfile::certs(&mut open(path)?).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no certificates found in {:?}", path),
+        ));
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &Path) -> Result<PrivateKeyDer<'static>, io::Error> {
+    rustls_pemfile::private_key(&mut open(path)?)?.ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("no private key found in {:?}", path),
+        )
+    })
+}
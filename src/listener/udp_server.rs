@@ -1,15 +1,26 @@
+use bytes::BytesMut;
 use tokio::net::UdpSocket;
 
 use std::io;
 use std::net::SocketAddr;
 
-use crate::listener::metrics;
-use crate::settings::Settings;
-use crate::writer::backpressure::BackpressureAwareSender;
-use crate::writer::file_writer::FileWriterCommand;
+use crate::listener::access_list::AccessList;
+use crate::listener::message_size::SizeLimit;
+use crate::listener::rate_limit::RateLimiter;
+#[cfg(target_os = "linux")]
+use crate::listener::recvmmsg::{RecvBatch, MAX_BATCH_SIZE};
+#[cfg(target_os = "linux")]
+use crate::listener::udp_stats::{KernelStats, STATS_INTERVAL};
+use crate::listener::{canonical_peer, command_for, metrics, parsed, ListenerControls};
+use crate::router::Router;
+use crate::settings::{ProtocolType, ServerConfig, Settings};
+use crate::writer::file_writer::{FileWriterCommand, Message};
 
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
+use socket2::{Domain, Protocol, Socket, Type};
 use std::sync::Arc;
+#[cfg(target_os = "linux")]
+use tokio::io::Interest;
 use tokio::sync::broadcast;
 
 pub struct UdpServer;
@@ -17,87 +28,291 @@ pub struct UdpServer;
 impl UdpServer {
     pub async fn start(
         settings: Arc<Settings>,
-        sender: BackpressureAwareSender,
-        shutdown_rx: broadcast::Receiver<()>,
+        server: ServerConfig,
+        router: Router,
+        controls: ListenerControls,
     ) -> Result<(), io::Error> {
-        let addr = format!("{}:{}", settings.server.host, settings.server.port)
+        let ListenerControls {
+            access_list,
+            rate_limiter,
+            shutdown_rx,
+            ..
+        } = controls;
+        let addr = format!("{}:{}", server.host, server.port)
             .parse::<SocketAddr>()
             .unwrap();
 
-        info!("Listening at {} via UDP...", addr);
+        SizeLimit::for_server(&server)?;
+        batch_size(&server)?;
+        let sockets = bind_sockets(addr, socket_count(&settings, &server)?, &server)?;
+        info!(
+            "Listening at {} via UDP with {} socket(s)...",
+            sockets[0].local_addr()?,
+            sockets.len()
+        );
 
-        let socket = UdpSocket::bind(addr).await?;
-        let mut service = UdpService::new(socket, sender, 0, settings);
+        #[cfg(target_os = "linux")]
+        {
+            // Labelled by address and socket, so that every listener has its own series
+            let local_addr = sockets[0].local_addr()?;
+            let labels = (0..sockets.len()).map(|id| format!("{}#{}", local_addr, id));
+            match KernelStats::new(labels.zip(&sockets)) {
+                Ok(stats) => {
+                    tokio::spawn(stats.run(STATS_INTERVAL, shutdown_rx.resubscribe()));
+                }
+                Err(e) => warn!("UDP kernel statistics are not available: {}", e),
+            }
+        }
 
-        // Pass shutdown receiver to run() so it can check for shutdown signals
-        match service.run(shutdown_rx).await {
-            Ok(()) => {
-                info!("UDP server shutting down gracefully");
+        let mut services = Vec::with_capacity(sockets.len());
+        for (id, socket) in sockets.into_iter().enumerate() {
+            let mut service = UdpService::new(
+                socket,
+                router.clone(),
+                id as i32,
+                settings.clone(),
+                server.clone(),
+            )
+            .with_access_list(access_list.clone());
+            if let Some(rate_limiter) = &rate_limiter {
+                service = service.with_rate_limiter(rate_limiter.clone());
             }
-            Err(e) => {
-                error!("UDP service error: {}", e);
+            let shutdown_rx = shutdown_rx.resubscribe();
+            // Pass shutdown receiver to run() so it can check for shutdown signals
+            services.push(tokio::spawn(async move {
+                if let Err(e) = service.run(shutdown_rx).await {
+                    error!("UDP service {} error: {}", service.name, e);
+                }
+            }));
+        }
+        for service in services {
+            if let Err(e) = service.await {
+                error!("UDP service task failed: {}", e);
             }
         }
+        info!("UDP server shutting down gracefully");
 
         Ok(())
     }
 }
 
+/// Datagrams to receive per call with recvmmsg, `None` to receive them one by one
+fn batch_size(server: &ServerConfig) -> Result<Option<usize>, io::Error> {
+    #[cfg(target_os = "linux")]
+    match server.udp_batch_size {
+        Some(size) if size == 0 || size > MAX_BATCH_SIZE => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("udp_batch_size must be between 1 and {}", MAX_BATCH_SIZE),
+        )),
+        size => Ok(size),
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        if server.udp_batch_size.is_some() {
+            warn!("recvmmsg is only available on Linux, receiving datagrams one by one");
+        }
+        Ok(None)
+    }
+}
+
+/// Sockets to bind: `udp_sockets`, or one per worker thread
+fn socket_count(settings: &Settings, server: &ServerConfig) -> Result<usize, io::Error> {
+    let count = server
+        .udp_sockets
+        .unwrap_or_else(|| settings.threads.max(1) as usize);
+    if count == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "udp_sockets must be greater than 0",
+        ));
+    }
+    if count > 1 && !cfg!(unix) {
+        warn!("SO_REUSEPORT is not available, binding a single UDP socket");
+        return Ok(1);
+    }
+    Ok(count)
+}
+
+/// Bind `count` sockets to `addr` with SO_REUSEPORT, so that the kernel spreads the
+/// datagrams among them. Port 0 is resolved by the first one and shared by the rest.
+fn bind_sockets(
+    addr: SocketAddr,
+    count: usize,
+    server: &ServerConfig,
+) -> Result<Vec<UdpSocket>, io::Error> {
+    let first = bind_socket(addr, count > 1, server.udp_recv_buffer_size)?;
+    let addr = first.local_addr()?;
+    let mut sockets = vec![first];
+    for _ in 1..count {
+        sockets.push(bind_socket(addr, true, server.udp_recv_buffer_size)?);
+    }
+    Ok(sockets)
+}
+
+fn bind_socket(
+    addr: SocketAddr,
+    reuse_port: bool,
+    recv_buffer_size: Option<usize>,
+) -> Result<UdpSocket, io::Error> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    #[cfg(unix)]
+    socket.set_reuse_port(reuse_port)?;
+    #[cfg(not(unix))]
+    let _ = reuse_port;
+    if let Some(size) = recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+        // The kernel may cap it, e.g. at net.core.rmem_max on Linux
+        let actual = socket.recv_buffer_size()?;
+        if actual < size {
+            warn!(
+                "UDP receive buffer of {} is {} bytes, below the {} configured",
+                addr, actual, size
+            );
+        }
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Largest UDP payload, so that datagrams are always read whole
+const MAX_DATAGRAM_SIZE: usize = 65536;
+
+/// Size of the buffers datagrams are received into and then split off, so that many
+/// datagrams share an allocation. A buffer is freed once all its messages are written.
+const RECV_BUFFER_SIZE: usize = 16 * MAX_DATAGRAM_SIZE;
+
 pub struct UdpService {
     pub id: i32,
     pub name: String,
     pub socket: UdpSocket,
-    pub buf: Vec<u8>,
-    pub writer_sender: BackpressureAwareSender,
+    pub buf: BytesMut,
+    pub router: Router,
     settings: Arc<Settings>,
+    server: ServerConfig,
+    rate_limiter: Option<RateLimiter>,
+    access_list: Option<AccessList>,
+    size_limit: SizeLimit,
+    batch_size: Option<usize>,
     count: i32,
 }
 
 impl UdpService {
     pub fn new(
         s: UdpSocket,
-        writer_sender: BackpressureAwareSender,
+        router: Router,
         id: i32,
         settings: Arc<Settings>,
+        server: ServerConfig,
     ) -> Self {
+        // Validated by UdpServer::start
+        let size_limit = SizeLimit::for_server(&server).unwrap_or_default();
+        let batch_size = batch_size(&server).unwrap_or_default();
         UdpService {
             id,
             name: format!("server-udp-{}", id),
             socket: s,
-            buf: vec![0u8; 15000],
-            writer_sender,
+            buf: BytesMut::with_capacity(RECV_BUFFER_SIZE),
+            router,
             settings,
+            server,
+            rate_limiter: None,
+            access_list: None,
+            size_limit,
+            batch_size,
             count: 0, // For debug only
         }
     }
 
-    pub async fn run(&mut self, mut shutdown_rx: broadcast::Receiver<()>) -> Result<(), io::Error> {
+    /// Only accept datagrams from the peers the lists permit
+    pub fn with_access_list(mut self, access_list: AccessList) -> Self {
+        self.access_list = Some(access_list);
+        self
+    }
+
+    /// Limit the rate of datagrams accepted from each peer
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    pub async fn run(&mut self, shutdown_rx: broadcast::Receiver<()>) -> Result<(), io::Error> {
+        #[cfg(target_os = "linux")]
+        if let Some(batch_size) = self.batch_size {
+            return self.run_batched(batch_size, shutdown_rx).await;
+        }
+        self.run_single(shutdown_rx).await
+    }
+
+    /// Receive the datagrams one by one, forwarding each as soon as it arrives
+    async fn run_single(
+        &mut self,
+        mut shutdown_rx: broadcast::Receiver<()>,
+    ) -> Result<(), io::Error> {
+        let local_addr = self.socket.local_addr()?;
+        loop {
+            if self.buf.capacity() < MAX_DATAGRAM_SIZE {
+                // The previous buffer is still shared by the messages split off it
+                self.buf = BytesMut::with_capacity(RECV_BUFFER_SIZE);
+            }
+            tokio::select! {
+                res = self.socket.recv_buf_from(&mut self.buf) => {
+                    let (_, peer) = res?;
+                    let peer = canonical_peer(peer);
+                    let datagram = self.buf.split().freeze();
+                    if !self.admit(peer).await {
+                        continue;
+                    }
+                    // Oversized datagrams are truncated, split or dropped by the size limit
+                    for payload in self.size_limit.apply(datagram) {
+                        let message = Message::new(payload, peer, local_addr, ProtocolType::UDP);
+                        self.forward(message).await;
+                    }
+                }
+                _ = shutdown_rx.recv() => {
+                    info!("UdpService received shutdown signal");
+                    break;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Receive up to `batch_size` datagrams per recvmmsg call and forward the messages
+    /// of each call as a single batch
+    #[cfg(target_os = "linux")]
+    async fn run_batched(
+        &mut self,
+        batch_size: usize,
+        mut shutdown_rx: broadcast::Receiver<()>,
+    ) -> Result<(), io::Error> {
+        let local_addr = self.socket.local_addr()?;
+        let mut batch = RecvBatch::new(batch_size, MAX_DATAGRAM_SIZE);
         loop {
             tokio::select! {
-                res = self.socket.recv_from(&mut self.buf) => {
-                    let (size, _peer) = res?;
-                    metrics::udp::datagram_received();
-                    crate::metrics::messages::received();
+                res = self.socket.async_io(Interest::READABLE, || batch.recv(&self.socket)) => {
+                    let received = res?;
+                    metrics::udp::batch_received(received);
+                    let mut messages = Vec::with_capacity(received);
+                    for (datagram, peer) in batch.take() {
+                        // Only IP sockets are bound, the sender is always known
+                        let Some(peer) = peer.map(canonical_peer) else { continue };
+                        if !self.admit(peer).await {
+                            continue;
+                        }
+                        messages.extend(self.size_limit.apply(datagram).map(|payload| {
+                            Message::new(payload, peer, local_addr, ProtocolType::UDP)
+                        }));
+                    }
                     if self.settings.debug {
-                        self.count += 1;
-                        info!(
-                            "Poll datagram from server {}. Count: {}",
-                            self.name, self.count
-                        );
-                        let _ = self
-                            .writer_sender
-                            .send(FileWriterCommand::WriteDebug(
-                                self.name.clone(),
-                                self.buf[..size].to_vec(),
-                                self.count,
-                            ))
-                            .await;
-                    } else {
-                        debug!("Poll datagram from server {}.", self.name);
-                        let _ = self
-                            .writer_sender
-                            .send(FileWriterCommand::Write(self.buf[..size].to_vec()))
-                            .await;
+                        for message in messages {
+                            self.forward(message).await;
+                        }
+                    } else if !messages.is_empty() {
+                        debug!("Poll {} datagram(s) from server {}.", received, self.name);
+                        let format = &self.server.format;
+                        let messages = messages.into_iter().map(|m| parsed(format, m)).collect();
+                        let _ = self.router.send(FileWriterCommand::WriteBatch(messages)).await;
                     }
                 }
                 _ = shutdown_rx.recv() => {
@@ -108,4 +323,43 @@ impl UdpService {
         }
         Ok(())
     }
+
+    /// Whether a datagram from `peer` passes the allow/deny lists and the rate limit
+    async fn admit(&self, peer: SocketAddr) -> bool {
+        if let Some(access_list) = &self.access_list {
+            if !access_list.permits(peer.ip()) {
+                metrics::udp::datagram_denied();
+                return false;
+            }
+        }
+        metrics::udp::datagram_received();
+        crate::metrics::messages::received();
+        // Delaying stops reading the socket, the kernel buffers or drops meanwhile
+        match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter.admit(peer.ip()).await,
+            None => true,
+        }
+    }
+
+    async fn forward(&mut self, message: Message) {
+        if self.settings.debug {
+            self.count += 1;
+            info!(
+                "Poll datagram from server {}. Count: {}",
+                self.name, self.count
+            );
+            let _ = self
+                .router
+                .send(FileWriterCommand::WriteDebug(
+                    self.name.clone(),
+                    message,
+                    self.count,
+                ))
+                .await;
+        } else {
+            debug!("Poll datagram from server {}.", self.name);
+            let command = command_for(&self.server.format, message);
+            let _ = self.router.send(command).await;
+        }
+    }
 }
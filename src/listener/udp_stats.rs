@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::io;
+use std::mem;
+use std::os::fd::AsRawFd;
+use std::time::Duration;
+
+use log::{debug, warn};
+use socket2::SockRef;
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+
+use crate::listener::metrics;
+
+/// How often the kernel statistics are read
+pub const STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+const TABLES: [&str; 2] = ["/proc/net/udp", "/proc/net/udp6"];
+
+/// Kernel statistics of a UDP socket, as listed in /proc/net/udp
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocketStats {
+    pub inode: u64,
+    /// Bytes received and not read yet
+    pub rx_queue: u64,
+    /// Datagrams dropped since the socket was created, e.g. with a full receive buffer
+    pub drops: u64,
+}
+
+/// Parse the sockets of a /proc/net/udp or /proc/net/udp6 table, skipping the header
+/// and any malformed line
+pub fn parse_table(table: &str) -> impl Iterator<Item = SocketStats> + '_ {
+    table.lines().skip(1).filter_map(parse_line)
+}
+
+fn parse_line(line: &str) -> Option<SocketStats> {
+    // sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout
+    // inode ref pointer drops
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (_, rx_queue) = fields.get(4)?.split_once(':')?;
+    Some(SocketStats {
+        inode: fields.get(9)?.parse().ok()?,
+        rx_queue: u64::from_str_radix(rx_queue, 16).ok()?,
+        drops: fields.get(12)?.parse().ok()?,
+    })
+}
+
+struct Tracked {
+    label: String,
+    inode: u64,
+    drops: u64,
+}
+
+/// Exports the kernel drops and receive queue depth of the UDP sockets of a listener,
+/// which datagrams lost before they are read never show up in the other metrics
+pub struct KernelStats {
+    sockets: Vec<Tracked>,
+}
+
+impl KernelStats {
+    /// Track `sockets`, each with the label of its series, and export the size of
+    /// their receive buffers
+    pub fn new<'a>(
+        sockets: impl IntoIterator<Item = (String, &'a UdpSocket)>,
+    ) -> Result<Self, io::Error> {
+        let mut tracked = vec![];
+        for (label, socket) in sockets {
+            metrics::udp::recv_buffer(&label, SockRef::from(socket).recv_buffer_size()?);
+            tracked.push(Tracked {
+                label,
+                inode: inode(socket)?,
+                drops: 0,
+            });
+        }
+        Ok(KernelStats { sockets: tracked })
+    }
+
+    /// Read the statistics of the tracked sockets once and export them. Sockets
+    /// missing from the tables, e.g. closed already, are left out.
+    pub async fn collect(&mut self) -> Result<HashMap<String, SocketStats>, io::Error> {
+        let stats = tokio::task::spawn_blocking(read_tables).await??;
+        let mut collected = HashMap::new();
+        for socket in &mut self.sockets {
+            let Some(stats) = stats.get(&socket.inode) else {
+                continue;
+            };
+            metrics::udp::rx_queue(&socket.label, stats.rx_queue);
+            if stats.drops > socket.drops {
+                metrics::udp::kernel_drops(&socket.label, stats.drops - socket.drops);
+                socket.drops = stats.drops;
+            }
+            collected.insert(socket.label.clone(), *stats);
+        }
+        Ok(collected)
+    }
+
+    /// Collect every `interval` until shutdown
+    pub async fn run(mut self, interval: Duration, mut shutdown_rx: broadcast::Receiver<()>) {
+        let mut ticks = tokio::time::interval(interval);
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {
+                    if let Err(e) = self.collect().await {
+                        warn!("Failed to read UDP kernel statistics: {}", e);
+                    }
+                }
+                _ = shutdown_rx.recv() => {
+                    debug!("UDP kernel statistics collector stopped");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Statistics of every UDP socket of the host, by inode
+fn read_tables() -> Result<HashMap<u64, SocketStats>, io::Error> {
+    let mut stats = HashMap::new();
+    for table in TABLES {
+        match std::fs::read_to_string(table) {
+            Ok(table) => stats.extend(parse_table(&table).map(|s| (s.inode, s))),
+            // No IPv6 support in the kernel
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(stats)
+}
+
+/// Inode of a socket, which identifies it in /proc/net/udp
+fn inode(socket: &UdpSocket) -> Result<u64, io::Error> {
+    // SAFETY: fstat only writes the stat buffer, which all-zero is valid for
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+    if unsafe { libc::fstat(socket.as_raw_fd(), &mut stat) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stat.st_ino)
+}
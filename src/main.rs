@@ -3,16 +3,29 @@ use log::info;
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 use tokio::net::TcpListener;
+use tokio::sync::mpsc;
 
-use jon_listen::{metrics, settings::Settings, App};
+use jon_listen::{metrics, settings::Settings, App, Control};
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     pretty_env_logger::init();
 
     info!("Starting jon-listen app...");
 
     let settings = Settings::load().context("Failed to load settings")?;
+    let threads = usize::try_from(settings.threads)
+        .ok()
+        .filter(|threads| *threads > 0)
+        .context("threads must be greater than 0")?;
+    tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(threads)
+        .enable_all()
+        .build()
+        .context("Failed to build the Tokio runtime")?
+        .block_on(run(settings))
+}
+
+async fn run(settings: Settings) -> Result<()> {
     let metrics_port = settings.metrics_port;
     let settings = Arc::new(settings);
 
@@ -72,8 +85,19 @@ async fn main() -> Result<()> {
         }
     });
 
-    // Start the app with shutdown receiver
-    App::start_up(settings, shutdown_tx.subscribe())
+    // Reload the configuration on SIGHUP and reopen the output files on SIGHUP or SIGUSR1
+    let (control_tx, control_rx) = mpsc::channel(1);
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        if let Err(err) = control_on_signals(control_tx).await {
+            eprintln!("SIGHUP/SIGUSR1 handler task failed: {:#}", err);
+        }
+    });
+    #[cfg(not(unix))]
+    drop(control_tx);
+
+    // Start the app with shutdown and control receivers
+    App::start_up_with_control(settings, shutdown_tx.subscribe(), control_rx)
         .await
         .context("Application startup failed")?;
 
@@ -81,6 +105,44 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Load the configuration again on every SIGHUP and hand it to the app, which
+/// validates it and applies what it can without a restart. Both SIGHUP and SIGUSR1
+/// then reopen the output files, for external logrotate setups.
+#[cfg(unix)]
+async fn control_on_signals(control_tx: mpsc::Sender<Control>) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut sighup = signal(SignalKind::hangup())
+        .map_err(|e| anyhow::anyhow!("Failed to install SIGHUP handler: {}", e))?;
+    let mut sigusr1 = signal(SignalKind::user_defined1())
+        .map_err(|e| anyhow::anyhow!("Failed to install SIGUSR1 handler: {}", e))?;
+    loop {
+        tokio::select! {
+            Some(()) = sighup.recv() => {
+                info!("Received SIGHUP, reloading configuration and reopening files...");
+                match Settings::load() {
+                    Ok(settings) => {
+                        if control_tx.send(Control::Reload(Box::new(settings))).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => eprintln!(
+                        "Failed to reload configuration, keeping the running one: {:#}",
+                        e
+                    ),
+                }
+            }
+            Some(()) = sigusr1.recv() => {
+                info!("Received SIGUSR1, reopening files...");
+            }
+            else => break,
+        }
+        if control_tx.send(Control::Reopen).await.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
 /// Start HTTP server for Prometheus metrics scraping
 async fn start_metrics_server(port: u16, mut shutdown_rx: tokio::sync::broadcast::Receiver<()>) {
     let addr = format!("0.0.0.0:{}", port);
@@ -47,7 +47,32 @@ pub mod messages {
         counter!("messages_written_total", 1);
     }
 
+    pub fn written_batch(count: usize) {
+        counter!("messages_written_total", count as u64);
+    }
+
     pub fn dropped() {
         counter!("messages_dropped_total", 1);
     }
+
+    pub fn dropped_batch(count: usize) {
+        counter!("messages_dropped_total", count as u64);
+    }
+
+    pub fn lost_at_shutdown(count: usize) {
+        counter!("messages_lost_at_shutdown_total", count as u64);
+    }
+}
+
+/// Track configuration reloads
+pub mod config {
+    use metrics::counter;
+
+    pub fn reloaded() {
+        counter!("config_reloads_total", 1);
+    }
+
+    pub fn reload_failed() {
+        counter!("config_reload_errors_total", 1);
+    }
 }
@@ -0,0 +1,160 @@
+use anyhow::{Context, Result};
+
+use crate::listener::access_list::AccessList;
+use crate::router::DEFAULT_WRITER;
+use crate::settings::{FileWriterConfig, RotationPolicyType, ServerConfig, Settings};
+use crate::writer::file_writer::FileWriter;
+
+/// What a reloaded configuration changes in the running app
+#[derive(Debug)]
+pub struct Reload {
+    /// Settings in effect once the live changes are applied
+    pub settings: Settings,
+    /// Writers, by name, whose settings other than the file location changed
+    pub writers: Vec<String>,
+    /// Listeners, by position in `Settings::all_listeners`, whose max_connections or
+    /// allow/deny lists changed
+    pub listeners: Vec<usize>,
+    /// Changes that only take effect after a restart
+    pub restart_required: Vec<String>,
+}
+
+impl Reload {
+    /// Compare a freshly loaded configuration with the running one. Fails, leaving the
+    /// running configuration untouched, when the new one can't be applied.
+    pub fn plan(current: &Settings, new: Settings) -> Result<Self> {
+        for (name, config) in writer_configs(&new) {
+            FileWriter::validate_config(config)
+                .with_context(|| format!("Invalid configuration for writer '{}'", name))?;
+        }
+        for server in new.all_listeners() {
+            AccessList::new(&server.allow, &server.deny).with_context(|| {
+                format!(
+                    "Invalid allow/deny lists for {:?} listener on {}:{}",
+                    server.protocol, server.host, server.port
+                )
+            })?;
+        }
+
+        let mut reload = Reload {
+            settings: current.clone(),
+            writers: Vec::new(),
+            listeners: Vec::new(),
+            restart_required: Vec::new(),
+        };
+        let mut restart_if = |changed: bool, what: &str| {
+            if changed {
+                reload.restart_required.push(what.to_string());
+            }
+        };
+        restart_if(current.debug != new.debug, "debug");
+        restart_if(current.threads != new.threads, "threads");
+        restart_if(current.buffer_bound != new.buffer_bound, "buffer_bound");
+        restart_if(current.metrics_port != new.metrics_port, "metrics_port");
+        restart_if(current.routes != new.routes, "routes");
+        restart_if(
+            current.drain_timeout_ms != new.drain_timeout_ms,
+            "drain_timeout_ms",
+        );
+
+        reload.plan_listeners(current, &new);
+        reload.plan_writers(current, &new);
+        Ok(reload)
+    }
+
+    fn plan_listeners(&mut self, current: &Settings, new: &Settings) {
+        let running = current.all_listeners();
+        let loaded = new.all_listeners();
+        if running.len() != loaded.len() {
+            self.restart_required
+                .push("number of listeners".to_string());
+            return;
+        }
+        for (index, (running, loaded)) in running.iter().zip(&loaded).enumerate() {
+            // Only the connection limit and the allow/deny lists are applied while running
+            let applied = ServerConfig {
+                max_connections: loaded.max_connections,
+                allow: loaded.allow.clone(),
+                deny: loaded.deny.clone(),
+                ..running.clone()
+            };
+            if &applied != loaded {
+                self.restart_required.push(format!(
+                    "{:?} listener on {}:{}",
+                    running.protocol, running.host, running.port
+                ));
+            }
+            if &applied != running {
+                let server = match index {
+                    0 => &mut self.settings.server,
+                    _ => &mut self.settings.listeners[index - 1],
+                };
+                *server = applied;
+                self.listeners.push(index);
+            }
+        }
+    }
+
+    fn plan_writers(&mut self, current: &Settings, new: &Settings) {
+        let loaded = writer_configs(new);
+        for (name, _) in &loaded {
+            if !current.writers.contains_key(name) && name != DEFAULT_WRITER {
+                self.restart_required.push(format!("new writer '{}'", name));
+            }
+        }
+        for (name, running) in writer_configs(current) {
+            let Some((_, loaded)) = loaded.iter().find(|(loaded_name, _)| *loaded_name == name)
+            else {
+                self.restart_required
+                    .push(format!("removed writer '{}'", name));
+                continue;
+            };
+            if running.filedir != loaded.filedir || running.filename != loaded.filename {
+                self.restart_required
+                    .push(format!("file location of writer '{}'", name));
+            }
+            // The internal rotation task only exists when rotation isn't External
+            let external =
+                |config: &FileWriterConfig| config.rotation.policy == RotationPolicyType::External;
+            let rotation = if external(running) != external(loaded) {
+                self.restart_required
+                    .push(format!("External rotation of writer '{}'", name));
+                &running.rotation
+            } else {
+                &loaded.rotation
+            };
+            // Everything but the file location is applied to the running writer
+            let applied = FileWriterConfig {
+                filedir: running.filedir.clone(),
+                filename: running.filename.clone(),
+                rotation: rotation.clone(),
+                ..(*loaded).clone()
+            };
+            if &applied != running {
+                let config = match name.as_str() {
+                    DEFAULT_WRITER => &mut self.settings.filewriter,
+                    _ => self
+                        .settings
+                        .writers
+                        .get_mut(&name)
+                        .expect("running writer is in the running settings"),
+                };
+                *config = applied;
+                self.writers.push(name);
+            }
+        }
+    }
+}
+
+/// Every writer configuration by name, the `filewriter` section first
+fn writer_configs(settings: &Settings) -> Vec<(String, &FileWriterConfig)> {
+    let mut names: Vec<&String> = settings.writers.keys().collect();
+    names.sort();
+    let mut configs = vec![(DEFAULT_WRITER.to_string(), &settings.filewriter)];
+    configs.extend(
+        names
+            .into_iter()
+            .map(|name| (name.clone(), &settings.writers[name])),
+    );
+    configs
+}
@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use ipnet::IpNet;
+use log::debug;
+use regex::bytes::Regex;
+use tokio::sync::mpsc;
+
+use crate::error::RoutingError;
+use crate::settings::RouteConfig;
+use crate::writer::backpressure::BackpressureAwareSender;
+use crate::writer::file_writer::{FileWriterCommand, Message};
+
+/// Name under which the `filewriter` section is known to the router
+pub const DEFAULT_WRITER: &str = "default";
+
+/// Parse a network in CIDR notation or a single address
+pub(crate) fn parse_net(cidr: &str) -> Option<IpNet> {
+    cidr.parse::<IpNet>()
+        .or_else(|_| cidr.parse::<IpAddr>().map(IpNet::from))
+        .ok()
+}
+
+/// Compiled criteria of a route. Every criterion that is set must match
+#[derive(Debug)]
+struct RouteMatcher {
+    peer_net: Option<IpNet>,
+    listener_port: Option<u16>,
+    app_name: Option<String>,
+    pattern: Option<Regex>,
+}
+
+impl RouteMatcher {
+    fn compile(config: &RouteConfig) -> Result<Self, RoutingError> {
+        let peer_net = match &config.peer_cidr {
+            Some(cidr) => {
+                Some(parse_net(cidr).ok_or_else(|| RoutingError::InvalidCidr(cidr.clone()))?)
+            }
+            None => None,
+        };
+        let pattern = match &config.pattern {
+            Some(pattern) => {
+                Some(
+                    Regex::new(pattern).map_err(|e| RoutingError::InvalidPattern {
+                        pattern: pattern.clone(),
+                        reason: e.to_string(),
+                    })?,
+                )
+            }
+            None => None,
+        };
+        Ok(RouteMatcher {
+            peer_net,
+            listener_port: config.listener_port,
+            app_name: config.app_name.clone(),
+            pattern,
+        })
+    }
+
+    fn matches(&self, message: &Message) -> bool {
+        if let Some(net) = &self.peer_net {
+            if !net.contains(&message.peer.ip()) {
+                return false;
+            }
+        }
+        if let Some(port) = self.listener_port {
+            if port != message.listener.port() {
+                return false;
+            }
+        }
+        if let Some(app_name) = &self.app_name {
+            let message_app_name = message
+                .syslog
+                .as_ref()
+                .and_then(|syslog| syslog.app_name.as_deref());
+            if message_app_name != Some(app_name.as_str()) {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(&message.payload) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// The received message carried by a command, if any
+fn message(command: &FileWriterCommand) -> Option<&Message> {
+    match command {
+        FileWriterCommand::Write(message) => Some(message),
+        FileWriterCommand::WriteDebug(_, message, _) => Some(message),
+        // Routed message by message, see Router::send_batch
+        FileWriterCommand::WriteBatch(_)
+        | FileWriterCommand::Rename(_)
+        | FileWriterCommand::Reopen
+        | FileWriterCommand::Reconfigure(_) => None,
+    }
+}
+
+struct Route {
+    writer: String,
+    matcher: RouteMatcher,
+    sender: BackpressureAwareSender,
+}
+
+/// Dispatches messages from the listeners to the FileWriter selected by the
+/// first matching route, or to the default FileWriter when none matches
+#[derive(Clone)]
+pub struct Router {
+    default: BackpressureAwareSender,
+    routes: Arc<Vec<Route>>,
+}
+
+impl Router {
+    pub fn new(
+        default: BackpressureAwareSender,
+        writers: &HashMap<String, BackpressureAwareSender>,
+        routes: &[RouteConfig],
+    ) -> Result<Self, RoutingError> {
+        if writers.contains_key(DEFAULT_WRITER) {
+            return Err(RoutingError::ReservedWriter(DEFAULT_WRITER.to_string()));
+        }
+        let routes = routes
+            .iter()
+            .map(|config| {
+                let sender = match writers.get(&config.writer) {
+                    Some(sender) => sender.clone(),
+                    None if config.writer == DEFAULT_WRITER => default.clone(),
+                    None => return Err(RoutingError::UnknownWriter(config.writer.clone())),
+                };
+                Ok(Route {
+                    writer: config.writer.clone(),
+                    matcher: RouteMatcher::compile(config)?,
+                    sender,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Router {
+            default,
+            routes: Arc::new(routes),
+        })
+    }
+
+    /// Name of the writer a message would be dispatched to
+    pub fn writer_for(&self, command: &FileWriterCommand) -> &str {
+        self.route_for(command)
+            .map(|route| route.writer.as_str())
+            .unwrap_or(DEFAULT_WRITER)
+    }
+
+    pub async fn send(
+        &self,
+        command: FileWriterCommand,
+    ) -> Result<(), mpsc::error::SendError<FileWriterCommand>> {
+        if let FileWriterCommand::WriteBatch(messages) = command {
+            return self.send_batch(messages).await;
+        }
+        match self.route_for(&command) {
+            Some(route) => {
+                debug!("Routing message to {}", route.writer);
+                route.sender.send(command).await
+            }
+            None => self.default.send(command).await,
+        }
+    }
+
+    /// Split a batch into one batch per writer, keeping the order of the messages
+    async fn send_batch(
+        &self,
+        messages: Vec<Message>,
+    ) -> Result<(), mpsc::error::SendError<FileWriterCommand>> {
+        let mut batches: Vec<(Option<usize>, Vec<Message>)> = Vec::new();
+        for message in messages {
+            let route = self
+                .routes
+                .iter()
+                .position(|route| route.matcher.matches(&message));
+            match batches.iter_mut().find(|(index, _)| *index == route) {
+                Some((_, batch)) => batch.push(message),
+                None => batches.push((route, vec![message])),
+            }
+        }
+        for (route, batch) in batches {
+            let sender = match route {
+                Some(index) => &self.routes[index].sender,
+                None => &self.default,
+            };
+            sender.send(FileWriterCommand::WriteBatch(batch)).await?;
+        }
+        Ok(())
+    }
+
+    fn route_for(&self, command: &FileWriterCommand) -> Option<&Route> {
+        let message = message(command)?;
+        self.routes
+            .iter()
+            .find(|route| route.matcher.matches(message))
+    }
+}
+
+/// A router without rules, sending everything to a single FileWriter
+impl From<BackpressureAwareSender> for Router {
+    fn from(default: BackpressureAwareSender) -> Self {
+        Router {
+            default,
+            routes: Arc::new(Vec::new()),
+        }
+    }
+}
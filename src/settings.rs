@@ -2,17 +2,20 @@ use config::{Config, Environment, File};
 use log::info;
 use serde;
 use serde::de::Deserializer;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 use std::path::PathBuf;
 
+use crate::router::DEFAULT_WRITER;
+
 pub trait DeserializeWith: Sized {
     fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>;
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, PartialEq, Clone)]
 pub struct ServerConfig {
     #[serde(deserialize_with = "ProtocolType::deserialize_with")]
     pub protocol: ProtocolType,
@@ -20,13 +23,157 @@ pub struct ServerConfig {
     pub port: i32,
     #[serde(default = "default_max_connections")]
     pub max_connections: usize,
+    #[serde(
+        default = "default_message_format",
+        deserialize_with = "MessageFormat::deserialize_with"
+    )]
+    pub format: MessageFormat,
+    /// Serve TLS instead of plaintext (only applies to TCP)
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Limit the rate of messages accepted from each peer IP
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitConfig>,
+    /// Peer networks or addresses accepted, e.g. "10.0.0.0/8". Every peer when empty
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Peer networks or addresses rejected, even when allowed
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Close TCP connections that send nothing for this long
+    #[serde(default)]
+    pub idle_timeout_ms: Option<u64>,
+    /// Close TCP connections that take longer than this to complete a line once it started
+    #[serde(default)]
+    pub read_timeout_ms: Option<u64>,
+    /// Close TCP connections once they have been open this long
+    #[serde(default)]
+    pub max_connection_lifetime_ms: Option<u64>,
+    /// Largest message accepted as is, in bytes, for both TCP lines and UDP datagrams
+    #[serde(default = "default_max_message_size")]
+    pub max_message_size: usize,
+    #[serde(
+        default = "default_oversized_policy",
+        deserialize_with = "OversizedPolicy::deserialize_with"
+    )]
+    pub oversized_policy: OversizedPolicy,
+    /// UDP sockets bound to the address with SO_REUSEPORT, each read by its own task.
+    /// One per worker thread when unset
+    #[serde(default)]
+    pub udp_sockets: Option<usize>,
+    /// SO_RCVBUF of the UDP sockets, in bytes. The system default when unset
+    #[serde(default)]
+    pub udp_recv_buffer_size: Option<usize>,
+    /// Datagrams received with a single recvmmsg call and written as a batch (Linux
+    /// only). One recv_from call per datagram when unset
+    #[serde(default)]
+    pub udp_batch_size: Option<usize>,
+}
+
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+fn default_max_message_size() -> usize {
+    DEFAULT_MAX_MESSAGE_SIZE
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum OversizedPolicy {
+    Truncate, // Keep the first max_message_size bytes followed by a marker
+    Split,    // Write the message as several messages of at most max_message_size bytes
+    Drop,     // Discard the message
+}
+
+impl DeserializeWith for OversizedPolicy {
+    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(de)?;
+
+        match s.as_ref() {
+            "Truncate" => Ok(OversizedPolicy::Truncate),
+            "Split" => Ok(OversizedPolicy::Split),
+            "Drop" => Ok(OversizedPolicy::Drop),
+            _ => Err(serde::de::Error::custom(
+                "error trying to deserialize oversized policy config. Must be 'Truncate', 'Split' or 'Drop'",
+            )),
+        }
+    }
+}
+
+fn default_oversized_policy() -> OversizedPolicy {
+    OversizedPolicy::Truncate
+}
+
+/// Token bucket applied to every peer IP of a listener
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct RateLimitConfig {
+    /// Messages per second allowed from each peer
+    pub rate: f64,
+    /// Messages a peer can send at once above the rate. Defaults to the rate
+    pub burst: Option<f64>,
+    #[serde(
+        default = "default_rate_limit_action",
+        deserialize_with = "RateLimitAction::deserialize_with"
+    )]
+    pub action: RateLimitAction,
+    /// Different limits for the peers of some networks, the first matching one applies
+    #[serde(default)]
+    pub overrides: Vec<RateLimitOverride>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct RateLimitOverride {
+    /// Peer network or address, e.g. "10.0.0.0/8" or "192.168.1.10"
+    pub cidr: String,
+    pub rate: f64,
+    pub burst: Option<f64>,
+}
+
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum RateLimitAction {
+    Drop,  // Discard the messages above the limit
+    Delay, // Wait until the limit allows the message, slowing the peer down
+}
+
+impl DeserializeWith for RateLimitAction {
+    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(de)?;
+
+        match s.as_ref() {
+            "Drop" => Ok(RateLimitAction::Drop),
+            "Delay" => Ok(RateLimitAction::Delay),
+            _ => Err(serde::de::Error::custom(
+                "error trying to deserialize rate limit action config. Must be 'Drop' or 'Delay'",
+            )),
+        }
+    }
+}
+
+fn default_rate_limit_action() -> RateLimitAction {
+    RateLimitAction::Drop
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct TlsConfig {
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// CA bundle used to verify client certificates. When set, clients must present one (mTLS)
+    pub client_ca_path: Option<PathBuf>,
 }
 
 fn default_max_connections() -> usize {
     1000
 }
 
-#[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
+fn default_message_format() -> MessageFormat {
+    MessageFormat::Raw
+}
+
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone)]
 pub enum ProtocolType {
     TCP,
     UDP,
@@ -49,16 +196,44 @@ impl DeserializeWith for ProtocolType {
     }
 }
 
+#[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
+pub enum MessageFormat {
+    Raw,    // Each line or datagram is written as received
+    Syslog, // RFC 3164 / RFC 5424 messages, with RFC 6587 framing over TCP
+}
+
+impl DeserializeWith for MessageFormat {
+    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(de)?;
+
+        match s.as_ref() {
+            "Raw" => Ok(MessageFormat::Raw),
+            "Syslog" => Ok(MessageFormat::Syslog),
+            _ => Err(serde::de::Error::custom(
+                "error trying to deserialize message format config. Must be 'Raw' or 'Syslog'",
+            )),
+        }
+    }
+}
+
 #[derive(Debug, Deserialize, Eq, PartialEq, Clone)]
 pub enum RotationPolicyType {
     ByDuration,
     ByDay,
+    BySize,
+    External, // Leave rotation to an external tool such as logrotate
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub enum BackpressurePolicy {
-    Block,   // Block message ingestion until space is available
-    Discard, // Discard messages when channel is full
+    Block,       // Block message ingestion until space is available
+    Discard,     // Discard messages when channel is full
+    Spill, // Queue messages on disk when channel is full and write them once the writer catches up
+    DropOldest, // Evict the oldest queued message to make room for the new one
+    Sample(u64), // Keep one message in n while the channel is full, discarding the others
 }
 
 impl DeserializeWith for BackpressurePolicy {
@@ -71,9 +246,18 @@ impl DeserializeWith for BackpressurePolicy {
         match s.as_ref() {
             "Block" => Ok(BackpressurePolicy::Block),
             "Discard" => Ok(BackpressurePolicy::Discard),
-            _ => Err(serde::de::Error::custom(
-                "error trying to deserialize backpressure policy config. Must be 'Block' or 'Discard'",
-            )),
+            "Spill" => Ok(BackpressurePolicy::Spill),
+            "DropOldest" => Ok(BackpressurePolicy::DropOldest),
+            _ => match s
+                .strip_prefix("Sample(")
+                .and_then(|rest| rest.strip_suffix(')'))
+                .and_then(|n| n.trim().parse().ok())
+            {
+                Some(n) if n > 0 => Ok(BackpressurePolicy::Sample(n)),
+                _ => Err(serde::de::Error::custom(
+                    "error trying to deserialize backpressure policy config. Must be 'Block', 'Discard', 'Spill', 'DropOldest' or 'Sample(n)' with n > 0",
+                )),
+            },
         }
     }
 }
@@ -88,6 +272,8 @@ impl DeserializeWith for RotationPolicyType {
         match s.as_ref() {
             "ByDuration" => Ok(RotationPolicyType::ByDuration),
             "ByDay" => Ok(RotationPolicyType::ByDay),
+            "BySize" => Ok(RotationPolicyType::BySize),
+            "External" => Ok(RotationPolicyType::External),
             _ => Err(serde::de::Error::custom(
                 "error trying to deserialize rotation policy config",
             )),
@@ -95,21 +281,251 @@ impl DeserializeWith for RotationPolicyType {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Compression {
+    None, // Keep rotated files as they are
+    Gzip, // Compress rotated files to `<name>.N.gz`
+    Zstd, // Compress rotated files to `<name>.N.zst`
+}
+
+impl DeserializeWith for Compression {
+    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(de)?;
+
+        match s.as_ref() {
+            "None" => Ok(Compression::None),
+            "Gzip" => Ok(Compression::Gzip),
+            "Zstd" => Ok(Compression::Zstd),
+            _ => Err(serde::de::Error::custom(
+                "error trying to deserialize compression config. Must be 'None', 'Gzip' or 'Zstd'",
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
 pub struct RotationPolicyConfig {
     pub count: i32,
     #[serde(deserialize_with = "RotationPolicyType::deserialize_with")]
     pub policy: RotationPolicyType,
     pub duration: Option<u64>,
+    /// Rotate once the active file reaches this many bytes. Required for BySize,
+    /// and combined with ByDuration/ByDay it rotates on whichever comes first
+    pub max_size: Option<u64>,
+    #[serde(
+        default = "default_compression",
+        deserialize_with = "Compression::deserialize_with"
+    )]
+    pub compression: Compression,
+}
+
+impl RotationPolicyConfig {
+    /// Check the settings the policy requires or forbids
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_size == Some(0) {
+            // Would rotate on every message
+            return Err("max_size must be greater than 0".to_string());
+        }
+        match self.policy {
+            RotationPolicyType::ByDuration if self.duration.is_none() => {
+                Err("duration is required for the ByDuration rotation policy".to_string())
+            }
+            RotationPolicyType::BySize if self.max_size.is_none() => {
+                Err("max_size is required for the BySize rotation policy".to_string())
+            }
+            RotationPolicyType::External if self.max_size.is_some() => {
+                Err("max_size can't be used with the External rotation policy".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn default_compression() -> Compression {
+    Compression::None
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum OutputFormat {
+    Plain, // Each message is written as received, one per line
+    Json,  // Each message is written as a JSON object with its metadata, one per line
+}
+
+impl DeserializeWith for OutputFormat {
+    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(de)?;
+
+        match s.as_ref() {
+            "Plain" => Ok(OutputFormat::Plain),
+            "Json" => Ok(OutputFormat::Json),
+            _ => Err(serde::de::Error::custom(
+                "error trying to deserialize output format config. Must be 'Plain' or 'Json'",
+            )),
+        }
+    }
+}
+
+/// Time zone used to render timestamps in line prefixes
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Timezone {
+    Utc,
+    Local,
+}
+
+impl DeserializeWith for Timezone {
+    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(de)?;
+
+        match s.as_ref() {
+            "Utc" => Ok(Timezone::Utc),
+            "Local" => Ok(Timezone::Local),
+            _ => Err(serde::de::Error::custom(
+                "error trying to deserialize timezone config. Must be 'Utc' or 'Local'",
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
 pub struct FormattingConfig {
     pub startingmsg: bool,
     pub endingmsg: bool,
+    #[serde(
+        default = "default_output_format",
+        deserialize_with = "OutputFormat::deserialize_with"
+    )]
+    pub output: OutputFormat,
+    /// Template prepended to every written line, e.g. "{timestamp_rfc3339} {peer_ip} "
+    #[serde(default)]
+    pub prefix: Option<String>,
+    #[serde(
+        default = "default_timezone",
+        deserialize_with = "Timezone::deserialize_with"
+    )]
+    pub timezone: Timezone,
+}
+
+impl FormattingConfig {
+    /// Whether a file starts with a "Starting ..." line. Never with Json output, which
+    /// must stay one JSON object per line.
+    pub fn starting_msg(&self) -> bool {
+        self.startingmsg && self.output == OutputFormat::Plain
+    }
+
+    /// Whether a file ends with an "Ending ..." line, never with Json output either
+    pub fn ending_msg(&self) -> bool {
+        self.endingmsg && self.output == OutputFormat::Plain
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.prefix.is_some() && self.output == OutputFormat::Json {
+            // The prefix would come before the JSON object on every line
+            return Err("prefix can't be used with the Json output".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn default_output_format() -> OutputFormat {
+    OutputFormat::Plain
+}
+
+fn default_timezone() -> Timezone {
+    Timezone::Local
+}
+
+/// Batching of the messages written to a file
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct BufferConfig {
+    /// Bytes collected before they are written to the file at once. 0 writes every message on its own
+    #[serde(default = "default_buffer_size")]
+    pub size: usize,
+    /// Longest time in milliseconds a message waits in the buffer. 0 writes the buffer as soon as
+    /// no more messages are queued
+    #[serde(default)]
+    pub flush_interval_ms: u64,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        BufferConfig {
+            size: default_buffer_size(),
+            flush_interval_ms: 0,
+        }
+    }
+}
+
+fn default_buffer_size() -> usize {
+    64 * 1024
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// When written data is synced to disk with fsync
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Durability {
+    None,     // Leave it to the OS, a host crash loses whatever the page cache held
+    Interval, // Sync at most every `interval_ms`
+    Batch,    // Sync after every batched write
+    Message,  // Write and sync every message on its own
+}
+
+impl DeserializeWith for Durability {
+    fn deserialize_with<'de, D>(de: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(de)?;
+
+        match s.as_ref() {
+            "None" => Ok(Durability::None),
+            "Interval" => Ok(Durability::Interval),
+            "Batch" => Ok(Durability::Batch),
+            "Message" => Ok(Durability::Message),
+            _ => Err(serde::de::Error::custom(
+                "error trying to deserialize durability config. Must be 'None', 'Interval', 'Batch' or 'Message'",
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct DurabilityConfig {
+    #[serde(
+        default = "default_durability",
+        deserialize_with = "Durability::deserialize_with"
+    )]
+    pub mode: Durability,
+    /// Milliseconds between syncs with the Interval mode
+    #[serde(default = "default_sync_interval_ms")]
+    pub interval_ms: u64,
+}
+
+impl Default for DurabilityConfig {
+    fn default() -> Self {
+        DurabilityConfig {
+            mode: default_durability(),
+            interval_ms: default_sync_interval_ms(),
+        }
+    }
+}
+
+fn default_durability() -> Durability {
+    Durability::None
+}
+
+fn default_sync_interval_ms() -> u64 {
+    1000
+}
+
+#[derive(Debug, Deserialize, PartialEq, Clone)]
 pub struct FileWriterConfig {
     pub filedir: PathBuf,
     pub filename: String,
@@ -120,28 +536,109 @@ pub struct FileWriterConfig {
         deserialize_with = "BackpressurePolicy::deserialize_with"
     )]
     pub backpressure_policy: BackpressurePolicy,
+    #[serde(default)]
+    pub buffer: BufferConfig,
+    #[serde(default)]
+    pub durability: DurabilityConfig,
+    /// Maximum size in bytes of the on-disk queue used by the Spill backpressure policy
+    #[serde(default = "default_spill_max_size")]
+    pub spill_max_size: u64,
+}
+
+fn default_spill_max_size() -> u64 {
+    1024 * 1024 * 1024
+}
+
+/// Dispatch messages matching every criterion that is set to the named writer
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct RouteConfig {
+    pub writer: String,
+    /// Peer address or network, e.g. "10.0.0.0/8" or "192.168.1.10"
+    pub peer_cidr: Option<String>,
+    /// Local port of the listener that received the message
+    pub listener_port: Option<u16>,
+    /// Syslog APP-NAME (only matches messages parsed by a Syslog listener)
+    pub app_name: Option<String>,
+    /// Regular expression searched in the raw message
+    pub pattern: Option<String>,
 }
 
 fn default_backpressure_policy() -> BackpressurePolicy {
     BackpressurePolicy::Discard
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, PartialEq, Clone)]
 pub struct Settings {
     pub debug: bool,
     pub threads: i32,
     pub buffer_bound: usize,
     pub server: ServerConfig,
+    /// Extra listeners started alongside `server`, all feeding the same FileWriter
+    #[serde(default)]
+    pub listeners: Vec<ServerConfig>,
     pub filewriter: FileWriterConfig,
+    /// Named FileWriters that routes can dispatch to, besides the default `filewriter`
+    #[serde(default)]
+    pub writers: HashMap<String, FileWriterConfig>,
+    /// Routing rules, evaluated in order. Messages matching none go to `filewriter`
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
     #[serde(default = "default_metrics_port")]
     pub metrics_port: u16,
+    /// Milliseconds the writers get on shutdown to write the messages still queued
+    #[serde(default = "default_drain_timeout_ms")]
+    pub drain_timeout_ms: u64,
 }
 
 fn default_metrics_port() -> u16 {
     9090
 }
 
+fn default_drain_timeout_ms() -> u64 {
+    5000
+}
+
 impl Settings {
+    /// Every listener definition to start: the primary `server` followed by `listeners`
+    pub fn all_listeners(&self) -> Vec<ServerConfig> {
+        let mut listeners = Vec::with_capacity(self.listeners.len() + 1);
+        listeners.push(self.server.clone());
+        listeners.extend(self.listeners.iter().cloned());
+        listeners
+    }
+
+    /// Reject settings that deserialize but can't work
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.writers.contains_key(DEFAULT_WRITER) {
+            anyhow::bail!(
+                "Writer name '{}' is reserved for [filewriter]",
+                DEFAULT_WRITER
+            );
+        }
+        let writers = std::iter::once((DEFAULT_WRITER, &self.filewriter))
+            .chain(self.writers.iter().map(|(name, w)| (name.as_str(), w)));
+        for (name, writer) in writers {
+            writer
+                .rotation
+                .validate()
+                .map_err(|e| anyhow::anyhow!("Invalid rotation for writer '{}': {}", name, e))?;
+            writer
+                .formatting
+                .validate()
+                .map_err(|e| anyhow::anyhow!("Invalid formatting for writer '{}': {}", name, e))?;
+        }
+        for server in std::iter::once(&self.server).chain(&self.listeners) {
+            if server.protocol == ProtocolType::UDP && server.tls.is_some() {
+                anyhow::bail!(
+                    "TLS can't be used with the UDP listener on {}:{}",
+                    server.host,
+                    server.port
+                );
+            }
+        }
+        Ok(())
+    }
+
     pub fn load() -> anyhow::Result<Self> {
         let run_mode = env::var("RUN_MODE").unwrap_or("development".into());
 
@@ -161,6 +658,7 @@ impl Settings {
             .try_deserialize()
             .map_err(|e| anyhow::anyhow!("Failed to deserialize settings: {}", e))?;
         info!("Settings: {:?}", settings);
+        settings.validate()?;
         Ok(settings)
     }
 }
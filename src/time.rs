@@ -0,0 +1,9 @@
+use tokio::time::Instant;
+
+/// Wait until the deadline, or forever when there is none
+pub(crate) async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
@@ -1,19 +1,25 @@
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
 use crate::metrics::messages;
 use crate::settings::BackpressurePolicy;
-use crate::writer::file_writer::FileWriterCommand;
+use crate::writer::file_writer::{FileWriterCommand, Message};
 use crate::writer::metrics;
+use crate::writer::queue::CommandQueue;
+use crate::writer::spill::SpillQueue;
 
 /// Helper for sending messages to FileWriter with backpressure detection and logging
 pub struct BackpressureAwareSender {
     sender: mpsc::Sender<FileWriterCommand>,
-    backpressure_policy: BackpressurePolicy,
+    // Shared by every clone so that a reload applies to all of them
+    backpressure_policy: Arc<RwLock<BackpressurePolicy>>,
     backpressure_events: Arc<AtomicU64>,
-    dropped_messages: Arc<AtomicU64>, // Only used when policy is Discard
+    dropped_messages: Arc<AtomicU64>, // Only used when policy is Discard, Spill, DropOldest or Sample
+    sampled_messages: Arc<AtomicU64>, // Messages seen on a full channel with the Sample policy
+    spill: Option<Arc<SpillQueue>>,
+    queue: Option<CommandQueue>,
     last_log_time: Arc<std::sync::Mutex<Instant>>,
     log_interval: Duration,
 }
@@ -25,53 +31,79 @@ impl BackpressureAwareSender {
     ) -> Self {
         Self {
             sender,
-            backpressure_policy,
+            backpressure_policy: Arc::new(RwLock::new(backpressure_policy)),
             backpressure_events: Arc::new(AtomicU64::new(0)),
             dropped_messages: Arc::new(AtomicU64::new(0)),
+            sampled_messages: Arc::new(AtomicU64::new(0)),
+            spill: None,
+            queue: None,
             last_log_time: Arc::new(std::sync::Mutex::new(Instant::now())),
             log_interval: Duration::from_secs(5), // Log at most once every 5 seconds
         }
     }
 
+    /// Queue used by the Spill policy. Without one, Spill blocks like Block.
+    pub fn with_spill_queue(mut self, spill: Arc<SpillQueue>) -> Self {
+        self.spill = Some(spill);
+        self
+    }
+
+    /// Queue the DropOldest policy evicts from, the receiving end of the channel.
+    /// Without one, DropOldest discards the new message like Discard.
+    pub fn with_command_queue(mut self, queue: CommandQueue) -> Self {
+        self.queue = Some(queue);
+        self
+    }
+
     /// Send a message with backpressure detection.
     /// If the channel is full, behavior depends on the configured backpressure policy:
     /// - Block: Waits until space is available (provides natural backpressure)
     /// - Discard: Drops the message and logs a warning
+    /// - Spill: Appends the message to the on-disk spill queue, which the FileWriter
+    ///   replays once it catches up. Messages keep going to the spill queue until then,
+    ///   so that they are written in order.
+    /// - DropOldest: Evicts the oldest queued message to make room for the new one
+    /// - Sample(n): Keeps one message in n, waiting for space for it, and drops the others
+    ///
+    /// The messages of a batch are dropped or spilled together, and sampled one by one.
     ///
     /// Logs to stderr (not through FileWriter) to avoid feedback loops.
     pub async fn send(
+        &self,
+        mut command: FileWriterCommand,
+    ) -> Result<(), mpsc::error::SendError<FileWriterCommand>> {
+        mark_enqueued(&mut command);
+        let result = self.send_with_policy(command).await;
+        if let Some(queue) = &self.queue {
+            queue.enqueued(&self.sender);
+        }
+        result
+    }
+
+    async fn send_with_policy(
         &self,
         command: FileWriterCommand,
     ) -> Result<(), mpsc::error::SendError<FileWriterCommand>> {
-        // Try to send without blocking first
-        match self.sender.try_send(command.clone()) {
-            Ok(()) => {
-                // Note: tokio::sync::mpsc::Sender doesn't expose queue depth directly
-                // The capacity() method returns remaining capacity, not current depth
-                // To track queue depth accurately, we would need a wrapper that counts sends/receives
-                Ok(())
+        if let (Some(spill), Some(messages)) = (&self.spill, received_messages(&command)) {
+            if spill.has_pending() && self.backpressure_policy() == BackpressurePolicy::Spill {
+                self.spill_messages(spill, messages).await;
+                return Ok(());
             }
-            Err(mpsc::error::TrySendError::Full(_)) => {
+        }
+        // Try to send without blocking first, the command is handed back when it fails
+        match self.sender.try_send(command) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(command)) => {
                 // Channel is full - handle based on policy
                 metrics::backpressure::event();
-                match self.backpressure_policy {
+                let backpressure_policy = self.backpressure_policy();
+                match backpressure_policy {
                     BackpressurePolicy::Block => {
                         // Increment backpressure event counter
                         let events = self.backpressure_events.fetch_add(1, Ordering::Relaxed) + 1;
 
                         // Rate-limited logging to stderr (not through FileWriter channel)
-                        let should_log = {
-                            let mut last_log = self.last_log_time.lock().unwrap();
-                            let now = Instant::now();
-                            if now.duration_since(*last_log) >= self.log_interval {
-                                *last_log = now;
-                                true
-                            } else {
-                                false
-                            }
-                        };
-
-                        if should_log {
+                        if self.should_log() {
                             eprintln!(
                                 "WARNING: FileWriter channel is full (capacity: {}). {} backpressure events detected. \
                                  Message ingestion will block until FileWriter processes messages and clears space. \
@@ -84,39 +116,42 @@ impl BackpressureAwareSender {
                         // Block until there's space - this provides natural backpressure
                         self.sender.send(command).await
                     }
-                    BackpressurePolicy::Discard => {
-                        // Increment counters
-                        let events = self.backpressure_events.fetch_add(1, Ordering::Relaxed) + 1;
-                        let dropped = self.dropped_messages.fetch_add(1, Ordering::Relaxed) + 1;
-
-                        // Rate-limited logging to stderr
-                        let should_log = {
-                            let mut last_log = self.last_log_time.lock().unwrap();
-                            let now = Instant::now();
-                            if now.duration_since(*last_log) >= self.log_interval {
-                                *last_log = now;
-                                true
-                            } else {
-                                false
-                            }
-                        };
-
-                        if should_log {
-                            eprintln!(
-                                "WARNING: FileWriter channel is full (capacity: {}). {} backpressure events detected. \
-                                 {} messages dropped so far. Message discarded (backpressure_policy=Discard). \
-                                 This indicates backpressure - FileWriter may be slower than message rate.",
-                                self.sender.capacity(),
-                                events,
-                                dropped
-                            );
+                    BackpressurePolicy::Spill => match (&self.spill, received_messages(&command)) {
+                        (Some(spill), Some(messages)) => {
+                            self.backpressure_events.fetch_add(1, Ordering::Relaxed);
+                            self.spill_messages(spill, messages).await;
+                            Ok(())
                         }
+                        // Only received messages can be spilled
+                        _ => self.sender.send(command).await,
+                    },
+                    BackpressurePolicy::Discard => {
+                        let count = command.message_count();
+                        metrics::backpressure::discarded(count);
+                        self.drop_messages(count, "Message discarded", "Discard");
 
                         // Return success even though we dropped the message
                         // This allows the caller to continue processing
-                        messages::dropped();
                         Ok(())
                     }
+                    // Only received messages can be dropped, other commands always wait
+                    _ if command.message_count() == 0 => self.sender.send(command).await,
+                    BackpressurePolicy::DropOldest => match &self.queue {
+                        Some(queue) => self.send_dropping_oldest(queue, command).await,
+                        None => {
+                            let count = command.message_count();
+                            metrics::backpressure::discarded(count);
+                            self.drop_messages(count, "Message discarded", "DropOldest");
+                            Ok(())
+                        }
+                    },
+                    BackpressurePolicy::Sample(n) => match self.sample(command, n) {
+                        Some(command) => {
+                            self.backpressure_events.fetch_add(1, Ordering::Relaxed);
+                            self.sender.send(command).await
+                        }
+                        None => Ok(()),
+                    },
                 }
             }
             Err(mpsc::error::TrySendError::Closed(msg)) => {
@@ -127,12 +162,133 @@ impl BackpressureAwareSender {
         }
     }
 
+    /// Evict the oldest queued messages until the new one fits in the channel, without
+    /// ever waiting for the FileWriter
+    async fn send_dropping_oldest(
+        &self,
+        queue: &CommandQueue,
+        mut command: FileWriterCommand,
+    ) -> Result<(), mpsc::error::SendError<FileWriterCommand>> {
+        loop {
+            // With no message queued, the other commands are set aside and the
+            // channel is left empty
+            let evicted = queue.evict_oldest();
+            if evicted > 0 {
+                metrics::backpressure::dropped_oldest(evicted);
+                self.drop_messages(evicted, "Oldest queued message evicted", "DropOldest");
+            }
+            // Another sender may take the freed slot first
+            match self.sender.try_send(command) {
+                Ok(()) => return Ok(()),
+                Err(mpsc::error::TrySendError::Full(retry)) => command = retry,
+                Err(mpsc::error::TrySendError::Closed(command)) => {
+                    return Err(mpsc::error::SendError(command))
+                }
+            }
+        }
+    }
+
+    /// Keep one message in `n` of a command, `None` when none of its messages is kept
+    fn sample(&self, command: FileWriterCommand, n: u64) -> Option<FileWriterCommand> {
+        let count = command.message_count() as u64;
+        let seen = self.sampled_messages.fetch_add(count, Ordering::Relaxed);
+        let mut index = seen;
+        let mut keep = || {
+            let kept = index.is_multiple_of(n);
+            index += 1;
+            metrics::backpressure::sampled(kept);
+            kept
+        };
+        let command = match command {
+            FileWriterCommand::WriteBatch(mut messages) => {
+                messages.retain(|_| keep());
+                (!messages.is_empty()).then_some(FileWriterCommand::WriteBatch(messages))
+            }
+            command => keep().then_some(command),
+        };
+        let sampled_out =
+            count - command.as_ref().map_or(0, FileWriterCommand::message_count) as u64;
+        if sampled_out > 0 {
+            self.drop_messages(sampled_out as usize, "Message sampled out", "Sample");
+        }
+        command
+    }
+
+    /// Count messages dropped by a full channel and warn about it, rate-limited
+    fn drop_messages(&self, count: usize, what: &str, policy: &str) {
+        let events = self.backpressure_events.fetch_add(1, Ordering::Relaxed) + 1;
+        let dropped = self
+            .dropped_messages
+            .fetch_add(count as u64, Ordering::Relaxed)
+            + count as u64;
+        messages::dropped_batch(count);
+        if self.should_log() {
+            eprintln!(
+                "WARNING: FileWriter channel is full (capacity: {}). {} backpressure events detected. \
+                 {} messages dropped so far. {} (backpressure_policy={}). \
+                 This indicates backpressure - FileWriter may be slower than message rate.",
+                self.sender.capacity(),
+                events,
+                dropped,
+                what,
+                policy
+            );
+        }
+    }
+
+    /// Append messages to the spill queue, dropping the ones that don't fit
+    async fn spill_messages(&self, spill: &SpillQueue, messages: &[Message]) {
+        for message in messages {
+            self.spill_message(spill, message).await;
+        }
+    }
+
+    /// Append a message to the spill queue, dropping it when the queue is full
+    async fn spill_message(&self, spill: &SpillQueue, message: &Message) {
+        let error = match spill.push(message).await {
+            Ok(true) => return,
+            Ok(false) => format!("spill queue {:?} reached its maximum size", spill.path()),
+            Err(e) => format!("failed to write to spill queue {:?}: {}", spill.path(), e),
+        };
+        let dropped = self.dropped_messages.fetch_add(1, Ordering::Relaxed) + 1;
+        metrics::spill::dropped();
+        messages::dropped();
+        if self.should_log() {
+            eprintln!(
+                "WARNING: Message discarded (backpressure_policy=Spill): {}. {} messages dropped so far.",
+                error, dropped
+            );
+        }
+    }
+
+    /// Rate-limits warnings to one every `log_interval`
+    fn should_log(&self) -> bool {
+        let mut last_log = self.last_log_time.lock().unwrap();
+        let now = Instant::now();
+        if now.duration_since(*last_log) >= self.log_interval {
+            *last_log = now;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Current backpressure policy
+    pub fn backpressure_policy(&self) -> BackpressurePolicy {
+        self.backpressure_policy.read().unwrap().clone()
+    }
+
+    /// Replace the backpressure policy of this sender and all its clones
+    pub fn set_backpressure_policy(&self, backpressure_policy: BackpressurePolicy) {
+        *self.backpressure_policy.write().unwrap() = backpressure_policy;
+    }
+
     /// Get the number of backpressure events detected
     pub fn backpressure_events(&self) -> u64 {
         self.backpressure_events.load(Ordering::Relaxed)
     }
 
-    /// Get the number of dropped messages (only meaningful when policy is Discard)
+    /// Get the number of dropped messages (only meaningful with a policy that drops messages)
     pub fn dropped_messages(&self) -> u64 {
         self.dropped_messages.load(Ordering::Relaxed)
     }
@@ -152,11 +308,39 @@ impl Clone for BackpressureAwareSender {
     fn clone(&self) -> Self {
         Self {
             sender: self.sender.clone(),
-            backpressure_policy: self.backpressure_policy.clone(),
+            backpressure_policy: Arc::clone(&self.backpressure_policy),
             backpressure_events: Arc::clone(&self.backpressure_events),
             dropped_messages: Arc::clone(&self.dropped_messages),
+            sampled_messages: Arc::clone(&self.sampled_messages),
+            spill: self.spill.clone(),
+            queue: self.queue.clone(),
             last_log_time: Arc::clone(&self.last_log_time),
             log_interval: self.log_interval,
         }
     }
 }
+
+/// Mark the messages of a command as sent now, for the time they wait in the queue
+fn mark_enqueued(command: &mut FileWriterCommand) {
+    let now = Instant::now();
+    match command {
+        FileWriterCommand::Write(message) | FileWriterCommand::WriteDebug(_, message, _) => {
+            message.enqueued_at = Some(now)
+        }
+        FileWriterCommand::WriteBatch(messages) => {
+            for message in messages {
+                message.enqueued_at = Some(now);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Received messages of a command that can be spilled
+fn received_messages(command: &FileWriterCommand) -> Option<&[Message]> {
+    match command {
+        FileWriterCommand::Write(message) => Some(std::slice::from_ref(message)),
+        FileWriterCommand::WriteBatch(messages) => Some(messages),
+        _ => None,
+    }
+}
@@ -0,0 +1,138 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::write::GzEncoder;
+use log::info;
+
+use crate::settings::Compression;
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Id of the latest file claimed for compression, making every claimed name unique,
+/// also across restarts
+static CLAIMED: AtomicU64 = AtomicU64::new(0);
+
+/// File extensions produced by the supported compression formats
+pub const COMPRESSED_EXTENSIONS: [&str; 2] = ["gz", "zst"];
+
+/// Extension appended to rotated files for the given compression, if any
+pub fn extension(compression: &Compression) -> Option<&'static str> {
+    match compression {
+        Compression::None => None,
+        Compression::Gzip => Some("gz"),
+        Compression::Zstd => Some("zst"),
+    }
+}
+
+/// Remove a compression extension, so `log.3.gz`, `log.3` and `.log.3.7.compressing`
+/// (see `claimed_path`) refer to the same rotation slot
+pub fn strip_extension(path: &Path) -> PathBuf {
+    let claimed_slot = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.strip_prefix('.')?.strip_suffix(".compressing"))
+        .and_then(|name| name.rsplit_once('.'))
+        .map(|(slot, _)| path.with_file_name(slot));
+    if let Some(slot) = claimed_slot {
+        return slot;
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) if COMPRESSED_EXTENSIONS.contains(&ext) => path.with_extension(""),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Path of `path` with the compression extension appended (`log.3` -> `log.3.gz`)
+pub fn compressed_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Unique hidden name a rotated file is moved to while it's compressed
+/// (`log.3` -> `.log.3.7.compressing`), which frees its rotation slot for the next rotation
+pub fn claimed_path(path: &Path) -> io::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::other(format!("invalid file name: {:?}", path)))?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+    let last = CLAIMED
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap_or_default();
+    let id = now.max(last + 1);
+    Ok(path.with_file_name(format!(".{}.{}.compressing", file_name, id)))
+}
+
+/// Compress a rotated file in place, returning the path of the compressed file.
+/// Blocking: run it on the blocking thread pool.
+pub fn compress_file(path: &Path, compression: &Compression) -> io::Result<PathBuf> {
+    if extension(compression).is_none() {
+        return Ok(path.to_path_buf());
+    }
+    let claimed = claimed_path(path)?;
+    fs::rename(path, &claimed)?;
+    compress_claimed(&claimed, path, compression)
+}
+
+/// Compress a rotated file moved to `claimed` into the compressed file of its rotation
+/// slot `slot`, returning the path of the compressed file. Only `claimed` is removed:
+/// the slot may already hold the file of a later rotation. Blocking: run it on the
+/// blocking thread pool.
+///
+/// The output is written under a hidden temporary name and renamed once complete,
+/// so a partially compressed file is never taken into account for retention.
+pub fn compress_claimed(
+    claimed: &Path,
+    slot: &Path,
+    compression: &Compression,
+) -> io::Result<PathBuf> {
+    let extension = match extension(compression) {
+        Some(extension) => extension,
+        None => return Ok(claimed.to_path_buf()),
+    };
+    let target = compressed_path(slot, extension);
+    let partial = compressed_path(claimed, &format!("{}.partial", extension));
+
+    let result = write_compressed(claimed, &partial, compression);
+    if let Err(e) = result {
+        let _ = fs::remove_file(&partial);
+        // Back into its slot, unless a later rotation took it already
+        if fs::hard_link(claimed, slot).is_ok() {
+            let _ = fs::remove_file(claimed);
+        }
+        return Err(e);
+    }
+    fs::rename(&partial, &target)?;
+    fs::remove_file(claimed)?;
+    info!("Compressed {:?} into {:?}", slot, target);
+    Ok(target)
+}
+
+fn write_compressed(source: &Path, target: &Path, compression: &Compression) -> io::Result<()> {
+    let mut reader = BufReader::new(File::open(source)?);
+    let writer = BufWriter::new(File::create(target)?);
+    let mut writer = match compression {
+        Compression::Gzip => {
+            let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?
+        }
+        Compression::Zstd => {
+            let mut encoder = zstd::stream::Encoder::new(writer, ZSTD_LEVEL)?;
+            io::copy(&mut reader, &mut encoder)?;
+            encoder.finish()?
+        }
+        Compression::None => writer,
+    };
+    writer.flush()?;
+    writer.get_ref().sync_all()
+}
@@ -1,27 +1,37 @@
 use chrono::prelude::*;
+use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::time::{sleep, Duration as TokioDuration};
+use tokio::time::{sleep, Duration as TokioDuration, Instant};
 
 use regex::Regex;
 
 use glob::glob;
 
 use crate::error::RotationError;
+use crate::time;
+use crate::writer::compression;
 use crate::writer::file_writer::FileWriterCommand;
 use crate::writer::metrics;
 use crate::writer::rotation_policy::RotationPolicy;
 use log::{error, info};
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{broadcast, mpsc, watch, Notify};
+
+/// Rotation settings that can be replaced while FileRotation is running
+pub struct RotationSettings {
+    pub max_files: i32,
+    pub policy: Box<dyn RotationPolicy>,
+}
 
 pub struct FileRotation {
     file_dir_path: PathBuf,
     file_path: PathBuf,
     file_name: String,
-    max_files: i32,
-    rotation_policy: Box<dyn RotationPolicy>,
+    settings: watch::Sender<RotationSettings>,
     tx_file_writer: mpsc::Sender<FileWriterCommand>,
+    size_trigger: Arc<Notify>,
 }
 
 impl FileRotation {
@@ -37,9 +47,12 @@ impl FileRotation {
             file_dir_path,
             file_path,
             file_name,
-            max_files,
-            rotation_policy,
+            settings: watch::Sender::new(RotationSettings {
+                max_files,
+                policy: rotation_policy,
+            }),
             tx_file_writer,
+            size_trigger: Arc::new(Notify::new()),
         }
     }
 
@@ -48,53 +61,86 @@ impl FileRotation {
         mut shutdown_rx: broadcast::Receiver<()>,
     ) -> Result<(), RotationError> {
         let mut last_rotation = Local::now(); // FIXME: get modified of the current file
+        let mut settings_rx = self.settings.subscribe();
         loop {
             info!("loop rotate...");
-            let time_for_rotate = self.rotation_policy.next_rotation(last_rotation);
+            let time_for_rotate = settings_rx
+                .borrow_and_update()
+                .policy
+                .next_rotation(last_rotation);
             let now = Local::now();
-            if time_for_rotate.gt(&now) {
-                let dur_to_rotate = time_for_rotate.signed_duration_since(now).to_std().unwrap();
-                info!("Sleep and wait {:?} for the time to rotate", dur_to_rotate);
-
-                // Convert std::time::Duration to tokio::time::Duration preserving full precision
-                let tokio_dur = TokioDuration::from_nanos(dur_to_rotate.as_nanos() as u64);
-                tokio::select! {
-                    _ = sleep(tokio_dur) => {
-                        // Sleep completed, continue to rotation check
-                    }
-                    _ = shutdown_rx.recv() => {
-                        info!("FileRotation received shutdown signal");
-                        break;
-                    }
+            let by_size = match time_for_rotate {
+                Some(time) if !time.gt(&now) => {
+                    info!("it's the time to rotate: {}", &now);
+                    false
                 }
-            } else {
-                info!("it's the time to rotate: {}", &now);
-                match self.request_rotate().await {
-                    Err(err) => {
-                        error!("Failed trying to rename the file. Reason: {}", err);
-                        metrics::rotation::error();
-                        tokio::select! {
-                            _ = sleep(TokioDuration::from_secs(1)) => {
-                                // Sleep completed, continue loop
-                            }
-                            _ = shutdown_rx.recv() => {
-                                info!("FileRotation received shutdown signal");
-                                break;
-                            }
+                _ => {
+                    let deadline = time_for_rotate.map(|time| {
+                        let dur_to_rotate = time.signed_duration_since(now).to_std().unwrap();
+                        info!("Sleep and wait {:?} for the time to rotate", dur_to_rotate);
+                        Instant::now() + dur_to_rotate
+                    });
+                    tokio::select! {
+                        _ = time::sleep_until(deadline) => {
+                            // Sleep completed, continue to rotation check
+                            continue;
+                        }
+                        _ = self.size_trigger.notified() => {
+                            info!("File reached its maximum size, it's the time to rotate");
+                            true
                         }
+                        _ = settings_rx.changed() => {
+                            info!("Rotation settings changed, recomputing the time to rotate");
+                            continue;
+                        }
+                        _ = shutdown_rx.recv() => {
+                            info!("FileRotation received shutdown signal");
+                            break;
+                        }
+                    }
+                }
+            };
+
+            match self.request_rotate().await {
+                Err(err) => {
+                    error!("Failed trying to rename the file. Reason: {}", err);
+                    metrics::rotation::error();
+                    if by_size {
+                        // The writer only signals once per file, so keep the request pending
+                        self.size_trigger.notify_one();
                     }
-                    Ok(new_path) => {
-                        info!("File rename requested. It will be saved as {:?}", new_path);
-                        metrics::rotation::event();
-                        last_rotation = now;
+                    tokio::select! {
+                        _ = sleep(TokioDuration::from_secs(1)) => {
+                            // Sleep completed, continue loop
+                        }
+                        _ = shutdown_rx.recv() => {
+                            info!("FileRotation received shutdown signal");
+                            break;
+                        }
                     }
                 }
+                Ok(new_path) => {
+                    info!("File rename requested. It will be saved as {:?}", new_path);
+                    metrics::rotation::event();
+                    last_rotation = now;
+                }
             }
         }
         info!("FileRotation shutting down gracefully");
         Ok(())
     }
 
+    /// Handle the FileWriter uses to request a rotation once the active file reaches
+    /// the policy's `max_size`
+    pub fn size_trigger(&self) -> Arc<Notify> {
+        self.size_trigger.clone()
+    }
+
+    /// Handle to replace the rotation policy and retention count of a running FileRotation
+    pub fn settings_handle(&self) -> watch::Sender<RotationSettings> {
+        self.settings.clone()
+    }
+
     pub fn start_async(
         self,
         shutdown_rx: broadcast::Receiver<()>,
@@ -106,13 +152,28 @@ impl FileRotation {
     /// Public method for testing only
     pub async fn request_rotate(&self) -> Result<PathBuf, RotationError> {
         let files = Self::search_files(self.file_path.clone()).await?;
+        // A slot may hold its file being compressed and the compressed one of an earlier rotation
+        let slots: HashSet<PathBuf> = files
+            .iter()
+            .map(|f| compression::strip_extension(f))
+            .collect();
 
-        let new_path = if files.len() >= self.max_files as usize {
+        let max_files = self.settings.borrow().max_files;
+        let new_path = if slots.len() >= max_files as usize {
             self.oldest_file(&files).await?
         } else {
             self.next_path(&files)?
         };
 
+        // The slot may still hold a compressed file from a previous rotation
+        for extension in compression::COMPRESSED_EXTENSIONS {
+            let stale = compression::compressed_path(&new_path, extension);
+            if tokio::fs::try_exists(&stale).await.unwrap_or(false) {
+                info!("Removing {:?} to reuse its rotation slot", stale);
+                tokio::fs::remove_file(&stale).await?;
+            }
+        }
+
         // Now fully async - can use .await directly
         self.tx_file_writer
             .send(FileWriterCommand::Rename(new_path.clone()))
@@ -124,6 +185,8 @@ impl FileRotation {
         Ok(new_path)
     }
 
+    /// Rotated files of `path`, compressed (`log.N.gz`, `log.N.zst`), being compressed
+    /// (`.log.N.<id>.compressing`) or not (`log.N`).
     /// Public method for testing only
     pub async fn search_files(path: PathBuf) -> Result<Vec<PathBuf>, RotationError> {
         let files_query = path.to_str().ok_or(RotationError::OtherError(format!(
@@ -131,13 +194,24 @@ impl FileRotation {
             &path
         )))?;
         let files_query = format!("{}.*", files_query);
+        let file_name =
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .ok_or(RotationError::OtherError(format!(
+                    "Impossible get file name from {:?}",
+                    &path
+                )))?;
+        let claimed_query = path.with_file_name(format!(".{}.*.compressing", file_name));
+        let claimed_query = claimed_query.to_string_lossy().into_owned();
 
         // glob is a blocking library, so we use spawn_blocking to run it on a blocking thread pool
         let files_query_clone = files_query.clone();
         let files = tokio::task::spawn_blocking(move || {
             let mut files: Vec<PathBuf> = vec![];
-            for result in glob(&files_query_clone)? {
-                files.push(result?);
+            for query in [files_query_clone, claimed_query] {
+                for result in glob(&query)? {
+                    files.push(result?);
+                }
             }
             Ok::<Vec<PathBuf>, RotationError>(files)
         })
@@ -167,7 +241,10 @@ impl FileRotation {
             }
         }
 
-        let oldest = oldest_opt.map(|(path, _)| path).unwrap_or(default_file);
+        // Rotate into the uncompressed name; compression adds the extension back afterwards
+        let oldest = oldest_opt
+            .map(|(path, _)| compression::strip_extension(&path))
+            .unwrap_or(default_file);
 
         // If the file doesn't exist (empty list case), return the default path without canonicalizing
         if !tokio::fs::try_exists(&oldest).await.unwrap_or(false) {
@@ -186,9 +263,10 @@ impl FileRotation {
             files
         );
         let re =
-            Regex::new(r".*(\d+)$").map_err(|e| RotationError::RegexError(format!("{}", e)))?;
+            Regex::new(r"\.(\d+)$").map_err(|e| RotationError::RegexError(format!("{}", e)))?;
         let mut next_id = 0;
         for file in files.iter() {
+            let file = compression::strip_extension(file);
             let filename_x = file.file_name().and_then(|fname| fname.to_str()).ok_or(
                 RotationError::InvalidFile(format!("invalid file: {:?}", file)),
             )?;
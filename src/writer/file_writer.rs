@@ -1,18 +1,31 @@
+use bytes::Bytes;
 use chrono::prelude::*;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::fs::{File, OpenOptions};
 use tokio::io::AsyncWriteExt;
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{broadcast, mpsc, watch, Notify};
+use tokio::time::Instant;
 
 use crate::error::FileWriterError;
+use crate::listener::syslog::SyslogMessage;
 use crate::metrics::messages;
-use crate::settings::FileWriterConfig;
-use crate::settings::RotationPolicyType;
-use crate::writer::file_rotation::FileRotation;
+use crate::settings::{Durability, RotationPolicyType};
+use crate::settings::{FileWriterConfig, ProtocolType};
+use crate::time;
+use crate::writer::compression;
+use crate::writer::file_rotation::{FileRotation, RotationSettings};
+use crate::writer::formatter;
 use crate::writer::metrics;
-use crate::writer::rotation_policy::{RotationByDay, RotationByDuration, RotationPolicy};
-use log::{debug, info};
+use crate::writer::prefix::PrefixTemplate;
+use crate::writer::queue::CommandQueue;
+use crate::writer::rotation_policy::{
+    RotationByDay, RotationByDuration, RotationBySize, RotationByTimeOrSize, RotationPolicy,
+};
+use crate::writer::spill::{self, SpillQueue};
+use log::{debug, error, info, warn};
 
 pub struct FileWriter {
     file_dir_path: PathBuf,
@@ -20,8 +33,42 @@ pub struct FileWriter {
     file_name: String,
     file: File,
     pub tx: mpsc::Sender<FileWriterCommand>,
-    rx: mpsc::Receiver<FileWriterCommand>,
+    rx: CommandQueue,
     file_config: FileWriterConfig,
+    prefix: Option<PrefixTemplate>,
+    rotation: Option<RotationControl>,
+    size_rotation: Option<SizeRotation>,
+    /// Lines waiting to be written to the file in a single batch
+    buffer: Vec<u8>,
+    buffered_messages: usize,
+    /// When the buffered messages were sent to the writer, for the queue wait time
+    buffered_enqueued: Vec<std::time::Instant>,
+    /// Name of the writer in the settings, which labels its queue metrics
+    name: Option<Arc<str>>,
+    /// When the buffer must be written at the latest, if `flush_interval_ms` is set
+    flush_deadline: Option<Instant>,
+    /// When written data must be synced to disk at the latest, with the Interval durability
+    sync_deadline: Option<Instant>,
+    drain_timeout: Duration,
+    spill: Arc<SpillQueue>,
+    /// Latest compression of a rotated file, the next one waits for it to finish
+    compression: Option<tokio::task::JoinHandle<()>>,
+}
+
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Handles on the running FileRotation task
+struct RotationControl {
+    settings: watch::Sender<RotationSettings>,
+    size_trigger: Arc<Notify>,
+}
+
+/// Tracks the size of the active file for policies that rotate by size
+struct SizeRotation {
+    max_size: u64,
+    bytes_written: u64,
+    requested: bool,
+    trigger: Arc<Notify>,
 }
 
 impl FileWriter {
@@ -32,9 +79,18 @@ impl FileWriter {
         let file_dir_path = file_config.filedir.clone();
         let mut file_path = file_dir_path.clone();
         file_path.push(file_config.filename.clone());
-        let file = Self::open_file(&file_path, file_config.formatting.startingmsg, true).await?;
+        let prefix = Self::prefix_template(&file_config)?;
+        let file = Self::open_file(&file_path, file_config.formatting.starting_msg(), true).await?;
+        let spill_path = spill::spill_path(&file_path);
+        let spill = SpillQueue::open(spill_path.clone(), file_config.spill_max_size)
+            .await
+            .map_err(|e| FileWriterError::SpillError {
+                path: spill_path,
+                source: e,
+            })?;
 
         let (tx, rx) = mpsc::channel(buffer_bound);
+        let buffer = Vec::with_capacity(file_config.buffer.size);
 
         Ok(FileWriter {
             file_dir_path,
@@ -42,23 +98,64 @@ impl FileWriter {
             file_name: file_config.filename.clone(),
             file,
             tx,
-            rx,
+            rx: CommandQueue::new(rx),
             file_config,
+            prefix,
+            rotation: None,
+            size_rotation: None,
+            buffer,
+            buffered_messages: 0,
+            buffered_enqueued: Vec::new(),
+            name: None,
+            flush_deadline: None,
+            sync_deadline: None,
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            spill: Arc::new(spill),
+            compression: None,
         })
     }
 
+    /// On-disk queue the BackpressureAwareSender spills to with the Spill policy
+    pub fn spill_queue(&self) -> Arc<SpillQueue> {
+        self.spill.clone()
+    }
+
+    /// Queue of the commands sent to this writer, which the DropOldest backpressure
+    /// policy evicts messages from
+    pub fn command_queue(&self) -> CommandQueue {
+        self.rx.clone()
+    }
+
+    /// Name the writer, to export the metrics of its queue. Call it before
+    /// `command_queue`, whose clones keep the name they had.
+    pub fn set_name(&mut self, name: &str) {
+        let name: Arc<str> = name.into();
+        self.rx.set_writer(name.clone());
+        self.name = Some(name);
+    }
+
+    /// Longest time spent on shutdown writing the commands still queued
+    pub fn set_drain_timeout(&mut self, drain_timeout: Duration) {
+        self.drain_timeout = drain_timeout;
+    }
+
     pub async fn start(
         &mut self,
         shutdown_rx: broadcast::Receiver<()>,
         rotation_shutdown_rx: broadcast::Receiver<()>,
     ) -> Result<(), FileWriterError> {
         info!("File writer starting");
-        let rotation_policy: Box<dyn RotationPolicy> = match self.file_config.rotation.policy {
-            RotationPolicyType::ByDuration => Box::new(RotationByDuration::new(
-                Duration::from_secs(self.file_config.rotation.duration.unwrap()),
-            )),
-            RotationPolicyType::ByDay => Box::new(RotationByDay::new()),
+        let mut shutdown_rx = shutdown_rx;
+        let Some(rotation_policy) = Self::rotation_policy(&self.file_config)? else {
+            info!(
+                "Rotation of {:?} is left to an external tool, reopen the file once it's moved",
+                self.file_path
+            );
+            Self::listen_commands_internal(self, &mut shutdown_rx).await?;
+            info!("File writer shutting down gracefully");
+            return Ok(());
         };
+        let max_size = rotation_policy.max_size();
         let file_rotation = FileRotation::new(
             self.file_dir_path.clone(),
             self.file_path.clone(),
@@ -67,18 +164,35 @@ impl FileWriter {
             rotation_policy,
             self.tx.clone(),
         );
-        let rotation_handle = file_rotation.start_async(rotation_shutdown_rx);
-        let mut shutdown_rx = shutdown_rx;
+        let control = RotationControl {
+            settings: file_rotation.settings_handle(),
+            size_trigger: file_rotation.size_trigger(),
+        };
+        if let Some(max_size) = max_size {
+            self.size_rotation = Some(SizeRotation {
+                max_size,
+                bytes_written: Self::file_size(&self.file_path).await,
+                requested: false,
+                trigger: control.size_trigger.clone(),
+            });
+        }
+        self.rotation = Some(control);
+        let mut rotation_handle = file_rotation.start_async(rotation_shutdown_rx);
 
-        // Run listen_commands and rotation concurrently, wait for shutdown
+        // Run listen_commands and rotation concurrently. Rotation may stop first on
+        // shutdown, the writer keeps going until it has drained its queue.
+        let listen = Self::listen_commands_internal(self, &mut shutdown_rx);
+        tokio::pin!(listen);
         tokio::select! {
-            result = Self::listen_commands_internal(self, &mut shutdown_rx) => {
+            result = &mut listen => {
+                rotation_handle.abort();
                 result?;
             }
-            result = rotation_handle => {
+            result = &mut rotation_handle => {
                 result
                     .map_err(|e| FileWriterError::OtherError(format!("Rotation task join error: {:?}", e)))?
                     .map_err(|e| FileWriterError::OtherError(format!("Rotation error: {}", e)))?;
+                listen.await?;
             }
         }
 
@@ -86,6 +200,96 @@ impl FileWriter {
         Ok(())
     }
 
+    /// Policy for the internal rotation, `None` when rotation is left to an external tool
+    fn rotation_policy(
+        file_config: &FileWriterConfig,
+    ) -> Result<Option<Box<dyn RotationPolicy>>, FileWriterError> {
+        let rotation = &file_config.rotation;
+        rotation.validate().map_err(FileWriterError::OtherError)?;
+        // The settings each policy requires are checked by validate
+        let time_policy: Box<dyn RotationPolicy> = match rotation.policy {
+            RotationPolicyType::ByDuration => Box::new(RotationByDuration::new(
+                Duration::from_secs(rotation.duration.unwrap_or_default()),
+            )),
+            RotationPolicyType::ByDay => Box::new(RotationByDay::new()),
+            RotationPolicyType::BySize => {
+                let max_size = rotation.max_size.unwrap_or_default();
+                return Ok(Some(Box::new(RotationBySize::new(max_size))));
+            }
+            RotationPolicyType::External => return Ok(None),
+        };
+        Ok(Some(match rotation.max_size {
+            Some(max_size) => Box::new(RotationByTimeOrSize::new(time_policy, max_size)),
+            None => time_policy,
+        }))
+    }
+
+    fn prefix_template(
+        file_config: &FileWriterConfig,
+    ) -> Result<Option<PrefixTemplate>, FileWriterError> {
+        file_config
+            .formatting
+            .validate()
+            .map_err(FileWriterError::InvalidPrefix)?;
+        file_config
+            .formatting
+            .prefix
+            .as_ref()
+            .map(|template| {
+                PrefixTemplate::parse(template, file_config.formatting.timezone.clone())
+            })
+            .transpose()
+    }
+
+    /// Check that a configuration can be used by a FileWriter, without opening any file
+    pub fn validate_config(file_config: &FileWriterConfig) -> Result<(), FileWriterError> {
+        Self::rotation_policy(file_config)?;
+        Self::prefix_template(file_config)?;
+        Ok(())
+    }
+
+    /// Apply a reloaded configuration to the running writer. The file location is kept:
+    /// changing `filedir` or `filename` requires a restart.
+    async fn reconfigure(&mut self, file_config: FileWriterConfig) -> Result<(), FileWriterError> {
+        let rotation_policy = Self::rotation_policy(&file_config)?;
+        let prefix = Self::prefix_template(&file_config)?;
+        if let Some(control) = &self.rotation {
+            let Some(rotation_policy) = rotation_policy else {
+                return Err(FileWriterError::OtherError(
+                    "switching to the External rotation policy requires a restart".to_string(),
+                ));
+            };
+            self.size_rotation = match rotation_policy.max_size() {
+                Some(max_size) => {
+                    let (bytes_written, requested) = match &self.size_rotation {
+                        Some(current) => (current.bytes_written, current.requested),
+                        None => (Self::file_size(&self.file_path).await, false),
+                    };
+                    Some(SizeRotation {
+                        max_size,
+                        bytes_written,
+                        requested,
+                        trigger: control.size_trigger.clone(),
+                    })
+                }
+                None => None,
+            };
+            control.settings.send_replace(RotationSettings {
+                max_files: file_config.rotation.count,
+                policy: rotation_policy,
+            });
+        }
+        self.prefix = prefix;
+        self.spill.set_max_size(file_config.spill_max_size);
+        self.file_config = FileWriterConfig {
+            filedir: self.file_config.filedir.clone(),
+            filename: self.file_config.filename.clone(),
+            ..file_config
+        };
+        info!("FileWriter for {:?} reconfigured", self.file_path);
+        Ok(())
+    }
+
     pub(crate) async fn listen_commands_internal(
         &mut self,
         shutdown_rx: &mut broadcast::Receiver<()>,
@@ -95,37 +299,34 @@ impl FileWriter {
             tokio::select! {
                 command = self.rx.recv() => {
                     match command {
-                        Some(mut cmd) => {
-                            // Note: tokio::sync::mpsc::Receiver doesn't expose len() for queue depth tracking
-                            // To track queue depth, we would need a wrapper that counts sends/receives
-                            debug!("Command received: {:?}", cmd);
-                            match cmd {
-                                FileWriterCommand::WriteDebug(id, value, i) => {
-                                    count += 1;
-                                    info!(
-                                        "WriteDebug - {} - Count in FileWriter: {} - In Server: {}",
-                                        id, count, i
-                                    );
-                                    self.write(value.as_slice()).await?
-                                }
-                                FileWriterCommand::Write(ref value)
-                                    if value.last().map(|x| x.eq(&b'\n')).unwrap_or(false) =>
-                                {
-                                    self.write(value).await?
+                        Some(cmd) => {
+                            self.handle_command(cmd, &mut count).await?;
+                            // Batch whatever else is already queued into the same write
+                            while self.buffer.len() < self.file_config.buffer.size {
+                                match self.rx.try_recv() {
+                                    Ok(cmd) => self.handle_command(cmd, &mut count).await?,
+                                    Err(_) => break,
                                 }
-                                FileWriterCommand::Write(ref mut value) => {
-                                    value.push(b'\n');
-                                    self.write(value.as_slice()).await?
-                                }
-                                FileWriterCommand::Rename(new_path) => Self::rotate_internal(self, new_path).await?,
                             }
+                            self.flush_if_due().await?;
                         }
                         None => {
                             // Channel closed - return error immediately
+                            self.flush().await?;
                             return Err(FileWriterError::ChannelClosed);
                         }
                     }
                 }
+                // Spilled messages are written once the writer has caught up with its queue
+                _ = self.spill.ready(), if self.rx.is_empty() => {
+                    self.replay_spill(&mut count).await?;
+                }
+                _ = time::sleep_until(self.flush_deadline), if self.flush_deadline.is_some() => {
+                    self.flush().await?;
+                }
+                _ = time::sleep_until(self.sync_deadline), if self.sync_deadline.is_some() => {
+                    self.sync().await?;
+                }
                 result = shutdown_rx.recv() => {
                     match result {
                         Ok(_) => {
@@ -141,9 +342,118 @@ impl FileWriter {
                 }
             }
         }
+        self.drain().await
+    }
+
+    /// Write every command still queued, within the drain timeout, then close the file
+    /// with its ending message and sync it to disk
+    async fn drain(&mut self) -> Result<(), FileWriterError> {
+        let deadline = Instant::now() + self.drain_timeout;
+        let mut count = 0;
+        let mut drained = 0;
+        while let Ok(cmd) = self.rx.try_recv() {
+            self.handle_command(cmd, &mut count).await?;
+            drained += 1;
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+        if self.spill.has_pending() {
+            info!(
+                "Spilled messages for {:?} are kept in {:?} and written on the next start",
+                self.file_path,
+                self.spill.path()
+            );
+        }
+        let mut lost = 0;
+        while let Ok(cmd) = self.rx.try_recv() {
+            lost += cmd.message_count();
+        }
+        if lost > 0 {
+            warn!(
+                "Drain timeout of {:?} reached, {} queued message(s) for {:?} are lost",
+                self.drain_timeout, lost, self.file_path
+            );
+            messages::lost_at_shutdown(lost);
+        }
+        info!(
+            "Drained {} queued command(s) for {:?}",
+            drained, self.file_path
+        );
+        self.flush().await?;
+        if self.file_config.formatting.ending_msg() {
+            let ending_msg = format!(
+                "Ending {} at {}\n",
+                self.file_path.to_string_lossy(),
+                Local::now().to_rfc2822()
+            );
+            self.write(ending_msg.as_bytes()).await?;
+        }
+        self.sync().await
+    }
+
+    /// Write the oldest spilled messages, a buffer's worth at a time
+    async fn replay_spill(&mut self, count: &mut u64) -> Result<(), FileWriterError> {
+        let messages = self
+            .spill
+            .pop_batch(self.file_config.buffer.size)
+            .await
+            .map_err(|e| FileWriterError::SpillError {
+                path: self.spill.path().to_path_buf(),
+                source: e,
+            })?;
+        debug!("Replaying {} spilled message(s)", messages.len());
+        for message in messages {
+            self.handle_command(FileWriterCommand::Write(message), count)
+                .await?;
+        }
+        self.flush_if_due().await
+    }
+
+    /// Write the buffer if it's full, or right away when no flush interval is set
+    async fn flush_if_due(&mut self) -> Result<(), FileWriterError> {
+        if self.buffer.len() >= self.file_config.buffer.size
+            || self.file_config.buffer.flush_interval_ms == 0
+        {
+            self.flush().await?;
+        }
         Ok(())
     }
 
+    async fn handle_command(
+        &mut self,
+        cmd: FileWriterCommand,
+        count: &mut u64,
+    ) -> Result<(), FileWriterError> {
+        debug!("Command received: {:?}", cmd);
+        match cmd {
+            FileWriterCommand::WriteDebug(id, value, i) => {
+                *count += 1;
+                info!(
+                    "WriteDebug - {} - Count in FileWriter: {} - In Server: {}",
+                    id, count, i
+                );
+                self.write(&value.payload).await
+            }
+            FileWriterCommand::Write(ref message) => self.write_message(message).await,
+            FileWriterCommand::WriteBatch(ref messages) => {
+                for message in messages {
+                    self.write_message(message).await?;
+                }
+                Ok(())
+            }
+            FileWriterCommand::Rename(new_path) => Self::rotate_internal(self, new_path).await,
+            FileWriterCommand::Reopen => self.reopen().await,
+            FileWriterCommand::Reconfigure(file_config) => {
+                // A rejected configuration leaves the current one in place
+                if let Err(e) = self.reconfigure(*file_config).await {
+                    error!("Failed to apply reloaded configuration: {}", e);
+                }
+                Ok(())
+            }
+        }
+    }
+
     /// Public method for testing only
     pub async fn listen_commands(
         &mut self,
@@ -152,14 +462,127 @@ impl FileWriter {
         Self::listen_commands_internal(self, shutdown_rx).await
     }
 
+    async fn write_message(&mut self, message: &Message) -> Result<(), FileWriterError> {
+        self.buffer_message(message);
+        if self.file_config.durability.mode == Durability::Message {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Add a received message to the buffer as a single line in the configured output
+    /// format, after the configured prefix
+    fn buffer_message(&mut self, message: &Message) {
+        let start = self.buffer.len();
+        if let Some(prefix) = &self.prefix {
+            prefix.render(message, &mut self.buffer);
+        }
+        let line = formatter::render(&self.file_config.formatting.output, message);
+        self.buffer.extend_from_slice(&line);
+        self.buffered_enqueued.extend(message.enqueued_at);
+        self.buffered(self.buffer.len() - start);
+    }
+
+    /// Write `buf` to the file right away, after anything already buffered
     pub async fn write(&mut self, buf: &[u8]) -> Result<(), FileWriterError> {
+        self.buffer.extend_from_slice(buf);
+        self.buffered(buf.len());
+        self.flush().await
+    }
+
+    fn buffered(&mut self, bytes: usize) {
+        self.buffered_messages += 1;
+        self.track_size(bytes);
+        if self.flush_deadline.is_none() && self.file_config.buffer.flush_interval_ms > 0 {
+            self.flush_deadline = Some(
+                Instant::now() + Duration::from_millis(self.file_config.buffer.flush_interval_ms),
+            );
+        }
+    }
+
+    /// Write every buffered line to the file at once
+    pub async fn flush(&mut self) -> Result<(), FileWriterError> {
+        self.flush_deadline = None;
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
         let timer = metrics::file_write::WriteTimer::start();
-        let result = Self::write_with(&mut self.file, buf).await;
+        let result = Self::write_with(&mut self.file, &self.buffer).await;
         timer.finish();
+        metrics::batch::written(self.buffered_messages, self.buffer.len());
         if result.is_ok() {
-            messages::written();
+            messages::written_batch(self.buffered_messages);
+        }
+        // A failed batch is not retried, it may have been partially written
+        self.buffer.clear();
+        self.buffered_messages = 0;
+        let enqueued = std::mem::take(&mut self.buffered_enqueued);
+        result?;
+        let synced = match self.file_config.durability.mode {
+            Durability::None => Ok(()),
+            Durability::Interval => {
+                if self.sync_deadline.is_none() {
+                    self.sync_deadline = Some(
+                        Instant::now()
+                            + Duration::from_millis(self.file_config.durability.interval_ms),
+                    );
+                }
+                Ok(())
+            }
+            Durability::Batch | Durability::Message => self.sync().await,
+        };
+        // Written, and synced unless syncs are left for later
+        if let Some(name) = &self.name {
+            for enqueued_at in enqueued {
+                metrics::queue::waited(name, enqueued_at.elapsed());
+            }
+        }
+        synced
+    }
+
+    /// Sync the data written to the file to disk
+    async fn sync(&mut self) -> Result<(), FileWriterError> {
+        self.sync_deadline = None;
+        let timer = std::time::Instant::now();
+        let result = self.file.sync_data().await;
+        metrics::fsync::record_latency(timer.elapsed());
+        result.map_err(|e| {
+            metrics::fsync::error();
+            FileWriterError::SyncError {
+                path: self.file_path.clone(),
+                source: e,
+            }
+        })
+    }
+
+    /// Sync the data written since the last sync, if it is still waiting for the interval
+    async fn sync_pending(&mut self) -> Result<(), FileWriterError> {
+        match self.sync_deadline {
+            Some(_) => self.sync().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Ask FileRotation for a rotation once the active file crosses `max_size`
+    fn track_size(&mut self, written: usize) {
+        if let Some(size_rotation) = self.size_rotation.as_mut() {
+            size_rotation.bytes_written += written as u64;
+            if !size_rotation.requested && size_rotation.bytes_written >= size_rotation.max_size {
+                debug!(
+                    "File {:?} reached {} bytes, requesting rotation",
+                    self.file_path, size_rotation.bytes_written
+                );
+                size_rotation.requested = true;
+                size_rotation.trigger.notify_one();
+            }
         }
-        result
+    }
+
+    async fn file_size(path: &PathBuf) -> u64 {
+        tokio::fs::metadata(path)
+            .await
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
     }
 
     async fn write_with(file: &mut File, buf: &[u8]) -> Result<(), FileWriterError> {
@@ -167,6 +590,7 @@ impl FileWriter {
         file.write_all(buf)
             .await
             .map_err(FileWriterError::WriteError)?;
+        file.flush().await.map_err(FileWriterError::WriteError)?;
         Ok(())
     }
 
@@ -208,6 +632,9 @@ impl FileWriter {
         &mut self,
         new_path: PathBuf,
     ) -> Result<(), FileWriterError> {
+        // Everything received before the rotation belongs to the rotated file
+        self.flush().await?;
+        self.sync_pending().await?;
         tokio::fs::rename(self.file_path.clone(), new_path.clone())
             .await
             .map_err(|e| FileWriterError::RenameError {
@@ -222,27 +649,157 @@ impl FileWriter {
             Local::now().to_rfc2822()
         );
         info!("File rename successfully. {}", ending_msg);
-        if self.file_config.formatting.endingmsg {
+        if self.file_config.formatting.ending_msg() {
             self.write(ending_msg.as_bytes()).await?;
         }
         self.file = Self::open_file(
             &self.file_path.clone(),
-            self.file_config.formatting.startingmsg,
+            self.file_config.formatting.starting_msg(),
             false,
         )
         .await?;
+        if let Some(size_rotation) = self.size_rotation.as_mut() {
+            size_rotation.bytes_written = Self::file_size(&self.file_path).await;
+            size_rotation.requested = false;
+        }
+        self.compress_rotated(new_path).await;
         Ok(())
     }
 
+    /// Close the active file and open `file_path` again without renaming anything,
+    /// so writes go to a new file once an external tool has moved the old one away
+    pub async fn reopen(&mut self) -> Result<(), FileWriterError> {
+        self.flush().await?;
+        self.sync_pending().await?;
+        self.file = Self::open_file(&self.file_path.clone(), false, true).await?;
+        if let Some(size_rotation) = self.size_rotation.as_mut() {
+            size_rotation.bytes_written = Self::file_size(&self.file_path).await;
+            size_rotation.requested = false;
+        }
+        metrics::rotation::reopened();
+        info!("File {:?} reopened", self.file_path);
+        Ok(())
+    }
+
+    /// Compress the rotated file on the blocking pool so it never delays writes
+    async fn compress_rotated(&mut self, rotated_path: PathBuf) {
+        let compression = self.file_config.rotation.compression.clone();
+        if compression::extension(&compression).is_none() {
+            return;
+        }
+        // Moved out of its slot right away, a later rotation may reuse the slot while
+        // the file is compressed
+        let claimed = match compression::claimed_path(&rotated_path) {
+            Ok(claimed) => claimed,
+            Err(e) => {
+                error!("Failed to compress rotated file {:?}: {}", rotated_path, e);
+                metrics::rotation::compression_error();
+                return;
+            }
+        };
+        if let Err(e) = tokio::fs::rename(&rotated_path, &claimed).await {
+            error!("Failed to compress rotated file {:?}: {}", rotated_path, e);
+            metrics::rotation::compression_error();
+            return;
+        }
+        // One at a time and in order, so a slot ends up with its latest file
+        let previous = self.compression.take();
+        self.compression = Some(tokio::spawn(async move {
+            if let Some(previous) = previous {
+                let _ = previous.await;
+            }
+            let timer = std::time::Instant::now();
+            let result = tokio::task::spawn_blocking(move || {
+                compression::compress_claimed(&claimed, &rotated_path, &compression)
+                    .map_err(|e| (rotated_path, e))
+            })
+            .await;
+            match result {
+                Ok(Ok(_)) => metrics::rotation::compressed(timer.elapsed()),
+                Ok(Err((rotated_path, e))) => {
+                    error!("Failed to compress rotated file {:?}: {}", rotated_path, e);
+                    metrics::rotation::compression_error();
+                }
+                Err(e) => {
+                    error!("Compression task failed: {}", e);
+                    metrics::rotation::compression_error();
+                }
+            }
+        }));
+    }
+
     /// Public method for testing only
     pub async fn rotate(&mut self, new_path: PathBuf) -> Result<(), FileWriterError> {
         Self::rotate_internal(self, new_path).await
     }
 }
 
+/// A message received by a listener, along with where and when it was received.
+/// The payload shares the buffer it was received in, so clones are cheap.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub payload: Bytes,
+    pub peer: SocketAddr,
+    pub received_at: DateTime<Utc>,
+    /// Local address of the listener that received the message
+    pub listener: SocketAddr,
+    pub protocol: ProtocolType,
+    /// Parsed fields, for messages received by a Syslog listener
+    pub syslog: Option<SyslogMessage>,
+    /// When the message was sent to its FileWriter, for the time it waits in the queue
+    pub enqueued_at: Option<std::time::Instant>,
+}
+
+impl Message {
+    pub fn new(
+        payload: impl Into<Bytes>,
+        peer: SocketAddr,
+        listener: SocketAddr,
+        protocol: ProtocolType,
+    ) -> Self {
+        Message {
+            payload: payload.into(),
+            peer,
+            received_at: Utc::now(),
+            listener,
+            protocol,
+            syslog: None,
+            enqueued_at: None,
+        }
+    }
+}
+
+// The same message, whenever it was queued
+impl PartialEq for Message {
+    fn eq(&self, other: &Self) -> bool {
+        self.payload == other.payload
+            && self.peer == other.peer
+            && self.received_at == other.received_at
+            && self.listener == other.listener
+            && self.protocol == other.protocol
+            && self.syslog == other.syslog
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum FileWriterCommand {
-    Write(Vec<u8>),
+    Write(Message),
+    /// Messages received together, e.g. datagrams read with a single syscall
+    WriteBatch(Vec<Message>),
     Rename(PathBuf),
-    WriteDebug(String, Vec<u8>, i32),
+    /// Reopen the active file, e.g. after an external logrotate moved it
+    Reopen,
+    WriteDebug(String, Message, i32),
+    Reconfigure(Box<FileWriterConfig>),
+}
+
+impl FileWriterCommand {
+    /// Number of received messages the command carries
+    pub fn message_count(&self) -> usize {
+        match self {
+            FileWriterCommand::Write(_) | FileWriterCommand::WriteDebug(..) => 1,
+            FileWriterCommand::WriteBatch(messages) => messages.len(),
+            _ => 0,
+        }
+    }
 }
@@ -0,0 +1,108 @@
+use std::borrow::Cow;
+
+use chrono::prelude::*;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::listener::syslog::SyslogMessage;
+use crate::settings::{OutputFormat, ProtocolType};
+use crate::writer::file_writer::Message;
+
+/// A written line in the Json output format
+#[derive(Serialize)]
+struct JsonLine<'a> {
+    timestamp: String,
+    peer: String,
+    protocol: &'a ProtocolType,
+    listener_port: u16,
+    message: Cow<'a, str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    syslog: Option<JsonSyslog<'a>>,
+}
+
+/// Parsed syslog fields, only present for messages received by a Syslog listener
+#[derive(Serialize)]
+struct JsonSyslog<'a> {
+    facility: u8,
+    severity: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timestamp: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hostname: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    app_name: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proc_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    msg_id: Option<&'a str>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    structured_data: Map<String, Value>,
+    message: &'a str,
+}
+
+impl<'a> JsonSyslog<'a> {
+    fn new(message: &'a SyslogMessage) -> Self {
+        let structured_data = message
+            .structured_data
+            .iter()
+            .map(|element| {
+                let params = element
+                    .params
+                    .iter()
+                    .map(|(name, value)| (name.clone(), Value::String(value.clone())))
+                    .collect::<Map<_, _>>();
+                (element.id.clone(), Value::Object(params))
+            })
+            .collect();
+        JsonSyslog {
+            facility: message.facility,
+            severity: message.severity,
+            version: message.version,
+            timestamp: message.timestamp.as_deref(),
+            hostname: message.hostname.as_deref(),
+            app_name: message.app_name.as_deref(),
+            proc_id: message.proc_id.as_deref(),
+            msg_id: message.msg_id.as_deref(),
+            structured_data,
+            message: &message.message,
+        }
+    }
+}
+
+/// Render a message as one line, terminated by a newline, in the given output format
+pub fn render<'a>(output: &OutputFormat, message: &'a Message) -> Cow<'a, [u8]> {
+    let payload = &message.payload[..];
+    match output {
+        OutputFormat::Plain if payload.last() == Some(&b'\n') => Cow::Borrowed(payload),
+        OutputFormat::Plain => {
+            let mut line = Vec::with_capacity(payload.len() + 1);
+            line.extend_from_slice(payload);
+            line.push(b'\n');
+            Cow::Owned(line)
+        }
+        OutputFormat::Json => Cow::Owned(render_json(message)),
+    }
+}
+
+fn render_json(message: &Message) -> Vec<u8> {
+    let text = match String::from_utf8_lossy(&message.payload) {
+        Cow::Borrowed(text) => Cow::Borrowed(text.trim_end_matches(['\r', '\n'])),
+        Cow::Owned(text) => Cow::Owned(text.trim_end_matches(['\r', '\n']).to_string()),
+    };
+    let line = JsonLine {
+        timestamp: message
+            .received_at
+            .to_rfc3339_opts(SecondsFormat::Micros, true),
+        peer: message.peer.to_string(),
+        protocol: &message.protocol,
+        listener_port: message.listener.port(),
+        message: text,
+        syslog: message.syslog.as_ref().map(JsonSyslog::new),
+    };
+    // Serializing plain structs with string keys can't fail
+    let mut out = serde_json::to_vec(&line).expect("JSON line serialization");
+    out.push(b'\n');
+    out
+}
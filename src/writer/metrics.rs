@@ -1,4 +1,4 @@
-use metrics::{counter, histogram};
+use metrics::{counter, gauge, histogram};
 use std::time::Instant;
 
 /// Track backpressure metrics
@@ -8,6 +8,46 @@ pub mod backpressure {
     pub fn event() {
         counter!("backpressure_events_total", 1);
     }
+
+    /// New messages discarded by the Discard policy
+    pub fn discarded(count: usize) {
+        counter!("backpressure_discarded_total", count as u64);
+    }
+
+    /// Queued messages evicted by the DropOldest policy
+    pub fn dropped_oldest(count: usize) {
+        counter!("backpressure_dropped_oldest_total", count as u64);
+    }
+
+    /// A message kept or discarded by the Sample policy
+    pub fn sampled(kept: bool) {
+        if kept {
+            counter!("backpressure_sampled_kept_total", 1);
+        } else {
+            counter!("backpressure_sampled_out_total", 1);
+        }
+    }
+}
+
+/// Track the channel of commands to each FileWriter
+pub mod queue {
+    use super::*;
+
+    /// Most commands the channel holds, the `buffer_bound`
+    pub fn capacity(writer: &str, capacity: usize) {
+        gauge!("writer_queue_capacity", capacity as f64, "writer" => writer.to_string());
+    }
+
+    /// Commands waiting in the channel
+    pub fn depth(writer: &str, depth: usize) {
+        gauge!("writer_queue_depth", depth as f64, "writer" => writer.to_string());
+    }
+
+    /// Time from when a message was sent to the FileWriter until it was written to
+    /// the file, including the time buffered and, unless deferred, the sync
+    pub fn waited(writer: &str, wait: std::time::Duration) {
+        histogram!("writer_queue_wait_seconds", wait.as_secs_f64(), "writer" => writer.to_string());
+    }
 }
 
 /// Track file write metrics
@@ -37,6 +77,48 @@ pub mod file_write {
     }
 }
 
+/// Track the on-disk queue of the Spill backpressure policy
+pub mod spill {
+    use super::*;
+
+    pub fn spilled(bytes: usize) {
+        counter!("backpressure_spilled_bytes_total", bytes as u64);
+    }
+
+    pub fn replayed(bytes: usize) {
+        counter!("backpressure_replayed_bytes_total", bytes as u64);
+    }
+
+    /// A message dropped because the spill queue was full or couldn't be written
+    pub fn dropped() {
+        counter!("backpressure_spill_dropped_total", 1);
+    }
+}
+
+/// Track syncs of written data to disk
+pub mod fsync {
+    use super::*;
+
+    pub fn record_latency(duration: std::time::Duration) {
+        histogram!("file_fsync_latency_seconds", duration.as_secs_f64());
+    }
+
+    pub fn error() {
+        counter!("file_fsync_errors_total", 1);
+    }
+}
+
+/// Track batched file writes
+pub mod batch {
+    use super::*;
+
+    /// Record the messages and bytes written to the file in one batch
+    pub fn written(messages: usize, bytes: usize) {
+        histogram!("file_write_batch_messages", messages as f64);
+        histogram!("file_write_batch_bytes", bytes as f64);
+    }
+}
+
 /// Track file rotation metrics
 pub mod rotation {
     use super::*;
@@ -48,4 +130,17 @@ pub mod rotation {
     pub fn error() {
         counter!("file_rotation_errors_total", 1);
     }
+
+    pub fn compressed(duration: std::time::Duration) {
+        counter!("file_rotation_compressed_total", 1);
+        histogram!("file_rotation_compression_seconds", duration.as_secs_f64());
+    }
+
+    pub fn compression_error() {
+        counter!("file_rotation_compression_errors_total", 1);
+    }
+
+    pub fn reopened() {
+        counter!("file_reopen_total", 1);
+    }
 }
@@ -1,5 +1,10 @@
 pub mod backpressure;
+pub mod compression;
 pub mod file_rotation;
 pub mod file_writer;
+pub mod formatter;
 pub mod metrics;
+pub mod prefix;
+pub mod queue;
 pub mod rotation_policy;
+pub mod spill;
@@ -0,0 +1,154 @@
+use std::io::Write;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::prelude::*;
+
+use crate::error::FileWriterError;
+use crate::settings::Timezone;
+use crate::writer::file_writer::Message;
+
+const PLACEHOLDERS: &str = "timestamp_rfc3339, timestamp:<strftime format>, peer, peer_ip, \
+                            peer_port, listener, listener_port, protocol";
+
+/// A piece of a compiled prefix template
+#[derive(Debug)]
+enum Segment {
+    Literal(String),
+    TimestampRfc3339,
+    Timestamp(Vec<Item<'static>>),
+    Peer,
+    PeerIp,
+    PeerPort,
+    Listener,
+    ListenerPort,
+    Protocol,
+}
+
+/// Line prefix compiled from a template such as `{timestamp_rfc3339} {peer_ip} `.
+/// Placeholders are written in braces, literal braces are escaped as `{{` and `}}`.
+#[derive(Debug)]
+pub struct PrefixTemplate {
+    segments: Vec<Segment>,
+    timezone: Timezone,
+}
+
+impl PrefixTemplate {
+    pub fn parse(template: &str, timezone: Timezone) -> Result<Self, FileWriterError> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(FileWriterError::InvalidPrefix(format!(
+                                    "unclosed placeholder '{{{}'",
+                                    name
+                                )))
+                            }
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Self::placeholder(&name)?);
+                }
+                '}' => {
+                    return Err(FileWriterError::InvalidPrefix(
+                        "unmatched '}', use '}}' for a literal brace".to_string(),
+                    ))
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(PrefixTemplate { segments, timezone })
+    }
+
+    fn placeholder(name: &str) -> Result<Segment, FileWriterError> {
+        if let Some(format) = name.strip_prefix("timestamp:") {
+            let items = StrftimeItems::new(format).parse_to_owned().map_err(|_| {
+                FileWriterError::InvalidPrefix(format!("invalid timestamp format '{}'", format))
+            })?;
+            return Ok(Segment::Timestamp(items));
+        }
+        Ok(match name {
+            "timestamp_rfc3339" => Segment::TimestampRfc3339,
+            "peer" => Segment::Peer,
+            "peer_ip" => Segment::PeerIp,
+            "peer_port" => Segment::PeerPort,
+            "listener" => Segment::Listener,
+            "listener_port" => Segment::ListenerPort,
+            "protocol" => Segment::Protocol,
+            _ => {
+                return Err(FileWriterError::InvalidPrefix(format!(
+                    "unknown placeholder '{{{}}}', expected one of: {}",
+                    name, PLACEHOLDERS
+                )))
+            }
+        })
+    }
+
+    /// Append the prefix for a message to `out`
+    pub fn render(&self, message: &Message, out: &mut Vec<u8>) {
+        // Writing to a Vec can't fail
+        for segment in &self.segments {
+            let _ = match segment {
+                Segment::Literal(text) => out.write_all(text.as_bytes()),
+                Segment::TimestampRfc3339 => match self.timezone {
+                    Timezone::Utc => out.write_all(
+                        message
+                            .received_at
+                            .to_rfc3339_opts(SecondsFormat::Millis, true)
+                            .as_bytes(),
+                    ),
+                    Timezone::Local => out.write_all(
+                        message
+                            .received_at
+                            .with_timezone(&Local)
+                            .to_rfc3339_opts(SecondsFormat::Millis, false)
+                            .as_bytes(),
+                    ),
+                },
+                Segment::Timestamp(items) => match self.timezone {
+                    Timezone::Utc => {
+                        write!(
+                            out,
+                            "{}",
+                            message.received_at.format_with_items(items.iter())
+                        )
+                    }
+                    Timezone::Local => write!(
+                        out,
+                        "{}",
+                        message
+                            .received_at
+                            .with_timezone(&Local)
+                            .format_with_items(items.iter())
+                    ),
+                },
+                Segment::Peer => write!(out, "{}", message.peer),
+                Segment::PeerIp => write!(out, "{}", message.peer.ip()),
+                Segment::PeerPort => write!(out, "{}", message.peer.port()),
+                Segment::Listener => write!(out, "{}", message.listener),
+                Segment::ListenerPort => write!(out, "{}", message.listener.port()),
+                Segment::Protocol => write!(out, "{:?}", message.protocol),
+            };
+        }
+    }
+}
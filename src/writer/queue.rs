@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TryRecvError;
+
+use crate::writer::file_writer::FileWriterCommand;
+use crate::writer::metrics;
+
+/// Receiving end of a FileWriter's channel. The FileWriter reads its commands from it
+/// and, with the DropOldest backpressure policy, senders evict the oldest queued
+/// message from it to make room for a new one.
+///
+/// Other commands found in front of the evicted message are set aside, in order, and
+/// received before anything still in the channel, so that none of them moves behind
+/// messages sent after it. They take no room in the channel meanwhile.
+///
+/// Once named after its writer, exports the depth and capacity of the channel.
+#[derive(Clone)]
+pub struct CommandQueue {
+    inner: Arc<Mutex<Inner>>,
+    writer: Option<Arc<str>>,
+}
+
+struct Inner {
+    rx: mpsc::Receiver<FileWriterCommand>,
+    /// Commands taken out of the channel by an eviction, older than those in it
+    set_aside: VecDeque<FileWriterCommand>,
+}
+
+impl Inner {
+    fn len(&self) -> usize {
+        self.set_aside.len() + self.rx.len()
+    }
+}
+
+impl CommandQueue {
+    pub fn new(rx: mpsc::Receiver<FileWriterCommand>) -> Self {
+        CommandQueue {
+            inner: Arc::new(Mutex::new(Inner {
+                rx,
+                set_aside: VecDeque::new(),
+            })),
+            writer: None,
+        }
+    }
+
+    /// Label the metrics of the queue with the name of its writer. Clones made
+    /// before keep exporting nothing.
+    pub fn set_writer(&mut self, writer: Arc<str>) {
+        let inner = self.inner.lock().unwrap();
+        metrics::queue::capacity(&writer, inner.rx.max_capacity());
+        metrics::queue::depth(&writer, inner.len());
+        drop(inner);
+        self.writer = Some(writer);
+    }
+
+    /// Receive the next command, `None` once every sender is gone
+    pub async fn recv(&self) -> Option<FileWriterCommand> {
+        // The lock is only held while polling, never across an await
+        let (command, depth) = poll_fn(|cx| {
+            let mut inner = self.inner.lock().unwrap();
+            if let Some(command) = inner.set_aside.pop_front() {
+                return Poll::Ready(Some((command, inner.len())));
+            }
+            inner
+                .rx
+                .poll_recv(cx)
+                .map(|command| command.map(|command| (command, inner.len())))
+        })
+        .await?;
+        self.record_depth(depth);
+        Some(command)
+    }
+
+    pub fn try_recv(&self) -> Result<FileWriterCommand, TryRecvError> {
+        let (command, depth) = {
+            let mut inner = self.inner.lock().unwrap();
+            let command = match inner.set_aside.pop_front() {
+                Some(command) => command,
+                None => inner.rx.try_recv()?,
+            };
+            (command, inner.len())
+        };
+        self.record_depth(depth);
+        Ok(command)
+    }
+
+    fn record_depth(&self, depth: usize) {
+        if let Some(writer) = &self.writer {
+            metrics::queue::depth(writer, depth);
+        }
+    }
+
+    /// Record the depth after a send through `tx`, a sender of this channel
+    pub(crate) fn enqueued(&self, tx: &mpsc::Sender<FileWriterCommand>) {
+        // Permits held by sends in progress are counted as well, for a moment
+        self.record_depth(tx.max_capacity() - tx.capacity());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of commands waiting in the queue
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().len()
+    }
+
+    /// Remove the oldest queued message, or batch of messages, setting aside the
+    /// other commands found before it. Never waits, and frees at least one slot of
+    /// the channel unless it is empty. Returns the number of messages removed, 0 when
+    /// no message is queued.
+    pub fn evict_oldest(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let mut evicted = 0;
+        while let Ok(command) = inner.rx.try_recv() {
+            match command {
+                FileWriterCommand::Write(_) | FileWriterCommand::WriteDebug(..) => evicted = 1,
+                FileWriterCommand::WriteBatch(messages) => evicted = messages.len(),
+                command => {
+                    inner.set_aside.push_back(command);
+                    continue;
+                }
+            }
+            break;
+        }
+        self.record_depth(inner.len());
+        evicted
+    }
+}
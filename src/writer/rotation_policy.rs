@@ -2,7 +2,13 @@ use chrono::prelude::*;
 use std::time::Duration;
 
 pub trait RotationPolicy: Sync + Send {
-    fn next_rotation(&self, last_rotation: DateTime<Local>) -> DateTime<Local>;
+    /// Time of the next rotation, or `None` when time never triggers one
+    fn next_rotation(&self, last_rotation: DateTime<Local>) -> Option<DateTime<Local>>;
+
+    /// Size in bytes from which the active file is rotated, if size triggers rotation
+    fn max_size(&self) -> Option<u64> {
+        None
+    }
 }
 
 #[derive(Clone)]
@@ -17,8 +23,8 @@ impl RotationByDuration {
 }
 
 impl RotationPolicy for RotationByDuration {
-    fn next_rotation(&self, last_rotation: DateTime<Local>) -> DateTime<Local> {
-        last_rotation + chrono::Duration::from_std(self.duration).unwrap()
+    fn next_rotation(&self, last_rotation: DateTime<Local>) -> Option<DateTime<Local>> {
+        Some(last_rotation + chrono::Duration::from_std(self.duration).unwrap())
     }
 }
 
@@ -38,9 +44,55 @@ impl Default for RotationByDay {
 }
 
 impl RotationPolicy for RotationByDay {
-    fn next_rotation(&self, last_rotation: DateTime<Local>) -> DateTime<Local> {
+    fn next_rotation(&self, last_rotation: DateTime<Local>) -> Option<DateTime<Local>> {
         let next_day = (last_rotation + chrono::Duration::days(1)).date_naive();
         let midnight = next_day.and_hms_opt(0, 0, 0).unwrap();
-        Local.from_local_datetime(&midnight).single().unwrap()
+        Some(Local.from_local_datetime(&midnight).single().unwrap())
+    }
+}
+
+#[derive(Clone)]
+pub struct RotationBySize {
+    max_size: u64,
+}
+
+impl RotationBySize {
+    pub fn new(max_size: u64) -> Self {
+        RotationBySize { max_size }
+    }
+}
+
+impl RotationPolicy for RotationBySize {
+    fn next_rotation(&self, _last_rotation: DateTime<Local>) -> Option<DateTime<Local>> {
+        None
+    }
+
+    fn max_size(&self) -> Option<u64> {
+        Some(self.max_size)
+    }
+}
+
+/// Rotate on the schedule of a time-based policy, or earlier if the file reaches `max_size`
+pub struct RotationByTimeOrSize {
+    time_policy: Box<dyn RotationPolicy>,
+    max_size: u64,
+}
+
+impl RotationByTimeOrSize {
+    pub fn new(time_policy: Box<dyn RotationPolicy>, max_size: u64) -> Self {
+        RotationByTimeOrSize {
+            time_policy,
+            max_size,
+        }
+    }
+}
+
+impl RotationPolicy for RotationByTimeOrSize {
+    fn next_rotation(&self, last_rotation: DateTime<Local>) -> Option<DateTime<Local>> {
+        self.time_policy.next_rotation(last_rotation)
+    }
+
+    fn max_size(&self) -> Option<u64> {
+        Some(self.max_size)
     }
 }
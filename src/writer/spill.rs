@@ -0,0 +1,264 @@
+use std::io::{self, SeekFrom};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use bytes::Bytes;
+use chrono::prelude::*;
+use log::{info, warn};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::sync::{Mutex, Notify};
+
+use crate::listener::syslog::SyslogMessage;
+use crate::settings::ProtocolType;
+use crate::writer::file_writer::Message;
+use crate::writer::metrics;
+
+/// On-disk queue of the messages that overflowed a writer's channel with the Spill
+/// backpressure policy. Messages are appended to a single segment file and replayed
+/// in order by the FileWriter once it catches up; the segment is truncated whenever
+/// it has been fully replayed. Records left by a previous run are replayed after a restart.
+pub struct SpillQueue {
+    path: PathBuf,
+    max_size: AtomicU64,
+    /// Records appended and not replayed yet
+    pending: AtomicU64,
+    ready: Notify,
+    segment: Mutex<Segment>,
+}
+
+struct Segment {
+    /// Append handle, opened on first use
+    writer: Option<File>,
+    /// Bytes in the segment, replayed or not
+    size: u64,
+    /// Offset of the first record not replayed yet
+    read_offset: u64,
+}
+
+/// Record header: length of the rest of the record
+const LEN_SIZE: u64 = 4;
+
+impl SpillQueue {
+    /// Open the spill segment at `path`, keeping the complete records it already holds
+    pub async fn open(path: PathBuf, max_size: u64) -> io::Result<Self> {
+        let (size, pending) = match File::open(&path).await {
+            Ok(file) => Self::scan(&path, file).await?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (0, 0),
+            Err(e) => return Err(e),
+        };
+        if pending > 0 {
+            info!(
+                "Found {} spilled message(s) in {:?}, they will be written first",
+                pending, path
+            );
+        }
+        let queue = SpillQueue {
+            path,
+            max_size: AtomicU64::new(max_size),
+            pending: AtomicU64::new(pending),
+            ready: Notify::new(),
+            segment: Mutex::new(Segment {
+                writer: None,
+                size,
+                read_offset: 0,
+            }),
+        };
+        if pending > 0 {
+            queue.ready.notify_one();
+        }
+        Ok(queue)
+    }
+
+    /// Count the complete records of an existing segment, dropping a partial last one
+    async fn scan(path: &Path, file: File) -> io::Result<(u64, u64)> {
+        let len = file.metadata().await?.len();
+        let mut reader = BufReader::new(file);
+        let (mut size, mut pending) = (0, 0);
+        while size + LEN_SIZE <= len {
+            let record_len = reader.read_u32().await? as u64;
+            if size + LEN_SIZE + record_len > len {
+                break;
+            }
+            reader.seek(SeekFrom::Current(record_len as i64)).await?;
+            size += LEN_SIZE + record_len;
+            pending += 1;
+        }
+        if size < len {
+            warn!(
+                "Dropping {} bytes of an incomplete record at the end of {:?}",
+                len - size,
+                path
+            );
+            OpenOptions::new()
+                .write(true)
+                .open(path)
+                .await?
+                .set_len(size)
+                .await?;
+        }
+        Ok((size, pending))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether some spilled messages haven't been replayed yet
+    pub fn has_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire) > 0
+    }
+
+    /// Wait until there are spilled messages to replay
+    pub async fn ready(&self) {
+        while !self.has_pending() {
+            self.ready.notified().await;
+        }
+    }
+
+    pub fn set_max_size(&self, max_size: u64) {
+        self.max_size.store(max_size, Ordering::Relaxed);
+    }
+
+    /// Append a message to the segment. Returns `false`, leaving the segment untouched,
+    /// when it would grow beyond the maximum size.
+    pub async fn push(&self, message: &Message) -> io::Result<bool> {
+        let record = encode(message);
+        let mut segment = self.segment.lock().await;
+        if segment.size + record.len() as u64 > self.max_size.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        if segment.writer.is_none() {
+            segment.writer = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)
+                    .await?,
+            );
+        }
+        let writer = segment.writer.as_mut().expect("spill writer is open");
+        writer.write_all(&record).await?;
+        writer.flush().await?;
+        segment.size += record.len() as u64;
+        self.pending.fetch_add(1, Ordering::AcqRel);
+        metrics::spill::spilled(record.len());
+        self.ready.notify_one();
+        Ok(true)
+    }
+
+    /// Take the oldest spilled messages, about `max_bytes` worth and at least one
+    pub async fn pop_batch(&self, max_bytes: usize) -> io::Result<Vec<Message>> {
+        let mut segment = self.segment.lock().await;
+        let mut messages = Vec::new();
+        if !self.has_pending() {
+            return Ok(messages);
+        }
+        let mut file = File::open(&self.path).await?;
+        file.seek(SeekFrom::Start(segment.read_offset)).await?;
+        let mut reader = BufReader::new(file);
+        let mut replayed = 0;
+        while self.has_pending() && (messages.is_empty() || replayed < max_bytes) {
+            let record_len = reader.read_u32().await? as usize;
+            let mut record = vec![0; record_len];
+            reader.read_exact(&mut record).await?;
+            messages.push(decode(record)?);
+            replayed += LEN_SIZE as usize + record_len;
+            segment.read_offset += LEN_SIZE + record_len as u64;
+            self.pending.fetch_sub(1, Ordering::AcqRel);
+        }
+        metrics::spill::replayed(replayed);
+        if !self.has_pending() {
+            // Everything was replayed, start over with an empty segment
+            match segment.writer.as_mut() {
+                Some(writer) => writer.set_len(0).await?,
+                None => {
+                    OpenOptions::new()
+                        .write(true)
+                        .open(&self.path)
+                        .await?
+                        .set_len(0)
+                        .await?
+                }
+            }
+            segment.size = 0;
+            segment.read_offset = 0;
+        }
+        Ok(messages)
+    }
+}
+
+/// Spill file of the writer for `file_path`, hidden so that rotation never picks it up
+pub fn spill_path(file_path: &Path) -> PathBuf {
+    let file_name = file_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    file_path.with_file_name(format!(".{}.spill", file_name))
+}
+
+/// Record layout after the length: protocol, syslog flag, receive time in microseconds,
+/// peer and listener addresses prefixed by their length, then the payload
+fn encode(message: &Message) -> Vec<u8> {
+    let peer = message.peer.to_string();
+    let listener = message.listener.to_string();
+    let len = 2 + 8 + 1 + peer.len() + 1 + listener.len() + message.payload.len();
+    let mut record = Vec::with_capacity(LEN_SIZE as usize + len);
+    record.extend_from_slice(&(len as u32).to_be_bytes());
+    record.push(match message.protocol {
+        ProtocolType::TCP => 0,
+        ProtocolType::UDP => 1,
+    });
+    record.push(message.syslog.is_some() as u8);
+    record.extend_from_slice(&message.received_at.timestamp_micros().to_be_bytes());
+    record.push(peer.len() as u8);
+    record.extend_from_slice(peer.as_bytes());
+    record.push(listener.len() as u8);
+    record.extend_from_slice(listener.as_bytes());
+    record.extend_from_slice(&message.payload);
+    record
+}
+
+fn decode(record: Vec<u8>) -> io::Result<Message> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, "corrupted spill record");
+    let header = record.get(..10).ok_or_else(invalid)?;
+    let protocol = match header[0] {
+        0 => ProtocolType::TCP,
+        1 => ProtocolType::UDP,
+        _ => return Err(invalid()),
+    };
+    let syslog = header[1] == 1;
+    let micros = i64::from_be_bytes(header[2..10].try_into().expect("8 bytes"));
+    let received_at = Utc.timestamp_micros(micros).single().ok_or_else(invalid)?;
+
+    let mut rest = &record[10..];
+    let mut address = || -> io::Result<SocketAddr> {
+        let (&len, tail) = rest.split_first().ok_or_else(invalid)?;
+        let text = tail.get(..len as usize).ok_or_else(invalid)?;
+        rest = &tail[len as usize..];
+        std::str::from_utf8(text)
+            .ok()
+            .and_then(|text| text.parse().ok())
+            .ok_or_else(invalid)
+    };
+    let peer = address()?;
+    let listener = address()?;
+    let payload = Bytes::copy_from_slice(rest);
+    // Syslog fields are parsed again from the payload they were parsed from
+    let syslog = if syslog {
+        SyslogMessage::parse(&payload).ok()
+    } else {
+        None
+    };
+    Ok(Message {
+        payload,
+        peer,
+        received_at,
+        listener,
+        protocol,
+        syslog,
+        // Replayed straight to the file, it never waits in the queue
+        enqueued_at: None,
+    })
+}
@@ -0,0 +1,57 @@
+use jon_listen::error::AccessListError;
+use jon_listen::listener::access_list::AccessList;
+use std::net::IpAddr;
+
+fn ip(addr: &str) -> IpAddr {
+    addr.parse().unwrap()
+}
+
+fn list(cidrs: &[&str]) -> Vec<String> {
+    cidrs.iter().map(|cidr| cidr.to_string()).collect()
+}
+
+#[test]
+fn test_empty_lists_permit_every_peer() {
+    let access_list = AccessList::new(&[], &[]).unwrap();
+
+    assert!(access_list.permits(ip("10.0.0.1")));
+    assert!(access_list.permits(ip("::1")));
+}
+
+#[test]
+fn test_allow_list_restricts_peers() {
+    let access_list = AccessList::new(&list(&["10.0.0.0/8", "192.168.1.10"]), &[]).unwrap();
+
+    assert!(access_list.permits(ip("10.1.2.3")));
+    assert!(access_list.permits(ip("192.168.1.10")));
+    assert!(!access_list.permits(ip("192.168.1.11")));
+}
+
+#[test]
+fn test_deny_list_takes_precedence() {
+    let access_list = AccessList::new(&list(&["10.0.0.0/8"]), &list(&["10.0.0.0/24"])).unwrap();
+
+    assert!(!access_list.permits(ip("10.0.0.5")));
+    assert!(access_list.permits(ip("10.0.1.5")));
+
+    let deny_only = AccessList::new(&[], &list(&["203.0.113.0/24"])).unwrap();
+    assert!(!deny_only.permits(ip("203.0.113.7")));
+    assert!(deny_only.permits(ip("198.51.100.7")));
+}
+
+#[test]
+fn test_set_replaces_lists_of_every_clone() {
+    let access_list = AccessList::new(&[], &[]).unwrap();
+    let clone = access_list.clone();
+
+    access_list.set(&[], &list(&["10.0.0.1"])).unwrap();
+    assert!(!clone.permits(ip("10.0.0.1")));
+
+    // Invalid lists are rejected and the running ones kept
+    assert_eq!(
+        access_list.set(&list(&["nope"]), &[]),
+        Err(AccessListError::InvalidCidr("nope".to_string()))
+    );
+    assert!(!clone.permits(ip("10.0.0.1")));
+    assert!(clone.permits(ip("10.0.0.2")));
+}
@@ -1,5 +1,5 @@
 use jon_listen::settings::ProtocolType;
-use jon_listen::App;
+use jon_listen::{App, Control};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
@@ -179,3 +179,363 @@ async fn test_app_start_up_error_handling_for_file_writer_creation_failure() {
         err_msg
     );
 }
+
+#[tokio::test]
+async fn test_app_start_up_runs_tcp_and_udp_listeners_together() {
+    use jon_listen::settings::ServerConfig;
+    use tokio::io::AsyncWriteExt;
+
+    let (settings, _temp_dir) = helpers::create_test_settings(ProtocolType::TCP);
+    let mut settings = settings;
+    let tcp_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let udp_port = std::net::UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    settings.server.host = "127.0.0.1".to_string();
+    settings.server.port = tcp_port as i32;
+    settings.listeners = vec![ServerConfig {
+        protocol: ProtocolType::UDP,
+        port: udp_port as i32,
+        ..settings.server.clone()
+    }];
+    let file_path = settings
+        .filewriter
+        .filedir
+        .join(&settings.filewriter.filename);
+    let settings = Arc::new(settings);
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+
+    let app_handle = tokio::spawn(async move { App::start_up(settings, shutdown_rx).await });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect(("127.0.0.1", tcp_port))
+        .await
+        .expect("TCP listener should accept connections");
+    tcp_stream.write_all(b"hello from tcp\n").await.unwrap();
+    tcp_stream.flush().await.unwrap();
+
+    let udp_socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    udp_socket
+        .send_to(b"hello from udp", ("127.0.0.1", udp_port))
+        .await
+        .unwrap();
+
+    let path = file_path.clone();
+    helpers::wait_for_condition(
+        || {
+            let content = std::fs::read_to_string(&path).unwrap_or_default();
+            content.contains("hello from tcp") && content.contains("hello from udp")
+        },
+        Duration::from_secs(2),
+    )
+    .await
+    .expect("Messages from both listeners should reach the same file");
+
+    shutdown_tx.send(()).unwrap();
+
+    let result = timeout(Duration::from_secs(3), app_handle).await;
+    assert!(result.is_ok(), "App should complete within timeout");
+    assert!(
+        result.unwrap().unwrap().is_ok(),
+        "App should shutdown gracefully with several listeners"
+    );
+}
+
+#[tokio::test]
+async fn test_app_start_up_routes_messages_to_named_writers() {
+    use jon_listen::settings::RouteConfig;
+    use tokio::io::AsyncWriteExt;
+
+    let (settings, temp_dir) = helpers::create_test_settings(ProtocolType::TCP);
+    let mut settings = settings;
+    let tcp_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    settings.server.host = "127.0.0.1".to_string();
+    settings.server.port = tcp_port as i32;
+    let mut errors_config = settings.filewriter.clone();
+    errors_config.filename = "errors.log".to_string();
+    settings.writers.insert("errors".to_string(), errors_config);
+    settings.routes = vec![RouteConfig {
+        writer: "errors".to_string(),
+        peer_cidr: Some("127.0.0.0/8".to_string()),
+        listener_port: None,
+        app_name: None,
+        pattern: Some("^ERROR".to_string()),
+    }];
+    let default_path = settings
+        .filewriter
+        .filedir
+        .join(&settings.filewriter.filename);
+    let errors_path = temp_dir.path().join("errors.log");
+    let settings = Arc::new(settings);
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+
+    let app_handle = tokio::spawn(async move { App::start_up(settings, shutdown_rx).await });
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect(("127.0.0.1", tcp_port))
+        .await
+        .expect("TCP listener should accept connections");
+    tcp_stream
+        .write_all(b"ERROR disk full\nINFO all good\n")
+        .await
+        .unwrap();
+    tcp_stream.flush().await.unwrap();
+
+    let (default, errors) = (default_path.clone(), errors_path.clone());
+    helpers::wait_for_condition(
+        || {
+            std::fs::read_to_string(&default)
+                .unwrap_or_default()
+                .contains("INFO all good")
+                && std::fs::read_to_string(&errors)
+                    .unwrap_or_default()
+                    .contains("ERROR disk full")
+        },
+        Duration::from_secs(2),
+    )
+    .await
+    .expect("Each message should reach the writer selected by the routes");
+
+    let default_content = std::fs::read_to_string(&default_path).unwrap();
+    assert!(
+        !default_content.contains("ERROR disk full"),
+        "Routed message should not reach the default writer"
+    );
+
+    shutdown_tx.send(()).unwrap();
+
+    let result = timeout(Duration::from_secs(3), app_handle).await;
+    assert!(result.is_ok(), "App should complete within timeout");
+    assert!(
+        result.unwrap().unwrap().is_ok(),
+        "App should shutdown gracefully with several writers"
+    );
+}
+
+#[tokio::test]
+async fn test_app_start_up_rejects_route_to_unknown_writer() {
+    use jon_listen::settings::RouteConfig;
+
+    let (settings, _temp_dir) = helpers::create_test_settings(ProtocolType::TCP);
+    let mut settings = settings;
+    settings.routes = vec![RouteConfig {
+        writer: "missing".to_string(),
+        peer_cidr: None,
+        listener_port: None,
+        app_name: None,
+        pattern: None,
+    }];
+    let (_shutdown_tx, shutdown_rx) = broadcast::channel(1);
+
+    let result = timeout(
+        Duration::from_secs(3),
+        App::start_up(Arc::new(settings), shutdown_rx),
+    )
+    .await
+    .expect("App should fail fast on invalid routes");
+
+    let err = result.expect_err("Unknown writer should be a configuration error");
+    assert!(
+        format!("{:#}", err).contains("unknown writer 'missing'"),
+        "got: {:#}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn test_app_start_up_rejects_named_writer_called_default() {
+    let (settings, _temp_dir) = helpers::create_test_settings(ProtocolType::TCP);
+    let mut settings = settings;
+    let mut named = settings.filewriter.clone();
+    named.filename = "named.log".to_string();
+    settings.writers.insert("default".to_string(), named);
+    let (_shutdown_tx, shutdown_rx) = broadcast::channel(1);
+
+    let result = timeout(
+        Duration::from_secs(3),
+        App::start_up(Arc::new(settings), shutdown_rx),
+    )
+    .await
+    .expect("App should fail fast on a reserved writer name");
+
+    let err = result.expect_err("A writer named default should be a configuration error");
+    assert!(
+        format!("{:#}", err).contains("'default' is reserved"),
+        "got: {:#}",
+        err
+    );
+}
+
+#[tokio::test]
+async fn test_app_start_up_with_control_reload_applies_new_prefix() {
+    use tokio::io::AsyncWriteExt;
+    use tokio::sync::mpsc;
+
+    let (settings, _temp_dir) = helpers::create_test_settings(ProtocolType::TCP);
+    let mut settings = settings;
+    let tcp_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    settings.server.host = "127.0.0.1".to_string();
+    settings.server.port = tcp_port as i32;
+    let file_path = settings
+        .filewriter
+        .filedir
+        .join(&settings.filewriter.filename);
+    let mut reloaded = settings.clone();
+    reloaded.filewriter.formatting.prefix = Some("[{protocol}] ".to_string());
+    let settings = Arc::new(settings);
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+    let (control_tx, control_rx) = mpsc::channel(1);
+
+    let app_handle =
+        tokio::spawn(
+            async move { App::start_up_with_control(settings, shutdown_rx, control_rx).await },
+        );
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect(("127.0.0.1", tcp_port))
+        .await
+        .expect("TCP listener should accept connections");
+    tcp_stream.write_all(b"before reload\n").await.unwrap();
+    tcp_stream.flush().await.unwrap();
+
+    let path = file_path.clone();
+    helpers::wait_for_condition(
+        || {
+            std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .contains("before reload")
+        },
+        Duration::from_secs(2),
+    )
+    .await
+    .expect("Message should be written before the reload");
+
+    control_tx
+        .send(Control::Reload(Box::new(reloaded)))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    tcp_stream.write_all(b"after reload\n").await.unwrap();
+    tcp_stream.flush().await.unwrap();
+
+    let path = file_path.clone();
+    helpers::wait_for_condition(
+        || {
+            std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .contains("[TCP] after reload")
+        },
+        Duration::from_secs(2),
+    )
+    .await
+    .expect("Messages received after the reload should carry the new prefix");
+
+    let content = std::fs::read_to_string(&file_path).unwrap();
+    assert!(!content.contains("[TCP] before reload"));
+
+    shutdown_tx.send(()).unwrap();
+
+    let result = timeout(Duration::from_secs(3), app_handle).await;
+    assert!(result.is_ok(), "App should complete within timeout");
+    assert!(
+        result.unwrap().unwrap().is_ok(),
+        "App should shutdown gracefully after a reload"
+    );
+}
+
+#[tokio::test]
+async fn test_app_start_up_with_control_reopens_files() {
+    use tokio::io::AsyncWriteExt;
+    use tokio::sync::mpsc;
+
+    let (settings, temp_dir) = helpers::create_test_settings(ProtocolType::TCP);
+    let mut settings = settings;
+    let tcp_port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    settings.server.host = "127.0.0.1".to_string();
+    settings.server.port = tcp_port as i32;
+    let file_path = settings
+        .filewriter
+        .filedir
+        .join(&settings.filewriter.filename);
+    let moved_path = temp_dir.path().join("moved.log");
+    let settings = Arc::new(settings);
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+    let (control_tx, control_rx) = mpsc::channel(1);
+
+    let app_handle =
+        tokio::spawn(
+            async move { App::start_up_with_control(settings, shutdown_rx, control_rx).await },
+        );
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut tcp_stream = tokio::net::TcpStream::connect(("127.0.0.1", tcp_port))
+        .await
+        .expect("TCP listener should accept connections");
+    tcp_stream.write_all(b"before reopen\n").await.unwrap();
+    tcp_stream.flush().await.unwrap();
+
+    let path = file_path.clone();
+    helpers::wait_for_condition(
+        || {
+            std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .contains("before reopen")
+        },
+        Duration::from_secs(2),
+    )
+    .await
+    .expect("Message should be written before the file is moved");
+
+    std::fs::rename(&file_path, &moved_path).unwrap();
+    control_tx.send(Control::Reopen).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    tcp_stream.write_all(b"after reopen\n").await.unwrap();
+    tcp_stream.flush().await.unwrap();
+
+    let path = file_path.clone();
+    helpers::wait_for_condition(
+        || {
+            std::fs::read_to_string(&path)
+                .unwrap_or_default()
+                .contains("after reopen")
+        },
+        Duration::from_secs(2),
+    )
+    .await
+    .expect("Messages received after the reopen should go to a new file");
+
+    let moved = std::fs::read_to_string(&moved_path).unwrap();
+    assert!(moved.contains("before reopen"));
+    assert!(!moved.contains("after reopen"));
+
+    shutdown_tx.send(()).unwrap();
+
+    let result = timeout(Duration::from_secs(3), app_handle).await;
+    assert!(result.is_ok(), "App should complete within timeout");
+    assert!(
+        result.unwrap().unwrap().is_ok(),
+        "App should shutdown gracefully after a reopen"
+    );
+}
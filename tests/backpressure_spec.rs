@@ -1,6 +1,8 @@
-use jon_listen::settings::BackpressurePolicy;
+use bytes::Bytes;
+use chrono::prelude::*;
+use jon_listen::settings::{BackpressurePolicy, ProtocolType};
 use jon_listen::writer::backpressure::BackpressureAwareSender;
-use jon_listen::writer::file_writer::FileWriterCommand;
+use jon_listen::writer::file_writer::{FileWriterCommand, Message};
 use tokio::sync::mpsc;
 use tokio::time::{sleep, Duration};
 
@@ -14,6 +16,24 @@ fn create_test_channel(
     mpsc::channel(capacity)
 }
 
+/// Helper to create a received message with a fixed receive time
+fn message(payload: &[u8]) -> Message {
+    Message {
+        payload: Bytes::copy_from_slice(payload),
+        peer: "127.0.0.1:40000".parse().unwrap(),
+        received_at: Utc.timestamp_opt(0, 0).unwrap(),
+        listener: "127.0.0.1:8080".parse().unwrap(),
+        protocol: ProtocolType::UDP,
+        syslog: None,
+        enqueued_at: None,
+    }
+}
+
+/// Helper to create a Write command for a payload
+fn write(payload: &[u8]) -> FileWriterCommand {
+    FileWriterCommand::Write(message(payload))
+}
+
 #[tokio::test]
 async fn test_new_with_block_policy() {
     let (tx, _rx) = create_test_channel(10);
@@ -37,7 +57,7 @@ async fn test_send_successful_when_channel_has_capacity_block() {
     let (tx, mut rx) = create_test_channel(10);
     let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
 
-    let command = FileWriterCommand::Write(b"test message".to_vec());
+    let command = write(b"test message");
     let result = sender.send(command.clone()).await;
 
     assert!(result.is_ok());
@@ -54,7 +74,7 @@ async fn test_send_successful_when_channel_has_capacity_discard() {
     let (tx, mut rx) = create_test_channel(10);
     let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Discard);
 
-    let command = FileWriterCommand::Write(b"test message".to_vec());
+    let command = write(b"test message");
     let result = sender.send(command.clone()).await;
 
     assert!(result.is_ok());
@@ -73,11 +93,11 @@ async fn test_send_blocks_when_channel_full_block_policy() {
     let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
 
     // Fill the channel
-    let command1 = FileWriterCommand::Write(b"first".to_vec());
+    let command1 = write(b"first");
     sender.send(command1.clone()).await.unwrap();
 
     // This should block until space is available
-    let command2 = FileWriterCommand::Write(b"second".to_vec());
+    let command2 = write(b"second");
     let start = std::time::Instant::now();
 
     // Spawn a task to consume the message after a delay, which will unblock the send
@@ -99,10 +119,7 @@ async fn test_send_blocks_when_channel_full_block_policy() {
     // Verify the second message was received
     let received = rx_clone.recv().await;
     assert!(received.is_some());
-    assert_eq!(
-        received.unwrap(),
-        FileWriterCommand::Write(b"second".to_vec())
-    );
+    assert_eq!(received.unwrap(), write(b"second"));
 }
 
 #[tokio::test]
@@ -111,11 +128,11 @@ async fn test_send_discards_when_channel_full_discard_policy() {
     let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Discard);
 
     // Fill the channel
-    let command1 = FileWriterCommand::Write(b"first".to_vec());
+    let command1 = write(b"first");
     sender.send(command1.clone()).await.unwrap();
 
     // Try to send another - should be discarded
-    let command2 = FileWriterCommand::Write(b"second".to_vec());
+    let command2 = write(b"second");
     let result = sender.send(command2).await;
 
     assert!(result.is_ok()); // Returns Ok even though message was dropped
@@ -143,7 +160,7 @@ async fn test_send_error_when_channel_closed() {
     // Small delay to ensure channel closure propagates
     sleep(Duration::from_millis(10)).await;
 
-    let command = FileWriterCommand::Write(b"test".to_vec());
+    let command = write(b"test");
     let result = sender.send(command).await;
 
     assert!(result.is_err());
@@ -155,17 +172,11 @@ async fn test_backpressure_events_counter() {
     let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Discard);
 
     // Fill channel
-    sender
-        .send(FileWriterCommand::Write(b"first".to_vec()))
-        .await
-        .unwrap();
+    sender.send(write(b"first")).await.unwrap();
 
     // Trigger backpressure multiple times
     for _ in 0..5 {
-        sender
-            .send(FileWriterCommand::Write(b"overflow".to_vec()))
-            .await
-            .unwrap();
+        sender.send(write(b"overflow")).await.unwrap();
     }
 
     assert_eq!(sender.backpressure_events(), 5);
@@ -180,17 +191,11 @@ async fn test_dropped_messages_counter() {
     let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Discard);
 
     // Fill channel
-    sender
-        .send(FileWriterCommand::Write(b"first".to_vec()))
-        .await
-        .unwrap();
+    sender.send(write(b"first")).await.unwrap();
 
     // Drop 3 messages
     for _ in 0..3 {
-        sender
-            .send(FileWriterCommand::Write(b"overflow".to_vec()))
-            .await
-            .unwrap();
+        sender.send(write(b"overflow")).await.unwrap();
     }
 
     assert_eq!(sender.dropped_messages(), 3);
@@ -198,10 +203,7 @@ async fn test_dropped_messages_counter() {
     // With Block policy, dropped_messages should remain 0
     let (tx2, _rx2) = create_test_channel(1);
     let sender_block = BackpressureAwareSender::new(tx2, BackpressurePolicy::Block);
-    sender_block
-        .send(FileWriterCommand::Write(b"first".to_vec()))
-        .await
-        .unwrap();
+    sender_block.send(write(b"first")).await.unwrap();
 
     // This will block, not drop, so counter should stay 0
     assert_eq!(sender_block.dropped_messages(), 0);
@@ -216,14 +218,8 @@ async fn test_reset_backpressure_events() {
     let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Discard);
 
     // Fill channel and trigger backpressure
-    sender
-        .send(FileWriterCommand::Write(b"first".to_vec()))
-        .await
-        .unwrap();
-    sender
-        .send(FileWriterCommand::Write(b"overflow".to_vec()))
-        .await
-        .unwrap();
+    sender.send(write(b"first")).await.unwrap();
+    sender.send(write(b"overflow")).await.unwrap();
 
     assert_eq!(sender.backpressure_events(), 1);
 
@@ -241,18 +237,9 @@ async fn test_reset_dropped_messages() {
     let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Discard);
 
     // Fill channel and drop messages
-    sender
-        .send(FileWriterCommand::Write(b"first".to_vec()))
-        .await
-        .unwrap();
-    sender
-        .send(FileWriterCommand::Write(b"overflow1".to_vec()))
-        .await
-        .unwrap();
-    sender
-        .send(FileWriterCommand::Write(b"overflow2".to_vec()))
-        .await
-        .unwrap();
+    sender.send(write(b"first")).await.unwrap();
+    sender.send(write(b"overflow1")).await.unwrap();
+    sender.send(write(b"overflow2")).await.unwrap();
 
     assert_eq!(sender.dropped_messages(), 2);
 
@@ -271,16 +258,10 @@ async fn test_clone_shares_counters() {
     let sender2 = sender1.clone();
 
     // Fill channel with sender1
-    sender1
-        .send(FileWriterCommand::Write(b"first".to_vec()))
-        .await
-        .unwrap();
+    sender1.send(write(b"first")).await.unwrap();
 
     // Drop message with sender2
-    sender2
-        .send(FileWriterCommand::Write(b"overflow".to_vec()))
-        .await
-        .unwrap();
+    sender2.send(write(b"overflow")).await.unwrap();
 
     // Both should see the same counters
     assert_eq!(sender1.backpressure_events(), 1);
@@ -292,6 +273,28 @@ async fn test_clone_shares_counters() {
     let _ = rx.recv().await;
 }
 
+#[tokio::test]
+async fn test_set_backpressure_policy_is_shared_by_clones() {
+    let (tx, mut rx) = create_test_channel(1);
+    let sender1 = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+    let sender2 = sender1.clone();
+
+    sender1.set_backpressure_policy(BackpressurePolicy::Discard);
+    assert_eq!(sender2.backpressure_policy(), BackpressurePolicy::Discard);
+
+    // The clone now discards instead of blocking on a full channel
+    sender1.send(write(b"first")).await.unwrap();
+    let result =
+        tokio::time::timeout(Duration::from_millis(100), sender2.send(write(b"overflow"))).await;
+    assert!(
+        result.is_ok(),
+        "Send should not block after switching to Discard"
+    );
+    assert_eq!(sender2.dropped_messages(), 1);
+
+    let _ = rx.recv().await;
+}
+
 #[tokio::test]
 async fn test_rate_limited_logging() {
     // This test verifies that logging happens at most once per interval
@@ -302,17 +305,11 @@ async fn test_rate_limited_logging() {
     let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Discard);
 
     // Fill channel
-    sender
-        .send(FileWriterCommand::Write(b"first".to_vec()))
-        .await
-        .unwrap();
+    sender.send(write(b"first")).await.unwrap();
 
     // Trigger multiple backpressure events quickly
     for _ in 0..10 {
-        sender
-            .send(FileWriterCommand::Write(b"overflow".to_vec()))
-            .await
-            .unwrap();
+        sender.send(write(b"overflow")).await.unwrap();
     }
 
     // All events should be tracked
@@ -329,12 +326,12 @@ async fn test_different_command_types() {
     let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
 
     // Test Write command
-    let write_cmd = FileWriterCommand::Write(b"test".to_vec());
+    let write_cmd = write(b"test");
     sender.send(write_cmd.clone()).await.unwrap();
     assert_eq!(rx.recv().await.unwrap(), write_cmd);
 
     // Test WriteDebug command
-    let debug_cmd = FileWriterCommand::WriteDebug("test".to_string(), b"debug".to_vec(), 1);
+    let debug_cmd = FileWriterCommand::WriteDebug("test".to_string(), message(b"debug"), 1);
     sender.send(debug_cmd.clone()).await.unwrap();
     assert_eq!(rx.recv().await.unwrap(), debug_cmd);
 
@@ -344,3 +341,257 @@ async fn test_different_command_types() {
     sender.send(rename_cmd.clone()).await.unwrap();
     assert_eq!(rx.recv().await.unwrap(), rename_cmd);
 }
+
+#[tokio::test]
+async fn test_send_spills_when_channel_full_spill_policy() {
+    use jon_listen::writer::spill::SpillQueue;
+    use std::sync::Arc;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let spill = Arc::new(
+        SpillQueue::open(temp_dir.path().join(".test.log.spill"), 1024 * 1024)
+            .await
+            .unwrap(),
+    );
+    let (tx, mut rx) = create_test_channel(1);
+    let sender =
+        BackpressureAwareSender::new(tx, BackpressurePolicy::Spill).with_spill_queue(spill.clone());
+
+    sender.send(write(b"first")).await.unwrap();
+    sender.send(write(b"second")).await.unwrap();
+    assert!(spill.has_pending());
+    assert_eq!(sender.backpressure_events(), 1);
+    assert_eq!(sender.dropped_messages(), 0);
+
+    // Messages keep going to the spill queue until it's replayed, to stay in order
+    assert_eq!(rx.recv().await, Some(write(b"first")));
+    sender.send(write(b"third")).await.unwrap();
+    assert!(rx.try_recv().is_err());
+    assert_eq!(
+        spill.pop_batch(1024).await.unwrap(),
+        vec![message(b"second"), message(b"third")]
+    );
+
+    sender.send(write(b"fourth")).await.unwrap();
+    assert_eq!(rx.recv().await, Some(write(b"fourth")));
+}
+
+#[tokio::test]
+async fn test_send_drops_when_spill_queue_full() {
+    use jon_listen::writer::spill::SpillQueue;
+    use std::sync::Arc;
+
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let spill = Arc::new(
+        SpillQueue::open(temp_dir.path().join(".test.log.spill"), 10)
+            .await
+            .unwrap(),
+    );
+    let (tx, _rx) = create_test_channel(1);
+    let sender =
+        BackpressureAwareSender::new(tx, BackpressurePolicy::Spill).with_spill_queue(spill);
+
+    sender.send(write(b"first")).await.unwrap();
+    sender
+        .send(write(b"too big for the spill queue"))
+        .await
+        .unwrap();
+    assert_eq!(sender.dropped_messages(), 1);
+}
+
+#[tokio::test]
+async fn test_send_evicts_oldest_when_channel_full_drop_oldest_policy() {
+    use jon_listen::writer::queue::CommandQueue;
+
+    let (tx, rx) = create_test_channel(2);
+    let queue = CommandQueue::new(rx);
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::DropOldest)
+        .with_command_queue(queue.clone());
+
+    sender.send(write(b"first")).await.unwrap();
+    sender.send(write(b"second")).await.unwrap();
+    sender.send(write(b"third")).await.unwrap();
+
+    assert_eq!(sender.dropped_messages(), 1);
+    assert_eq!(queue.recv().await, Some(write(b"second")));
+    assert_eq!(queue.recv().await, Some(write(b"third")));
+}
+
+#[tokio::test]
+async fn test_send_marks_messages_enqueued() {
+    let (tx, mut rx) = create_test_channel(10);
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+
+    let before = std::time::Instant::now();
+    sender.send(write(b"single")).await.unwrap();
+    sender
+        .send(FileWriterCommand::WriteBatch(vec![
+            message(b"one"),
+            message(b"two"),
+        ]))
+        .await
+        .unwrap();
+
+    let Some(FileWriterCommand::Write(message)) = rx.recv().await else {
+        panic!("expected a Write command");
+    };
+    assert!(message.enqueued_at.unwrap() >= before);
+    let Some(FileWriterCommand::WriteBatch(messages)) = rx.recv().await else {
+        panic!("expected a WriteBatch command");
+    };
+    assert!(messages.iter().all(|m| m.enqueued_at.unwrap() >= before));
+}
+
+#[tokio::test]
+async fn test_drop_oldest_keeps_queued_commands() {
+    use jon_listen::writer::queue::CommandQueue;
+
+    let (tx, rx) = create_test_channel(2);
+    let queue = CommandQueue::new(rx);
+    let sender = BackpressureAwareSender::new(tx.clone(), BackpressurePolicy::DropOldest)
+        .with_command_queue(queue.clone());
+
+    tx.send(FileWriterCommand::Reopen).await.unwrap();
+    sender.send(write(b"first")).await.unwrap();
+    sender.send(write(b"second")).await.unwrap();
+
+    // The command is requeued behind the messages, only the message is evicted
+    assert_eq!(sender.dropped_messages(), 1);
+    assert_eq!(queue.recv().await, Some(FileWriterCommand::Reopen));
+    assert_eq!(queue.recv().await, Some(write(b"second")));
+}
+
+#[tokio::test]
+async fn test_drop_oldest_keeps_commands_ahead_of_later_messages() {
+    use jon_listen::writer::queue::CommandQueue;
+
+    let (tx, rx) = create_test_channel(3);
+    let queue = CommandQueue::new(rx);
+    let sender = BackpressureAwareSender::new(tx.clone(), BackpressurePolicy::DropOldest)
+        .with_command_queue(queue.clone());
+
+    tx.send(FileWriterCommand::Reopen).await.unwrap();
+    sender.send(write(b"first")).await.unwrap();
+    sender.send(write(b"second")).await.unwrap();
+    sender.send(write(b"third")).await.unwrap();
+
+    // Messages queued after the reopen are still written after it
+    assert_eq!(sender.dropped_messages(), 1);
+    assert_eq!(queue.recv().await, Some(FileWriterCommand::Reopen));
+    assert_eq!(queue.recv().await, Some(write(b"second")));
+    assert_eq!(queue.recv().await, Some(write(b"third")));
+    assert!(queue.is_empty());
+}
+
+#[tokio::test]
+async fn test_drop_oldest_never_waits_behind_other_commands() {
+    use jon_listen::writer::queue::CommandQueue;
+
+    let (tx, rx) = create_test_channel(1);
+    let queue = CommandQueue::new(rx);
+    let sender = BackpressureAwareSender::new(tx.clone(), BackpressurePolicy::DropOldest)
+        .with_command_queue(queue.clone());
+
+    tx.send(FileWriterCommand::Reopen).await.unwrap();
+    tokio::time::timeout(Duration::from_secs(1), sender.send(write(b"message")))
+        .await
+        .expect("DropOldest must not wait for the FileWriter")
+        .unwrap();
+
+    assert_eq!(sender.dropped_messages(), 0);
+    assert_eq!(queue.len(), 2);
+    assert_eq!(queue.recv().await, Some(FileWriterCommand::Reopen));
+    assert_eq!(queue.recv().await, Some(write(b"message")));
+}
+
+#[tokio::test]
+async fn test_drop_oldest_without_queue_discards_new_message() {
+    let (tx, mut rx) = create_test_channel(1);
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::DropOldest);
+
+    sender.send(write(b"first")).await.unwrap();
+    sender.send(write(b"second")).await.unwrap();
+
+    assert_eq!(sender.dropped_messages(), 1);
+    assert_eq!(rx.recv().await, Some(write(b"first")));
+}
+
+#[tokio::test]
+async fn test_send_keeps_one_in_n_when_channel_full_sample_policy() {
+    let (tx, mut rx) = create_test_channel(1);
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Sample(2));
+
+    sender.send(write(b"first")).await.unwrap();
+
+    // Kept: waits for the channel to have space
+    let kept = sender.clone();
+    let handle = tokio::spawn(async move { kept.send(write(b"second")).await });
+    sleep(Duration::from_millis(50)).await; // let it find the channel full
+    assert_eq!(rx.recv().await, Some(write(b"first")));
+    handle.await.unwrap().unwrap();
+
+    // Sampled out: dropped right away
+    sender.send(write(b"third")).await.unwrap();
+    assert_eq!(sender.dropped_messages(), 1);
+
+    let kept = sender.clone();
+    let handle = tokio::spawn(async move { kept.send(write(b"fourth")).await });
+    sleep(Duration::from_millis(50)).await; // let it find the channel full
+    assert_eq!(rx.recv().await, Some(write(b"second")));
+    handle.await.unwrap().unwrap();
+    assert_eq!(rx.recv().await, Some(write(b"fourth")));
+    assert_eq!(sender.dropped_messages(), 1);
+}
+
+/// Helper to create a WriteBatch command for some payloads
+fn write_batch(payloads: &[&[u8]]) -> FileWriterCommand {
+    FileWriterCommand::WriteBatch(payloads.iter().map(|p| message(p)).collect())
+}
+
+#[tokio::test]
+async fn test_discard_counts_every_message_of_a_batch() {
+    let (tx, mut rx) = create_test_channel(1);
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Discard);
+
+    sender.send(write(b"first")).await.unwrap();
+    sender.send(write_batch(&[b"a", b"b", b"c"])).await.unwrap();
+
+    assert_eq!(sender.dropped_messages(), 3);
+    assert_eq!(rx.recv().await, Some(write(b"first")));
+}
+
+#[tokio::test]
+async fn test_drop_oldest_evicts_a_whole_batch() {
+    use jon_listen::writer::queue::CommandQueue;
+
+    let (tx, rx) = create_test_channel(1);
+    let queue = CommandQueue::new(rx);
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::DropOldest)
+        .with_command_queue(queue.clone());
+
+    sender.send(write_batch(&[b"a", b"b"])).await.unwrap();
+    sender.send(write(b"new")).await.unwrap();
+
+    assert_eq!(sender.dropped_messages(), 2);
+    assert_eq!(queue.recv().await, Some(write(b"new")));
+}
+
+#[tokio::test]
+async fn test_sample_keeps_one_in_n_messages_of_a_batch() {
+    let (tx, mut rx) = create_test_channel(1);
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Sample(2));
+
+    sender.send(write(b"first")).await.unwrap();
+
+    let kept = sender.clone();
+    let handle = tokio::spawn(async move {
+        kept.send(write_batch(&[b"a", b"b", b"c", b"d", b"e"]))
+            .await
+    });
+    sleep(Duration::from_millis(50)).await; // let it find the channel full
+    assert_eq!(rx.recv().await, Some(write(b"first")));
+    handle.await.unwrap().unwrap();
+
+    assert_eq!(rx.recv().await, Some(write_batch(&[b"a", b"c", b"e"])));
+    assert_eq!(sender.dropped_messages(), 2);
+}
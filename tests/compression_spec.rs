@@ -0,0 +1,108 @@
+use flate2::read::GzDecoder;
+use jon_listen::settings::Compression;
+use jon_listen::writer::compression::{
+    claimed_path, compress_claimed, compress_file, compressed_path, strip_extension,
+};
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn rotated_file(temp_dir: &TempDir, content: &[u8]) -> PathBuf {
+    let path = temp_dir.path().join("test.log.3");
+    fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn test_compress_file_gzip_replaces_rotated_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = rotated_file(&temp_dir, b"line 1\nline 2\n");
+
+    let compressed = compress_file(&path, &Compression::Gzip).unwrap();
+
+    assert_eq!(compressed, temp_dir.path().join("test.log.3.gz"));
+    assert!(!path.exists(), "Uncompressed file should be removed");
+    let mut content = String::new();
+    GzDecoder::new(fs::File::open(&compressed).unwrap())
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "line 1\nline 2\n");
+}
+
+#[test]
+fn test_compress_file_zstd_replaces_rotated_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = rotated_file(&temp_dir, b"line 1\nline 2\n");
+
+    let compressed = compress_file(&path, &Compression::Zstd).unwrap();
+
+    assert_eq!(compressed, temp_dir.path().join("test.log.3.zst"));
+    assert!(!path.exists(), "Uncompressed file should be removed");
+    let content = zstd::decode_all(fs::File::open(&compressed).unwrap()).unwrap();
+    assert_eq!(content, b"line 1\nline 2\n");
+}
+
+#[test]
+fn test_compress_file_none_keeps_rotated_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = rotated_file(&temp_dir, b"line 1\n");
+
+    let result = compress_file(&path, &Compression::None).unwrap();
+
+    assert_eq!(result, path);
+    assert_eq!(fs::read(&path).unwrap(), b"line 1\n");
+}
+
+#[test]
+fn test_compress_file_missing_source_leaves_no_partial_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("test.log.3");
+
+    assert!(compress_file(&path, &Compression::Gzip).is_err());
+    assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_compress_claimed_leaves_a_reused_slot_alone() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = rotated_file(&temp_dir, b"rotated\n");
+    let claimed = claimed_path(&path).unwrap();
+    fs::rename(&path, &claimed).unwrap();
+    // A later rotation takes the slot while the file is compressed
+    fs::write(&path, b"reused\n").unwrap();
+
+    let compressed = compress_claimed(&claimed, &path, &Compression::Gzip).unwrap();
+
+    assert_eq!(compressed, temp_dir.path().join("test.log.3.gz"));
+    assert!(!claimed.exists(), "Claimed file should be removed");
+    assert_eq!(fs::read(&path).unwrap(), b"reused\n");
+    let mut content = String::new();
+    GzDecoder::new(fs::File::open(&compressed).unwrap())
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "rotated\n");
+}
+
+#[test]
+fn test_claimed_path_is_hidden_unique_and_in_the_same_slot() {
+    let path = PathBuf::from("/var/log/app.log.3");
+    let first = claimed_path(&path).unwrap();
+    let second = claimed_path(&path).unwrap();
+
+    assert_ne!(first, second);
+    let name = first.file_name().unwrap().to_str().unwrap();
+    assert!(name.starts_with(".app.log.3.") && name.ends_with(".compressing"));
+    assert_eq!(strip_extension(&first), path);
+}
+
+#[test]
+fn test_compressed_path_and_strip_extension() {
+    let path = PathBuf::from("/var/log/app.log.3");
+    assert_eq!(
+        compressed_path(&path, "gz"),
+        PathBuf::from("/var/log/app.log.3.gz")
+    );
+    assert_eq!(strip_extension(&compressed_path(&path, "zst")), path);
+    assert_eq!(strip_extension(&path), path);
+}
@@ -43,6 +43,19 @@ fn test_file_writer_error_display_rename_error() {
     assert!(display.contains("/test/new.log") || display.contains("new.log"));
 }
 
+#[test]
+fn test_file_writer_error_display_sync_error() {
+    let io_error = io::Error::new(io::ErrorKind::StorageFull, "No space left on device");
+    let error = FileWriterError::SyncError {
+        path: PathBuf::from("/test/path.log"),
+        source: io_error,
+    };
+
+    let display = format!("{}", error);
+    assert!(display.contains("sync"));
+    assert!(display.contains("/test/path.log"));
+}
+
 #[test]
 fn test_file_writer_error_display_channel_closed() {
     let error = FileWriterError::ChannelClosed;
@@ -1,7 +1,7 @@
 use jon_listen::error::RotationError;
 use jon_listen::writer::file_rotation::FileRotation;
 use jon_listen::writer::file_writer::FileWriterCommand;
-use jon_listen::writer::rotation_policy::{RotationByDuration, RotationPolicy};
+use jon_listen::writer::rotation_policy::{RotationByDuration, RotationBySize, RotationPolicy};
 use std::path::PathBuf;
 use std::time::Duration;
 use tempfile::TempDir;
@@ -312,8 +312,122 @@ async fn test_request_rotate_when_max_files_reached() {
     }
 }
 
+#[tokio::test]
+async fn test_next_path_counts_compressed_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let (rotation, _rx) = create_test_file_rotation(&temp_dir, 20);
+
+    let files = vec![
+        temp_dir.path().join("test.log.0.gz"),
+        temp_dir.path().join("test.log.12.zst"),
+        temp_dir.path().join("test.log.3"),
+    ];
+
+    let next = rotation.next_path(&files).unwrap();
+    assert_eq!(next, temp_dir.path().join("test.log.13"));
+}
+
+#[tokio::test]
+async fn test_request_rotate_counts_files_being_compressed() {
+    let temp_dir = TempDir::new().unwrap();
+    let (rotation, mut rx) = create_test_file_rotation(&temp_dir, 20);
+    fs::write(
+        temp_dir.path().join(".test.log.0.1234.compressing"),
+        b"rotated",
+    )
+    .await
+    .unwrap();
+    fs::write(temp_dir.path().join("test.log"), b"content")
+        .await
+        .unwrap();
+
+    let new_path = rotation.request_rotate().await.unwrap();
+
+    // Slot 0 is still taken until its compression is done
+    assert_eq!(new_path, temp_dir.path().join("test.log.1"));
+    assert_eq!(rx.recv().await, Some(FileWriterCommand::Rename(new_path)));
+}
+
+#[tokio::test]
+async fn test_oldest_file_strips_compression_extension() {
+    let temp_dir = TempDir::new().unwrap();
+    let (rotation, _rx) = create_test_file_rotation(&temp_dir, 2);
+
+    let oldest = temp_dir.path().join("test.log.0.gz");
+    let newest = temp_dir.path().join("test.log.1.zst");
+    fs::write(&oldest, b"oldest").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    fs::write(&newest, b"newest").await.unwrap();
+
+    let result = rotation.oldest_file(&vec![oldest, newest]).await.unwrap();
+    assert_eq!(result, temp_dir.path().join("test.log.0"));
+}
+
+#[tokio::test]
+async fn test_request_rotate_reuses_slot_of_compressed_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let (rotation, mut rx) = create_test_file_rotation(&temp_dir, 2);
+
+    let oldest = temp_dir.path().join("test.log.0.gz");
+    fs::write(&oldest, b"oldest").await.unwrap();
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    fs::write(temp_dir.path().join("test.log.1.gz"), b"newest")
+        .await
+        .unwrap();
+    fs::write(temp_dir.path().join("test.log"), b"content")
+        .await
+        .unwrap();
+
+    let new_path = rotation.request_rotate().await.unwrap();
+
+    assert_eq!(new_path, temp_dir.path().join("test.log.0"));
+    assert!(!oldest.exists(), "Stale compressed file should be removed");
+    assert_eq!(rx.recv().await, Some(FileWriterCommand::Rename(new_path)));
+}
+
 // Phase 6: FileRotation::start() integration tests
 
+#[tokio::test]
+async fn test_file_rotation_start_rotates_when_size_trigger_fires() {
+    let temp_dir = TempDir::new().unwrap();
+    let file_path = temp_dir.path().join("test.log");
+    fs::write(&file_path, b"content").await.unwrap();
+
+    let rotation_policy: Box<dyn RotationPolicy> = Box::new(RotationBySize::new(10));
+    let (tx, mut rx) = mpsc::channel(10);
+    let rotation = FileRotation::new(
+        temp_dir.path().to_path_buf(),
+        file_path,
+        "test.log".to_string(),
+        10,
+        rotation_policy,
+        tx,
+    );
+    let size_trigger = rotation.size_trigger();
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+    let rotation_handle = rotation.start_async(shutdown_rx);
+
+    // Without a time trigger nothing happens until the writer signals
+    assert!(timeout(Duration::from_millis(100), rx.recv())
+        .await
+        .is_err());
+
+    size_trigger.notify_one();
+    let received = timeout(Duration::from_millis(500), rx.recv())
+        .await
+        .expect("Size trigger should request a rotation");
+    assert_eq!(
+        received,
+        Some(FileWriterCommand::Rename(
+            temp_dir.path().join("test.log.0")
+        ))
+    );
+
+    shutdown_tx.send(()).unwrap();
+    let result = timeout(Duration::from_secs(1), rotation_handle).await;
+    assert!(result.unwrap().unwrap().is_ok());
+}
+
 #[tokio::test]
 async fn test_file_rotation_start_triggers_rotation_by_duration() {
     let temp_dir = TempDir::new().unwrap();
@@ -1,9 +1,13 @@
+use bytes::Bytes;
+use chrono::prelude::*;
 use jon_listen::error::FileWriterError;
+use jon_listen::listener::syslog::SyslogMessage;
 use jon_listen::settings::{
-    BackpressurePolicy, FileWriterConfig, FormattingConfig, RotationPolicyConfig,
-    RotationPolicyType,
+    BackpressurePolicy, BufferConfig, Compression, Durability, DurabilityConfig, FileWriterConfig,
+    FormattingConfig, OutputFormat, ProtocolType, RotationPolicyConfig, RotationPolicyType,
+    Timezone,
 };
-use jon_listen::writer::file_writer::{FileWriter, FileWriterCommand};
+use jon_listen::writer::file_writer::{FileWriter, FileWriterCommand, Message};
 use std::path::PathBuf;
 use std::time::Duration;
 use tempfile::TempDir;
@@ -24,12 +28,42 @@ fn create_test_file_config(
             count: 10,
             policy: RotationPolicyType::ByDuration,
             duration: Some(999999), // Very long duration for tests
+            max_size: None,
+            compression: Compression::None,
         },
         formatting: FormattingConfig {
             startingmsg: with_starting_msg,
             endingmsg: with_ending_msg,
+            output: OutputFormat::Plain,
+            prefix: None,
+            timezone: Timezone::Local,
         },
         backpressure_policy: BackpressurePolicy::Block,
+        buffer: BufferConfig::default(),
+        durability: DurabilityConfig::default(),
+        spill_max_size: 1024 * 1024,
+    }
+}
+
+/// Helper to create a message received over TCP at a fixed time
+fn test_message(payload: &[u8]) -> Message {
+    Message {
+        payload: Bytes::copy_from_slice(payload),
+        peer: "10.0.0.1:5000".parse().unwrap(),
+        received_at: Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap(),
+        listener: "0.0.0.0:8080".parse().unwrap(),
+        protocol: ProtocolType::TCP,
+        syslog: None,
+        enqueued_at: None,
+    }
+}
+
+/// Helper to create a message received by a Syslog listener
+fn test_syslog_message(raw: &[u8]) -> Message {
+    Message {
+        syslog: Some(SyslogMessage::parse(raw).unwrap()),
+        enqueued_at: None,
+        ..test_message(raw)
     }
 }
 
@@ -88,12 +122,20 @@ async fn test_file_writer_new_error_invalid_path() {
             count: 10,
             policy: RotationPolicyType::ByDuration,
             duration: Some(3600),
+            max_size: None,
+            compression: Compression::None,
         },
         formatting: FormattingConfig {
             startingmsg: false,
             endingmsg: false,
+            output: OutputFormat::Plain,
+            prefix: None,
+            timezone: Timezone::Local,
         },
         backpressure_policy: BackpressurePolicy::Block,
+        buffer: BufferConfig::default(),
+        durability: DurabilityConfig::default(),
+        spill_max_size: 1024 * 1024,
     };
 
     let result = FileWriter::new(10, invalid_config).await;
@@ -136,9 +178,9 @@ async fn test_file_writer_write_appends_newline_when_missing() {
         tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
 
     // Send a Write command without newline - listen_commands should append one
-    tx.send(FileWriterCommand::Write(
-        b"test message without newline".to_vec(),
-    ))
+    tx.send(FileWriterCommand::Write(test_message(
+        b"test message without newline",
+    )))
     .await
     .unwrap();
 
@@ -289,7 +331,7 @@ async fn test_file_writer_listen_commands_write() {
         tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
 
     // Send a Write command
-    tx.send(FileWriterCommand::Write(b"test message\n".to_vec()))
+    tx.send(FileWriterCommand::Write(test_message(b"test message\n")))
         .await
         .unwrap();
 
@@ -308,6 +350,34 @@ async fn test_file_writer_listen_commands_write() {
     assert!(content.contains("test message"));
 }
 
+#[tokio::test]
+async fn test_file_writer_listen_commands_write_batch() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = create_test_file_config(&temp_dir, false, false);
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    tx.send(FileWriterCommand::WriteBatch(vec![
+        test_message(b"first"),
+        test_message(b"second"),
+    ]))
+    .await
+    .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+
+    // Written in order, one line per message
+    let file_path = temp_dir.path().join("test.log");
+    let content = fs::read_to_string(&file_path).await.unwrap();
+    assert!(content.contains("first\nsecond\n"));
+}
+
 #[tokio::test]
 async fn test_file_writer_listen_commands_write_debug() {
     let temp_dir = TempDir::new().unwrap();
@@ -324,7 +394,7 @@ async fn test_file_writer_listen_commands_write_debug() {
     // Send a WriteDebug command
     tx.send(FileWriterCommand::WriteDebug(
         "test-id".to_string(),
-        b"debug message".to_vec(),
+        test_message(b"debug message"),
         42,
     ))
     .await
@@ -448,6 +518,37 @@ async fn test_file_writer_listen_commands_graceful_shutdown() {
     assert!(result.is_ok());
 }
 
+#[tokio::test]
+async fn test_file_writer_records_queue_wait_once_written() {
+    let _ = jon_listen::metrics::init(9120);
+    let temp_dir = TempDir::new().unwrap();
+    let config = create_test_file_config(&temp_dir, false, false);
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    file_writer.set_name("wait_spec");
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    let mut message = test_message(b"waited\n");
+    message.enqueued_at = Some(std::time::Instant::now() - Duration::from_millis(50));
+    tx.send(FileWriterCommand::Write(message)).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown_tx.send(()).unwrap();
+    listen_handle.await.unwrap().unwrap();
+
+    let output = jon_listen::metrics::get_handle().unwrap().render();
+    assert!(output.contains("writer_queue_wait_seconds_count{writer=\"wait_spec\"} 1"));
+    let sum: f64 = output
+        .lines()
+        .find_map(|line| line.strip_prefix("writer_queue_wait_seconds_sum{writer=\"wait_spec\"} "))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(sum >= 0.05);
+}
+
 // Phase 6: FileWriter::start() integration tests
 
 #[tokio::test]
@@ -470,9 +571,9 @@ async fn test_file_writer_start_coordinates_with_rotation() {
 
     // Send multiple messages to verify FileWriter processes commands while rotation is active
     for i in 0..5 {
-        tx.send(FileWriterCommand::Write(
-            format!("message {}\n", i).into_bytes(),
-        ))
+        tx.send(FileWriterCommand::Write(test_message(
+            format!("message {}\n", i).as_bytes(),
+        )))
         .await
         .unwrap();
     }
@@ -535,3 +636,739 @@ async fn test_file_writer_start_coordinates_with_rotation() {
         "File should contain written messages after shutdown"
     );
 }
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_write_syslog_keeps_raw_message() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = create_test_file_config(&temp_dir, false, false);
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    let message = test_syslog_message(b"<34>Oct 11 22:14:15 mymachine su: failed");
+    tx.send(FileWriterCommand::Write(message)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+
+    let file_path = temp_dir.path().join("test.log");
+    let content = fs::read_to_string(&file_path).await.unwrap();
+    assert_eq!(content, "<34>Oct 11 22:14:15 mymachine su: failed\n");
+}
+
+#[tokio::test]
+async fn test_file_writer_rotate_compresses_rotated_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.rotation.compression = Compression::Gzip;
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    file_writer.write(b"before rotation\n").await.unwrap();
+
+    let rotated_path = temp_dir.path().join("test.log.0");
+    file_writer.rotate(rotated_path.clone()).await.unwrap();
+    file_writer.write(b"after rotation\n").await.unwrap();
+
+    let compressed_path = temp_dir.path().join("test.log.0.gz");
+    timeout(Duration::from_secs(2), async {
+        while !compressed_path.exists() || rotated_path.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Rotated file should be compressed in the background");
+
+    let mut content = String::new();
+    std::io::Read::read_to_string(
+        &mut flate2::read::GzDecoder::new(std::fs::File::open(&compressed_path).unwrap()),
+        &mut content,
+    )
+    .unwrap();
+    assert_eq!(content, "before rotation\n");
+    let current = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    assert_eq!(current, "after rotation\n");
+}
+
+#[tokio::test]
+async fn test_file_writer_rotate_reuses_slot_being_compressed() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.rotation.compression = Compression::Gzip;
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let rotated_path = temp_dir.path().join("test.log.0");
+    file_writer.write(b"first\n").await.unwrap();
+    file_writer.rotate(rotated_path.clone()).await.unwrap();
+    // The slot is reused right away, e.g. with a count of 1
+    file_writer.write(b"second\n").await.unwrap();
+    file_writer.rotate(rotated_path.clone()).await.unwrap();
+    file_writer.write(b"current\n").await.unwrap();
+
+    let file_names = || {
+        let mut names: Vec<String> = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| !name.ends_with(".spill"))
+            .collect();
+        names.sort();
+        names
+    };
+    timeout(Duration::from_secs(2), async {
+        while file_names() != ["test.log", "test.log.0.gz"] {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| {
+        panic!(
+            "Both rotations should be compressed, got {:?}",
+            file_names()
+        )
+    });
+
+    // The slot holds its latest file
+    let mut content = String::new();
+    std::io::Read::read_to_string(
+        &mut flate2::read::GzDecoder::new(
+            std::fs::File::open(temp_dir.path().join("test.log.0.gz")).unwrap(),
+        ),
+        &mut content,
+    )
+    .unwrap();
+    assert_eq!(content, "second\n");
+    let current = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    assert_eq!(current, "current\n");
+}
+
+#[tokio::test]
+async fn test_file_writer_start_rotates_by_size() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.rotation.policy = RotationPolicyType::BySize;
+    config.rotation.max_size = Some(32);
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+    let rotation_shutdown_rx = shutdown_tx.subscribe();
+    let handle =
+        tokio::spawn(async move { file_writer.start(shutdown_rx, rotation_shutdown_rx).await });
+
+    // 20 + 20 bytes: the second message crosses the 32 bytes threshold
+    tx.send(FileWriterCommand::Write(test_message(
+        b"0123456789abcdefghi\n",
+    )))
+    .await
+    .unwrap();
+    tx.send(FileWriterCommand::Write(test_message(
+        b"jklmnopqrstuvwxyz01\n",
+    )))
+    .await
+    .unwrap();
+
+    let rotated_path = temp_dir.path().join("test.log.0");
+    timeout(Duration::from_secs(2), async {
+        while !rotated_path.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("File should be rotated once it reaches max_size");
+
+    tx.send(FileWriterCommand::Write(test_message(b"after rotation\n")))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let rotated = fs::read_to_string(&rotated_path).await.unwrap();
+    assert_eq!(rotated, "0123456789abcdefghi\njklmnopqrstuvwxyz01\n");
+    let current = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    assert_eq!(current, "after rotation\n");
+
+    shutdown_tx.send(()).unwrap();
+    let result = timeout(Duration::from_secs(2), handle).await.unwrap();
+    assert!(result.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn test_file_writer_start_requires_max_size_for_by_size_policy() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.rotation.policy = RotationPolicyType::BySize;
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+
+    let result = timeout(
+        Duration::from_secs(1),
+        file_writer.start(shutdown_rx, shutdown_tx.subscribe()),
+    )
+    .await
+    .expect("start should fail immediately");
+    match result {
+        Err(FileWriterError::OtherError(msg)) => assert!(msg.contains("max_size")),
+        other => panic!("Expected OtherError about max_size, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_json_output() {
+    let temp_dir = TempDir::new().unwrap();
+    // Starting and ending messages on, as in config/default.toml
+    let mut config = create_test_file_config(&temp_dir, true, true);
+    config.formatting.output = OutputFormat::Json;
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let rotated_path = temp_dir.path().join("test.log.0");
+    file_writer.rotate(rotated_path.clone()).await.unwrap();
+    // Neither the rotated file nor the new one get a plain text banner
+    assert_eq!(fs::read_to_string(&rotated_path).await.unwrap(), "");
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    tx.send(FileWriterCommand::Write(test_message(b"say \"hi\"\n")))
+        .await
+        .unwrap();
+    let message = test_syslog_message(b"<34>Oct 11 22:14:15 mymachine su: failed");
+    tx.send(FileWriterCommand::Write(message)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+
+    let content = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(
+        lines.len(),
+        2,
+        "Each message should be one line: {}",
+        content
+    );
+    assert_eq!(
+        lines[0],
+        r#"{"timestamp":"2024-01-01T12:00:00.000000Z","peer":"10.0.0.1:5000","protocol":"TCP","listener_port":8080,"message":"say \"hi\""}"#
+    );
+    assert_eq!(
+        lines[1],
+        r#"{"timestamp":"2024-01-01T12:00:00.000000Z","peer":"10.0.0.1:5000","protocol":"TCP","listener_port":8080,"message":"<34>Oct 11 22:14:15 mymachine su: failed","syslog":{"facility":4,"severity":2,"timestamp":"Oct 11 22:14:15","hostname":"mymachine","app_name":"su","message":"failed"}}"#
+    );
+    for line in lines {
+        assert!(serde_json::from_str::<serde_json::Value>(line).is_ok());
+    }
+}
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_applies_prefix() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.formatting.prefix = Some("{timestamp:%Y-%m-%d %H:%M:%S} {peer_ip} ".to_string());
+    config.formatting.timezone = Timezone::Utc;
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    tx.send(FileWriterCommand::Write(test_message(b"first\n")))
+        .await
+        .unwrap();
+    tx.send(FileWriterCommand::Write(test_message(b"second")))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+
+    let content = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    assert_eq!(
+        content,
+        "2024-01-01 12:00:00 10.0.0.1 first\n2024-01-01 12:00:00 10.0.0.1 second\n"
+    );
+}
+
+#[tokio::test]
+async fn test_file_writer_new_rejects_invalid_prefix() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.formatting.prefix = Some("{nope} ".to_string());
+
+    let result = FileWriter::new(10, config).await;
+    assert!(matches!(result, Err(FileWriterError::InvalidPrefix(_))));
+}
+
+#[tokio::test]
+async fn test_file_writer_rejects_prefix_with_json_output() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.formatting.prefix = Some("{peer_ip} ".to_string());
+    config.formatting.output = OutputFormat::Json;
+
+    assert!(matches!(
+        FileWriter::validate_config(&config),
+        Err(FileWriterError::InvalidPrefix(_))
+    ));
+    let result = FileWriter::new(10, config).await;
+    assert!(matches!(result, Err(FileWriterError::InvalidPrefix(_))));
+}
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_reconfigure_applies_new_prefix() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = create_test_file_config(&temp_dir, false, false);
+
+    let mut file_writer = FileWriter::new(10, config.clone()).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    tx.send(FileWriterCommand::Write(test_message(b"before")))
+        .await
+        .unwrap();
+    let mut reloaded = config.clone();
+    reloaded.filename = "ignored.log".to_string();
+    reloaded.formatting.prefix = Some("{peer_ip} ".to_string());
+    tx.send(FileWriterCommand::Reconfigure(Box::new(reloaded)))
+        .await
+        .unwrap();
+    tx.send(FileWriterCommand::Write(test_message(b"after")))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+
+    let content = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    assert_eq!(content, "before\n10.0.0.1 after\n");
+    assert!(!temp_dir.path().join("ignored.log").exists());
+}
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_reconfigure_keeps_config_when_invalid() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.formatting.prefix = Some("> ".to_string());
+
+    let mut file_writer = FileWriter::new(10, config.clone()).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    let mut reloaded = config.clone();
+    reloaded.formatting.prefix = Some("{nope} ".to_string());
+    tx.send(FileWriterCommand::Reconfigure(Box::new(reloaded)))
+        .await
+        .unwrap();
+    tx.send(FileWriterCommand::Write(test_message(b"still here")))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+
+    let content = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    assert_eq!(content, "> still here\n");
+}
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_reopen_after_external_rename() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = create_test_file_config(&temp_dir, false, false);
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    tx.send(FileWriterCommand::Write(test_message(b"before logrotate")))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // What logrotate does before signaling the server
+    let moved_path = temp_dir.path().join("test.log.1");
+    fs::rename(temp_dir.path().join("test.log"), &moved_path)
+        .await
+        .unwrap();
+    tx.send(FileWriterCommand::Reopen).await.unwrap();
+    tx.send(FileWriterCommand::Write(test_message(b"after logrotate")))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+
+    let moved = fs::read_to_string(&moved_path).await.unwrap();
+    assert_eq!(moved, "before logrotate\n");
+    let current = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    assert_eq!(current, "after logrotate\n");
+}
+
+#[tokio::test]
+async fn test_file_writer_start_with_external_rotation_never_rotates() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.rotation.policy = RotationPolicyType::External;
+    config.rotation.duration = None;
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+    let rotation_shutdown_rx = shutdown_tx.subscribe();
+    let handle =
+        tokio::spawn(async move { file_writer.start(shutdown_rx, rotation_shutdown_rx).await });
+
+    tx.send(FileWriterCommand::Write(test_message(b"hello")))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    shutdown_tx.send(()).unwrap();
+    let result = timeout(Duration::from_secs(2), handle).await.unwrap();
+    assert!(result.unwrap().is_ok());
+
+    let content = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    assert_eq!(content, "hello\n");
+    assert!(!temp_dir.path().join("test.log.0").exists());
+}
+
+#[tokio::test]
+async fn test_file_writer_start_rejects_max_size_with_external_rotation() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.rotation.policy = RotationPolicyType::External;
+    config.rotation.max_size = Some(1024);
+
+    let result = FileWriter::validate_config(&config);
+    match result {
+        Err(FileWriterError::OtherError(msg)) => assert!(msg.contains("External")),
+        other => panic!("Expected OtherError about External, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_buffers_until_flush_interval() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.buffer.flush_interval_ms = 300;
+    let file_path = temp_dir.path().join("test.log");
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    tx.send(FileWriterCommand::Write(test_message(b"first")))
+        .await
+        .unwrap();
+    tx.send(FileWriterCommand::Write(test_message(b"second")))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    let content = fs::read_to_string(&file_path).await.unwrap();
+    assert_eq!(content, "", "Messages should wait in the buffer");
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    let content = fs::read_to_string(&file_path).await.unwrap();
+    assert_eq!(content, "first\nsecond\n");
+
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_flushes_full_buffer() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.buffer.size = 16;
+    config.buffer.flush_interval_ms = 60_000;
+    let file_path = temp_dir.path().join("test.log");
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    tx.send(FileWriterCommand::Write(test_message(b"short")))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(fs::read_to_string(&file_path).await.unwrap(), "");
+
+    tx.send(FileWriterCommand::Write(test_message(b"fills the buffer")))
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(
+        fs::read_to_string(&file_path).await.unwrap(),
+        "short\nfills the buffer\n"
+    );
+
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_flushes_buffer_on_rotation_and_shutdown() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.buffer.flush_interval_ms = 60_000;
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    let rotated_path = temp_dir.path().join("test.log.0");
+    tx.send(FileWriterCommand::Write(test_message(b"before rotation")))
+        .await
+        .unwrap();
+    tx.send(FileWriterCommand::Rename(rotated_path.clone()))
+        .await
+        .unwrap();
+    tx.send(FileWriterCommand::Write(test_message(b"after rotation")))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+
+    let rotated = fs::read_to_string(&rotated_path).await.unwrap();
+    assert_eq!(rotated, "before rotation\n");
+    let current = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    assert_eq!(current, "after rotation\n");
+}
+
+/// Write two messages with the given durability and return the file content
+async fn write_with_durability(durability: DurabilityConfig) -> String {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, false);
+    config.durability = durability;
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+
+    tx.send(FileWriterCommand::Write(test_message(b"first")))
+        .await
+        .unwrap();
+    tx.send(FileWriterCommand::Write(test_message(b"second")))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+
+    fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_writes_with_every_durability() {
+    for mode in [
+        Durability::None,
+        Durability::Interval,
+        Durability::Batch,
+        Durability::Message,
+    ] {
+        let content = write_with_durability(DurabilityConfig {
+            mode: mode.clone(),
+            interval_ms: 20,
+        })
+        .await;
+        assert_eq!(content, "first\nsecond\n", "durability {:?}", mode);
+    }
+}
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_drains_queue_on_shutdown() {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = create_test_file_config(&temp_dir, false, true);
+    config.buffer.size = 0;
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+    for i in 0..5 {
+        tx.send(FileWriterCommand::Write(test_message(
+            format!("queued {}", i).as_bytes(),
+        )))
+        .await
+        .unwrap();
+    }
+
+    // Shutdown is already pending when the writer starts listening
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+    shutdown_tx.send(()).unwrap();
+    let result = timeout(
+        Duration::from_secs(1),
+        file_writer.listen_commands(&mut shutdown_rx),
+    )
+    .await
+    .expect("Writer should stop once the queue is drained");
+    assert!(result.is_ok());
+
+    let content = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    let lines: Vec<&str> = content.lines().collect();
+    assert_eq!(
+        &lines[..5],
+        &["queued 0", "queued 1", "queued 2", "queued 3", "queued 4"]
+    );
+    assert!(lines[5].starts_with("Ending "), "got {:?}", lines[5]);
+    assert_eq!(lines.len(), 6);
+}
+
+#[tokio::test]
+async fn test_file_writer_counts_lost_messages_of_batches() {
+    fn lost_at_shutdown() -> f64 {
+        jon_listen::metrics::get_handle()
+            .unwrap()
+            .render()
+            .lines()
+            .find_map(|line| line.strip_prefix("messages_lost_at_shutdown_total "))
+            .map_or(0.0, |value| value.parse().unwrap())
+    }
+
+    let _ = jon_listen::metrics::init(9121);
+    // Whether the writer takes commands before it notices the pending shutdown is up
+    // to chance, every run must account for all 5 messages either way
+    let mut batch_lost = false;
+    for _ in 0..20 {
+        let temp_dir = TempDir::new().unwrap();
+        let config = create_test_file_config(&temp_dir, false, false);
+        let mut file_writer = FileWriter::new(10, config).await.unwrap();
+        file_writer.set_drain_timeout(Duration::ZERO);
+        let tx = file_writer.tx.clone();
+        tx.send(FileWriterCommand::Write(test_message(b"first\n")))
+            .await
+            .unwrap();
+        tx.send(FileWriterCommand::WriteBatch(vec![
+            test_message(b"batch 1\n"),
+            test_message(b"batch 2\n"),
+            test_message(b"batch 3\n"),
+        ]))
+        .await
+        .unwrap();
+        tx.send(FileWriterCommand::Write(test_message(b"last\n")))
+            .await
+            .unwrap();
+
+        let before = lost_at_shutdown();
+        let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+        shutdown_tx.send(()).unwrap();
+        file_writer.listen_commands(&mut shutdown_rx).await.unwrap();
+
+        let lost = lost_at_shutdown() - before;
+        let content = fs::read_to_string(temp_dir.path().join("test.log"))
+            .await
+            .unwrap();
+        assert_eq!(lost as usize + content.lines().count(), 5, "{:?}", content);
+        batch_lost |= !content.contains("batch");
+    }
+    assert!(batch_lost, "The batch should have been lost at least once");
+}
+
+#[tokio::test]
+async fn test_file_writer_start_drains_queue_after_rotation_stops() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = create_test_file_config(&temp_dir, false, false);
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let (rotation_shutdown_tx, rotation_shutdown_rx) = broadcast::channel(1);
+    let (drain_tx, drain_rx) = broadcast::channel(1);
+    let handle =
+        tokio::spawn(async move { file_writer.start(drain_rx, rotation_shutdown_rx).await });
+
+    // Rotation stops first, the writer keeps accepting messages until it's told to drain
+    rotation_shutdown_tx.send(()).unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    tx.send(FileWriterCommand::Write(test_message(
+        b"after rotation stopped",
+    )))
+    .await
+    .unwrap();
+    drain_tx.send(()).unwrap();
+
+    let result = timeout(Duration::from_secs(2), handle).await.unwrap();
+    assert!(result.unwrap().is_ok());
+    let content = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    assert_eq!(content, "after rotation stopped\n");
+}
+
+#[tokio::test]
+async fn test_file_writer_listen_commands_replays_spilled_messages() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = create_test_file_config(&temp_dir, false, false);
+
+    let mut file_writer = FileWriter::new(10, config).await.unwrap();
+    let tx = file_writer.tx.clone();
+    let spill = file_writer.spill_queue();
+    let (shutdown_tx, mut shutdown_rx) = broadcast::channel(1);
+
+    // Queued before the channel filled up, then spilled
+    tx.send(FileWriterCommand::Write(test_message(b"queued")))
+        .await
+        .unwrap();
+    spill.push(&test_message(b"spilled 1")).await.unwrap();
+    spill.push(&test_message(b"spilled 2")).await.unwrap();
+
+    let listen_handle =
+        tokio::spawn(async move { file_writer.listen_commands(&mut shutdown_rx).await });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown_tx.send(()).unwrap();
+    assert!(listen_handle.await.unwrap().is_ok());
+
+    let content = fs::read_to_string(temp_dir.path().join("test.log"))
+        .await
+        .unwrap();
+    assert_eq!(content, "queued\nspilled 1\nspilled 2\n");
+    assert!(!spill.has_pending());
+}
@@ -0,0 +1,85 @@
+use bytes::Bytes;
+use chrono::prelude::*;
+use jon_listen::listener::syslog::SyslogMessage;
+use jon_listen::settings::{OutputFormat, ProtocolType};
+use jon_listen::writer::file_writer::Message;
+use jon_listen::writer::formatter::render;
+use serde_json::Value;
+
+fn message(payload: &[u8]) -> Message {
+    Message {
+        payload: Bytes::copy_from_slice(payload),
+        peer: "[2001:db8::1]:5000".parse().unwrap(),
+        received_at: Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap(),
+        listener: "[::]:514".parse().unwrap(),
+        protocol: ProtocolType::UDP,
+        syslog: None,
+        enqueued_at: None,
+    }
+}
+
+fn parse_line(line: &[u8]) -> Value {
+    assert_eq!(line.last(), Some(&b'\n'), "Line should end with a newline");
+    assert_eq!(
+        line.iter().filter(|b| **b == b'\n').count(),
+        1,
+        "Output should be a single line"
+    );
+    serde_json::from_slice(line).unwrap()
+}
+
+#[test]
+fn test_render_plain_appends_newline_only_when_missing() {
+    assert_eq!(
+        &*render(&OutputFormat::Plain, &message(b"hello")),
+        b"hello\n"
+    );
+    assert_eq!(
+        &*render(&OutputFormat::Plain, &message(b"hello\n")),
+        b"hello\n"
+    );
+}
+
+#[test]
+fn test_render_json_includes_metadata_and_escapes_message() {
+    let message = message(b"tab\there \"quoted\" \\ and\nnewline\r\n");
+    let json = parse_line(&render(&OutputFormat::Json, &message));
+
+    assert_eq!(json["timestamp"], "2024-01-01T12:00:00.000000Z");
+    assert_eq!(json["peer"], "[2001:db8::1]:5000");
+    assert_eq!(json["protocol"], "UDP");
+    assert_eq!(json["listener_port"], 514);
+    assert_eq!(json["message"], "tab\there \"quoted\" \\ and\nnewline");
+    assert!(json.get("syslog").is_none());
+}
+
+#[test]
+fn test_render_json_replaces_invalid_utf8() {
+    let message = message(b"bad \xff byte");
+    let json = parse_line(&render(&OutputFormat::Json, &message));
+    assert_eq!(json["message"], "bad \u{fffd} byte");
+}
+
+#[test]
+fn test_render_json_includes_syslog_fields() {
+    let raw = b"<165>1 2003-10-11T22:14:15.003Z host app 42 ID47 [ex@32473 a=\"1\" b=\"2\"] body";
+    let message = Message {
+        syslog: Some(SyslogMessage::parse(raw).unwrap()),
+        ..message(raw)
+    };
+    let json = parse_line(&render(&OutputFormat::Json, &message));
+
+    let syslog = &json["syslog"];
+    assert_eq!(syslog["facility"], 20);
+    assert_eq!(syslog["severity"], 5);
+    assert_eq!(syslog["version"], 1);
+    assert_eq!(syslog["timestamp"], "2003-10-11T22:14:15.003Z");
+    assert_eq!(syslog["hostname"], "host");
+    assert_eq!(syslog["app_name"], "app");
+    assert_eq!(syslog["proc_id"], "42");
+    assert_eq!(syslog["msg_id"], "ID47");
+    assert_eq!(syslog["structured_data"]["ex@32473"]["a"], "1");
+    assert_eq!(syslog["structured_data"]["ex@32473"]["b"], "2");
+    assert_eq!(syslog["message"], "body");
+    assert_eq!(json["message"], String::from_utf8_lossy(raw).as_ref());
+}
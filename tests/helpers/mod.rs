@@ -4,9 +4,12 @@
 //! and improve test maintainability.
 
 use jon_listen::settings::{
-    BackpressurePolicy, FileWriterConfig, FormattingConfig, ProtocolType, RotationPolicyConfig,
-    RotationPolicyType, ServerConfig, Settings,
+    BackpressurePolicy, BufferConfig, Compression, DurabilityConfig, FileWriterConfig,
+    FormattingConfig, MessageFormat, OutputFormat, OversizedPolicy, ProtocolType,
+    RotationPolicyConfig, RotationPolicyType, ServerConfig, Settings, Timezone,
+    DEFAULT_MAX_MESSAGE_SIZE,
 };
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -33,15 +36,33 @@ pub fn create_test_settings(protocol: ProtocolType) -> (Settings, TempDir) {
         host: "0.0.0.0".to_string(),
         port: 0, // Use port 0 to get any available port
         max_connections: 1000,
+        format: MessageFormat::Raw,
+        tls: None,
+        rate_limit: None,
+        allow: vec![],
+        deny: vec![],
+        idle_timeout_ms: None,
+        read_timeout_ms: None,
+        max_connection_lifetime_ms: None,
+        max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        oversized_policy: OversizedPolicy::Truncate,
+        udp_sockets: None,
+        udp_recv_buffer_size: None,
+        udp_batch_size: None,
     };
     let rotation_policy_config = RotationPolicyConfig {
         count: 10,
         policy: RotationPolicyType::ByDuration,
         duration: Some(3600),
+        max_size: None,
+        compression: Compression::None,
     };
     let formatting_config = FormattingConfig {
         startingmsg: false,
         endingmsg: false,
+        output: OutputFormat::Plain,
+        prefix: None,
+        timezone: Timezone::Local,
     };
     let file_config = FileWriterConfig {
         filedir: temp_dir.path().to_path_buf(),
@@ -49,14 +70,21 @@ pub fn create_test_settings(protocol: ProtocolType) -> (Settings, TempDir) {
         rotation: rotation_policy_config,
         formatting: formatting_config,
         backpressure_policy: BackpressurePolicy::Block,
+        buffer: BufferConfig::default(),
+        durability: DurabilityConfig::default(),
+        spill_max_size: 1024 * 1024,
     };
     let settings = Settings {
         debug: false,
         threads: 1,
         buffer_bound: 20,
         server,
+        listeners: vec![],
         filewriter: file_config,
+        writers: HashMap::new(),
+        routes: vec![],
         metrics_port: 9090,
+        drain_timeout_ms: 5000,
     };
     (settings, temp_dir)
 }
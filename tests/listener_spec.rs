@@ -1,6 +1,7 @@
-use jon_listen::listener::Listener;
+use jon_listen::listener::{Listener, ListenerControls};
 use jon_listen::settings::{BackpressurePolicy, *};
 use jon_listen::writer::file_writer::FileWriterCommand;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
@@ -17,15 +18,33 @@ fn settings_template(protocol: ProtocolType) -> Settings {
         host: "0.0.0.0".to_string(),
         port: 0,
         max_connections: 1000,
+        format: MessageFormat::Raw,
+        tls: None,
+        rate_limit: None,
+        allow: vec![],
+        deny: vec![],
+        idle_timeout_ms: None,
+        read_timeout_ms: None,
+        max_connection_lifetime_ms: None,
+        max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        oversized_policy: OversizedPolicy::Truncate,
+        udp_sockets: None,
+        udp_recv_buffer_size: None,
+        udp_batch_size: None,
     };
     let rotation_policy_config = RotationPolicyConfig {
         count: 10,
         policy: RotationPolicyType::ByDuration,
         duration: Option::default(),
+        max_size: None,
+        compression: Compression::None,
     };
     let formatting_config = FormattingConfig {
         startingmsg: false,
         endingmsg: false,
+        output: OutputFormat::Plain,
+        prefix: None,
+        timezone: Timezone::Local,
     };
     let file_config = FileWriterConfig {
         filedir: PathBuf::from(r"/tmp/"),
@@ -33,20 +52,28 @@ fn settings_template(protocol: ProtocolType) -> Settings {
         rotation: rotation_policy_config,
         formatting: formatting_config,
         backpressure_policy: BackpressurePolicy::Block,
+        buffer: BufferConfig::default(),
+        durability: DurabilityConfig::default(),
+        spill_max_size: 1024 * 1024,
     };
     Settings {
         debug: false,
         threads: 1,
         buffer_bound: 20,
         server,
+        listeners: vec![],
         filewriter: file_config,
+        writers: HashMap::new(),
+        routes: vec![],
         metrics_port: 9090,
+        drain_timeout_ms: 5000,
     }
 }
 
 #[tokio::test]
 async fn test_listener_routes_to_tcp_server() {
     let tcp_settings = Arc::new(settings_template(ProtocolType::TCP));
+    let tcp_server = tcp_settings.server.clone();
     let (tcp_tx, _tcp_rx) = mpsc::channel::<FileWriterCommand>(10);
     let tcp_sender = jon_listen::writer::backpressure::BackpressureAwareSender::new(
         tcp_tx,
@@ -54,10 +81,10 @@ async fn test_listener_routes_to_tcp_server() {
     );
     let (tcp_shutdown_tx, tcp_shutdown_rx) = broadcast::channel::<()>(1);
 
-    let tcp_listener_handle =
-        tokio::spawn(
-            async move { Listener::start(tcp_settings, tcp_sender, tcp_shutdown_rx).await },
-        );
+    let controls = ListenerControls::for_server(&tcp_server, tcp_shutdown_rx).unwrap();
+    let tcp_listener_handle = tokio::spawn(async move {
+        Listener::start(tcp_settings, tcp_server, tcp_sender.into(), controls).await
+    });
 
     tokio::time::sleep(Duration::from_millis(50)).await;
 
@@ -85,6 +112,7 @@ async fn test_listener_routes_to_tcp_server() {
 #[tokio::test]
 async fn test_listener_routes_to_udp_server() {
     let udp_settings = Arc::new(settings_template(ProtocolType::UDP));
+    let udp_server = udp_settings.server.clone();
     let (udp_tx, _udp_rx) = mpsc::channel::<FileWriterCommand>(10);
     let udp_sender = jon_listen::writer::backpressure::BackpressureAwareSender::new(
         udp_tx,
@@ -92,10 +120,10 @@ async fn test_listener_routes_to_udp_server() {
     );
     let (udp_shutdown_tx, udp_shutdown_rx) = broadcast::channel::<()>(1);
 
-    let udp_listener_handle =
-        tokio::spawn(
-            async move { Listener::start(udp_settings, udp_sender, udp_shutdown_rx).await },
-        );
+    let controls = ListenerControls::for_server(&udp_server, udp_shutdown_rx).unwrap();
+    let udp_listener_handle = tokio::spawn(async move {
+        Listener::start(udp_settings, udp_server, udp_sender.into(), controls).await
+    });
 
     tokio::time::sleep(Duration::from_millis(50)).await;
 
@@ -123,6 +151,7 @@ async fn test_listener_routes_to_udp_server() {
 #[tokio::test]
 async fn test_listener_passes_shutdown_receiver_correctly() {
     let tcp_settings = Arc::new(settings_template(ProtocolType::TCP));
+    let tcp_server = tcp_settings.server.clone();
     let (tcp_tx, _tcp_rx) = mpsc::channel::<FileWriterCommand>(10);
     let tcp_sender = jon_listen::writer::backpressure::BackpressureAwareSender::new(
         tcp_tx,
@@ -130,8 +159,10 @@ async fn test_listener_passes_shutdown_receiver_correctly() {
     );
     let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
 
-    let listener_handle =
-        tokio::spawn(async move { Listener::start(tcp_settings, tcp_sender, shutdown_rx).await });
+    let controls = ListenerControls::for_server(&tcp_server, shutdown_rx).unwrap();
+    let listener_handle = tokio::spawn(async move {
+        Listener::start(tcp_settings, tcp_server, tcp_sender.into(), controls).await
+    });
 
     tokio::time::sleep(Duration::from_millis(100)).await;
 
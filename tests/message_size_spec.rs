@@ -0,0 +1,169 @@
+use bytes::{Bytes, BytesMut};
+use jon_listen::listener::message_size::{LineFramer, RawCodec, SizeLimit, TRUNCATED_MARKER};
+use jon_listen::settings::OversizedPolicy;
+use tokio_util::codec::Decoder;
+
+fn limit(max: usize, policy: OversizedPolicy) -> SizeLimit {
+    SizeLimit { max, policy }
+}
+
+fn truncated(kept: &str) -> Bytes {
+    [kept.as_bytes(), TRUNCATED_MARKER].concat().into()
+}
+
+fn apply(limit: &SizeLimit, payload: &'static [u8]) -> Vec<Bytes> {
+    limit.apply(Bytes::from_static(payload)).collect()
+}
+
+fn decode_all(codec: &mut RawCodec, input: &[u8]) -> Vec<Bytes> {
+    let mut buf = BytesMut::from(input);
+    let mut lines = vec![];
+    while let Some(line) = codec.decode(&mut buf).unwrap() {
+        lines.push(line);
+    }
+    while let Some(line) = codec.decode_eof(&mut buf).unwrap() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[test]
+fn test_size_limit_keeps_messages_within_the_limit() {
+    let limit = limit(5, OversizedPolicy::Drop);
+
+    assert_eq!(apply(&limit, b"12345"), vec![&b"12345"[..]]);
+    assert_eq!(apply(&limit, b""), vec![&b""[..]]);
+}
+
+#[test]
+fn test_size_limit_applies_policy_to_oversized_messages() {
+    let payload = b"123456789012";
+
+    assert_eq!(
+        apply(&limit(5, OversizedPolicy::Truncate), payload),
+        vec![truncated("12345")]
+    );
+    assert_eq!(
+        apply(&limit(5, OversizedPolicy::Split), payload),
+        vec![&b"12345"[..], &b"67890"[..], &b"12"[..]]
+    );
+    assert!(apply(&limit(5, OversizedPolicy::Drop), payload).is_empty());
+}
+
+#[test]
+fn test_raw_codec_truncates_oversized_lines() {
+    let mut codec = RawCodec::new(limit(5, OversizedPolicy::Truncate));
+
+    assert_eq!(
+        decode_all(&mut codec, b"12345\n1234567890\nok\n"),
+        vec![Bytes::from("12345"), truncated("12345"), Bytes::from("ok")]
+    );
+}
+
+#[test]
+fn test_raw_codec_splits_oversized_lines() {
+    let mut codec = RawCodec::new(limit(5, OversizedPolicy::Split));
+
+    // A line that is a multiple of the limit doesn't yield an empty last part
+    assert_eq!(
+        decode_all(&mut codec, b"1234567\n1234567890\nok\n"),
+        vec!["12345", "67", "12345", "67890", "ok"]
+    );
+}
+
+#[test]
+fn test_raw_codec_drops_oversized_lines() {
+    let mut codec = RawCodec::new(limit(5, OversizedPolicy::Drop));
+
+    assert_eq!(
+        decode_all(&mut codec, b"one\n1234567890\r\ntwo\r\n"),
+        vec!["one", "two"]
+    );
+}
+
+#[test]
+fn test_raw_codec_handles_oversized_lines_across_reads() {
+    let mut codec = RawCodec::new(limit(5, OversizedPolicy::Truncate));
+    let mut buf = BytesMut::from(&b"1234"[..]);
+
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    buf.extend_from_slice(b"5678");
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(truncated("12345")));
+    // The rest of the line is skipped as it arrives
+    buf.extend_from_slice(b"90123");
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    assert!(buf.len() <= 5);
+    buf.extend_from_slice(b"\nnext\n");
+    assert_eq!(codec.decode(&mut buf).unwrap(), Some(Bytes::from("next")));
+}
+
+#[test]
+fn test_raw_codec_keeps_invalid_utf8_as_received() {
+    let mut codec = RawCodec::new(SizeLimit::default());
+
+    assert_eq!(decode_all(&mut codec, b"a\xffb\n"), vec![&b"a\xffb"[..]]);
+}
+
+#[test]
+fn test_line_framer_flushes_last_line_at_eof() {
+    let mut framer = LineFramer::new(limit(5, OversizedPolicy::Drop));
+
+    let mut buf = BytesMut::from(&b"last\r"[..]);
+    assert_eq!(framer.decode(&mut buf), None);
+    assert!(framer.in_progress());
+    assert_eq!(framer.decode_eof(&mut buf), Some(Bytes::from("last")));
+    assert!(!framer.in_progress());
+
+    // The unterminated end of a dropped line is discarded
+    let mut buf = BytesMut::from(&b"1234567"[..]);
+    assert_eq!(framer.decode_eof(&mut buf), None);
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_size_limit_rejects_zero_max_message_size() {
+    let mut server = jon_listen::settings::ServerConfig {
+        protocol: jon_listen::settings::ProtocolType::TCP,
+        host: "127.0.0.1".to_string(),
+        port: 0,
+        max_connections: 10,
+        format: jon_listen::settings::MessageFormat::Raw,
+        tls: None,
+        rate_limit: None,
+        allow: vec![],
+        deny: vec![],
+        idle_timeout_ms: None,
+        read_timeout_ms: None,
+        max_connection_lifetime_ms: None,
+        max_message_size: 0,
+        oversized_policy: OversizedPolicy::Split,
+        udp_sockets: None,
+        udp_recv_buffer_size: None,
+        udp_batch_size: None,
+    };
+
+    assert!(SizeLimit::for_server(&server).is_err());
+    server.max_message_size = 100;
+    let limit = SizeLimit::for_server(&server).unwrap();
+    assert_eq!(limit.max, 100);
+    assert_eq!(limit.policy, OversizedPolicy::Split);
+}
+
+#[test]
+fn test_parts_and_lines_share_the_received_buffer() {
+    let payload = Bytes::from_static(b"1234567890");
+    let parts: Vec<_> = limit(5, OversizedPolicy::Split)
+        .apply(payload.clone())
+        .collect();
+    assert_eq!(parts[0].as_ptr(), payload.as_ptr());
+    assert_eq!(parts[1].as_ptr(), payload[5..].as_ptr());
+
+    let mut codec = RawCodec::new(SizeLimit::default());
+    let mut buf = BytesMut::from(&b"one\ntwo\n"[..]);
+    let start = buf.as_ptr();
+    assert_eq!(codec.decode(&mut buf).unwrap().unwrap().as_ptr(), start);
+    assert_eq!(
+        codec.decode(&mut buf).unwrap().unwrap().as_ptr(),
+        start.wrapping_add(4)
+    );
+}
@@ -236,6 +236,30 @@ fn test_metrics_backpressure_event_increments_counter() {
     );
 }
 
+#[test]
+fn test_metrics_backpressure_policy_counters() {
+    let _ = metrics::init(9101);
+
+    jon_listen::writer::metrics::backpressure::discarded(1);
+    jon_listen::writer::metrics::backpressure::dropped_oldest(1);
+    jon_listen::writer::metrics::backpressure::sampled(true);
+    jon_listen::writer::metrics::backpressure::sampled(false);
+
+    let output = metrics::get_handle().unwrap().render();
+    for counter in [
+        "backpressure_discarded_total",
+        "backpressure_dropped_oldest_total",
+        "backpressure_sampled_kept_total",
+        "backpressure_sampled_out_total",
+    ] {
+        assert!(
+            output.contains(counter),
+            "Metrics output should contain {}",
+            counter
+        );
+    }
+}
+
 #[test]
 fn test_metrics_file_write_record_latency() {
     let _ = metrics::init(9102);
@@ -336,3 +360,60 @@ fn test_metrics_handle_render_format() {
         "Metrics output should contain at least one of the recorded metric names"
     );
 }
+
+#[test]
+fn test_metrics_fsync_record_latency() {
+    let _ = metrics::init(9107);
+
+    jon_listen::writer::metrics::fsync::record_latency(Duration::from_millis(5));
+
+    let handle = metrics::get_handle();
+    assert!(
+        handle.is_some(),
+        "Handle should be available for metrics verification"
+    );
+    let output = handle.unwrap().render();
+    assert!(
+        output.contains("file_fsync_latency_seconds"),
+        "Metrics output should contain file_fsync_latency_seconds histogram"
+    );
+}
+
+#[tokio::test]
+async fn test_metrics_writer_queue_tracks_depth_and_capacity() {
+    use jon_listen::settings::BackpressurePolicy;
+    use jon_listen::writer::backpressure::BackpressureAwareSender;
+    use jon_listen::writer::file_writer::{FileWriterCommand, Message};
+    use jon_listen::writer::queue::CommandQueue;
+
+    let _ = metrics::init(9110);
+    let handle = metrics::get_handle().unwrap();
+
+    let (tx, rx) = tokio::sync::mpsc::channel(8);
+    let mut queue = CommandQueue::new(rx);
+    queue.set_writer("queue_spec".into());
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block)
+        .with_command_queue(queue.clone());
+    let message = Message::new(
+        &b"queued"[..],
+        "127.0.0.1:40000".parse().unwrap(),
+        "127.0.0.1:8080".parse().unwrap(),
+        jon_listen::settings::ProtocolType::UDP,
+    );
+    sender
+        .send(FileWriterCommand::Write(message.clone()))
+        .await
+        .unwrap();
+    sender
+        .send(FileWriterCommand::Write(message))
+        .await
+        .unwrap();
+
+    let output = handle.render();
+    assert!(output.contains("writer_queue_capacity{writer=\"queue_spec\"} 8"));
+    assert!(output.contains("writer_queue_depth{writer=\"queue_spec\"} 2"));
+
+    queue.recv().await.unwrap();
+    let output = handle.render();
+    assert!(output.contains("writer_queue_depth{writer=\"queue_spec\"} 1"));
+}
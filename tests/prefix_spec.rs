@@ -0,0 +1,94 @@
+use bytes::Bytes;
+use chrono::prelude::*;
+use jon_listen::error::FileWriterError;
+use jon_listen::settings::{ProtocolType, Timezone};
+use jon_listen::writer::file_writer::Message;
+use jon_listen::writer::prefix::PrefixTemplate;
+
+fn message(peer: &str) -> Message {
+    Message {
+        payload: Bytes::from_static(
+            b"hello
+",
+        ),
+        peer: peer.parse().unwrap(),
+        received_at: Utc.with_ymd_and_hms(2024, 3, 1, 8, 30, 15).unwrap(),
+        listener: "0.0.0.0:5514".parse().unwrap(),
+        protocol: ProtocolType::TCP,
+        syslog: None,
+        enqueued_at: None,
+    }
+}
+
+fn render(template: &str, timezone: Timezone, message: &Message) -> String {
+    let prefix = PrefixTemplate::parse(template, timezone).unwrap();
+    let mut out = Vec::new();
+    prefix.render(message, &mut out);
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn test_prefix_renders_address_placeholders() {
+    let message = message("192.168.1.20:40001");
+    assert_eq!(
+        render(
+            "{peer} {peer_ip} {peer_port} {listener} {listener_port} {protocol} ",
+            Timezone::Utc,
+            &message
+        ),
+        "192.168.1.20:40001 192.168.1.20 40001 0.0.0.0:5514 5514 TCP "
+    );
+}
+
+#[test]
+fn test_prefix_renders_utc_timestamps() {
+    let message = message("10.0.0.1:5000");
+    assert_eq!(
+        render("{timestamp_rfc3339}", Timezone::Utc, &message),
+        "2024-03-01T08:30:15.000Z"
+    );
+    assert_eq!(
+        render("[{timestamp:%Y-%m-%d %H:%M:%S}] ", Timezone::Utc, &message),
+        "[2024-03-01 08:30:15] "
+    );
+}
+
+#[test]
+fn test_prefix_renders_local_timestamps() {
+    let message = message("10.0.0.1:5000");
+    let local = message.received_at.with_timezone(&Local);
+    assert_eq!(
+        render("{timestamp_rfc3339}", Timezone::Local, &message),
+        local.to_rfc3339_opts(SecondsFormat::Millis, false)
+    );
+    assert_eq!(
+        render("{timestamp:%b %e %H:%M:%S}", Timezone::Local, &message),
+        local.format("%b %e %H:%M:%S").to_string()
+    );
+}
+
+#[test]
+fn test_prefix_escapes_braces() {
+    let message = message("10.0.0.1:5000");
+    assert_eq!(render("{{{protocol}}} ", Timezone::Utc, &message), "{TCP} ");
+}
+
+#[test]
+fn test_prefix_rejects_invalid_templates() {
+    for template in [
+        "{unknown}",
+        "{peer",
+        "peer}",
+        "{timestamp:%Q}",
+        "{timestamp}",
+    ] {
+        assert!(
+            matches!(
+                PrefixTemplate::parse(template, Timezone::Utc),
+                Err(FileWriterError::InvalidPrefix(_))
+            ),
+            "Template {:?} should be rejected",
+            template
+        );
+    }
+}
@@ -0,0 +1,167 @@
+use jon_listen::error::RateLimitError;
+use jon_listen::listener::rate_limit::RateLimiter;
+use jon_listen::settings::{RateLimitAction, RateLimitConfig, RateLimitOverride};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+fn config(rate: f64, burst: f64, action: RateLimitAction) -> RateLimitConfig {
+    RateLimitConfig {
+        rate,
+        burst: Some(burst),
+        action,
+        overrides: vec![],
+    }
+}
+
+fn ip(addr: &str) -> IpAddr {
+    addr.parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_drops_messages_above_burst() {
+    let limiter = RateLimiter::new("test", &config(1.0, 3.0, RateLimitAction::Drop)).unwrap();
+
+    for _ in 0..3 {
+        assert!(limiter.admit(ip("10.0.0.1")).await);
+    }
+    assert!(!limiter.admit(ip("10.0.0.1")).await);
+}
+
+#[tokio::test]
+async fn test_limits_each_peer_separately() {
+    let limiter = RateLimiter::new("test", &config(1.0, 1.0, RateLimitAction::Drop)).unwrap();
+
+    assert!(limiter.admit(ip("10.0.0.1")).await);
+    assert!(!limiter.admit(ip("10.0.0.1")).await);
+    assert!(limiter.admit(ip("10.0.0.2")).await);
+}
+
+#[tokio::test]
+async fn test_refills_tokens_at_the_rate() {
+    let limiter = RateLimiter::new("test", &config(20.0, 1.0, RateLimitAction::Drop)).unwrap();
+
+    assert!(limiter.admit(ip("10.0.0.1")).await);
+    assert!(!limiter.admit(ip("10.0.0.1")).await);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert!(limiter.admit(ip("10.0.0.1")).await);
+}
+
+#[tokio::test]
+async fn test_overrides_apply_to_matching_networks() {
+    let mut config = config(1.0, 1.0, RateLimitAction::Drop);
+    config.overrides = vec![RateLimitOverride {
+        cidr: "192.168.0.0/16".to_string(),
+        rate: 100.0,
+        burst: None,
+    }];
+    let limiter = RateLimiter::new("test", &config).unwrap();
+
+    for _ in 0..100 {
+        assert!(limiter.admit(ip("192.168.1.10")).await);
+    }
+    assert!(limiter.admit(ip("10.0.0.1")).await);
+    assert!(!limiter.admit(ip("10.0.0.1")).await);
+}
+
+#[tokio::test]
+async fn test_delay_waits_for_a_token() {
+    let limiter = RateLimiter::new("test", &config(10.0, 1.0, RateLimitAction::Delay)).unwrap();
+
+    let start = Instant::now();
+    for _ in 0..3 {
+        assert!(limiter.admit(ip("10.0.0.1")).await);
+    }
+    // The first message uses the burst, the next two wait 100ms each
+    assert!(start.elapsed() >= Duration::from_millis(190));
+}
+
+#[test]
+fn test_rejects_invalid_configuration() {
+    let mut invalid_cidr = config(1.0, 1.0, RateLimitAction::Drop);
+    invalid_cidr.overrides = vec![RateLimitOverride {
+        cidr: "not a network".to_string(),
+        rate: 1.0,
+        burst: None,
+    }];
+    assert_eq!(
+        RateLimiter::new("test", &invalid_cidr).unwrap_err(),
+        RateLimitError::InvalidCidr("not a network".to_string())
+    );
+
+    assert!(RateLimiter::new("test", &config(0.0, 1.0, RateLimitAction::Drop)).is_err());
+    assert!(RateLimiter::new("test", &config(1.0, 0.5, RateLimitAction::Drop)).is_err());
+
+    // The burst defaults to the rate, and at least one message
+    let slow = RateLimitConfig {
+        burst: None,
+        ..config(0.5, 1.0, RateLimitAction::Drop)
+    };
+    assert!(RateLimiter::new("test", &slow).is_ok());
+}
+
+#[tokio::test]
+async fn test_forgets_least_recently_seen_peer_when_full() {
+    let limiter = RateLimiter::new("test", &config(0.001, 1.0, RateLimitAction::Drop)).unwrap();
+    let peer = |n: u32| IpAddr::from(std::net::Ipv4Addr::from(0x0a00_0000 + n));
+
+    // 10 000 peers are tracked at most, each with its only token used
+    for n in 0..10_000 {
+        assert!(limiter.admit(peer(n)).await);
+    }
+    // Seen again, the first peer is no longer the least recently seen one
+    assert!(!limiter.admit(peer(0)).await);
+
+    // A new peer is admitted, the second one is forgotten to make room
+    assert!(limiter.admit(peer(10_000)).await);
+    assert!(!limiter.admit(peer(0)).await);
+    assert!(limiter.admit(peer(1)).await);
+}
+
+#[tokio::test]
+async fn test_counts_limited_messages_by_listener_and_peer() {
+    let _ = jon_listen::metrics::init(9122);
+    let limiter =
+        RateLimiter::new("127.0.0.1:5514", &config(1.0, 1.0, RateLimitAction::Drop)).unwrap();
+
+    assert!(limiter.admit(ip("10.0.0.1")).await);
+    assert!(!limiter.admit(ip("10.0.0.1")).await);
+
+    let output = jon_listen::metrics::get_handle().unwrap().render();
+    let series = output
+        .lines()
+        .find(|line| line.starts_with("rate_limited_messages_total{"))
+        .expect("rate_limited_messages_total should be exported");
+    assert!(series.contains("listener=\"127.0.0.1:5514\""), "{}", series);
+    assert!(series.contains("peer=\"10.0.0.1\""), "{}", series);
+}
+
+#[tokio::test]
+async fn test_counts_limited_peers_past_the_first_hundred_as_other() {
+    let _ = jon_listen::metrics::init(9123);
+    let limiter =
+        RateLimiter::new("127.0.0.1:5515", &config(0.001, 1.0, RateLimitAction::Drop)).unwrap();
+    let peer = |n: u32| IpAddr::from(std::net::Ipv4Addr::from(0x0b00_0000 + n));
+
+    for n in 0..101 {
+        assert!(limiter.admit(peer(n)).await);
+        assert!(!limiter.admit(peer(n)).await);
+    }
+    // Already labelled peers keep their series
+    assert!(!limiter.admit(peer(0)).await);
+
+    let output = jon_listen::metrics::get_handle().unwrap().render();
+    let series: Vec<&str> = output
+        .lines()
+        .filter(|line| {
+            line.starts_with("rate_limited_messages_total{")
+                && line.contains("listener=\"127.0.0.1:5515\"")
+        })
+        .collect();
+    assert_eq!(series.len(), 101, "{:?}", series);
+    assert!(series
+        .iter()
+        .any(|line| line.contains("peer=\"other\"") && line.ends_with(" 1")));
+    assert!(series
+        .iter()
+        .any(|line| line.contains("peer=\"11.0.0.0\"") && line.ends_with(" 2")));
+}
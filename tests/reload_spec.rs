@@ -0,0 +1,188 @@
+use jon_listen::reload::Reload;
+use jon_listen::router::DEFAULT_WRITER;
+use jon_listen::settings::*;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+fn settings_template() -> Settings {
+    let server = ServerConfig {
+        protocol: ProtocolType::TCP,
+        host: "0.0.0.0".to_string(),
+        port: 8080,
+        max_connections: 1000,
+        format: MessageFormat::Raw,
+        tls: None,
+        rate_limit: None,
+        allow: vec![],
+        deny: vec![],
+        idle_timeout_ms: None,
+        read_timeout_ms: None,
+        max_connection_lifetime_ms: None,
+        max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        oversized_policy: OversizedPolicy::Truncate,
+        udp_sockets: None,
+        udp_recv_buffer_size: None,
+        udp_batch_size: None,
+    };
+    let file_config = FileWriterConfig {
+        filedir: PathBuf::from("/tmp/"),
+        filename: "reload_test.log".to_string(),
+        rotation: RotationPolicyConfig {
+            count: 10,
+            policy: RotationPolicyType::ByDuration,
+            duration: Some(3600),
+            max_size: None,
+            compression: Compression::None,
+        },
+        formatting: FormattingConfig {
+            startingmsg: false,
+            endingmsg: false,
+            output: OutputFormat::Plain,
+            prefix: None,
+            timezone: Timezone::Local,
+        },
+        backpressure_policy: BackpressurePolicy::Block,
+        buffer: BufferConfig::default(),
+        durability: DurabilityConfig::default(),
+        spill_max_size: 1024 * 1024,
+    };
+    Settings {
+        debug: false,
+        threads: 1,
+        buffer_bound: 20,
+        server,
+        listeners: vec![],
+        filewriter: file_config,
+        writers: HashMap::new(),
+        routes: vec![],
+        metrics_port: 9090,
+        drain_timeout_ms: 5000,
+    }
+}
+
+#[test]
+fn test_reload_without_changes_does_nothing() {
+    let settings = settings_template();
+
+    let reload = Reload::plan(&settings, settings.clone()).unwrap();
+
+    assert_eq!(reload.settings, settings);
+    assert!(reload.writers.is_empty());
+    assert!(reload.listeners.is_empty());
+    assert!(reload.restart_required.is_empty());
+}
+
+#[test]
+fn test_reload_applies_writer_settings_other_than_file_location() {
+    let settings = settings_template();
+    let mut new = settings.clone();
+    new.filewriter.rotation.count = 3;
+    new.filewriter.formatting.prefix = Some("{peer_ip} ".to_string());
+    new.filewriter.backpressure_policy = BackpressurePolicy::Discard;
+    new.filewriter.buffer.flush_interval_ms = 500;
+
+    let reload = Reload::plan(&settings, new.clone()).unwrap();
+
+    assert_eq!(reload.writers, vec![DEFAULT_WRITER.to_string()]);
+    assert_eq!(reload.settings, new);
+    assert!(reload.restart_required.is_empty());
+}
+
+#[test]
+fn test_reload_applies_max_connections() {
+    let mut settings = settings_template();
+    settings.listeners = vec![ServerConfig {
+        port: 9000,
+        ..settings.server.clone()
+    }];
+    let mut new = settings.clone();
+    new.listeners[0].max_connections = 5;
+
+    let reload = Reload::plan(&settings, new).unwrap();
+
+    assert_eq!(reload.listeners, vec![1]);
+    assert_eq!(reload.settings.listeners[0].max_connections, 5);
+    assert_eq!(reload.settings.server.max_connections, 1000);
+    assert!(reload.restart_required.is_empty());
+}
+
+#[test]
+fn test_reload_applies_allow_and_deny_lists() {
+    let settings = settings_template();
+    let mut new = settings.clone();
+    new.server.allow = vec!["10.0.0.0/8".to_string()];
+    new.server.deny = vec!["10.0.0.1".to_string()];
+
+    let reload = Reload::plan(&settings, new.clone()).unwrap();
+
+    assert_eq!(reload.listeners, vec![0]);
+    assert_eq!(reload.settings, new);
+    assert!(reload.restart_required.is_empty());
+
+    let mut invalid = settings.clone();
+    invalid.server.deny = vec!["10.0.0.0/33".to_string()];
+    assert!(Reload::plan(&settings, invalid).is_err());
+}
+
+#[test]
+fn test_reload_reports_changes_requiring_a_restart() {
+    let settings = settings_template();
+    let mut new = settings.clone();
+    new.server.port = 9000;
+    new.buffer_bound = 50;
+    new.filewriter.filename = "other.log".to_string();
+    new.writers
+        .insert("errors".to_string(), settings.filewriter.clone());
+
+    let reload = Reload::plan(&settings, new).unwrap();
+
+    assert_eq!(reload.settings, settings, "nothing should be applied");
+    assert!(reload.writers.is_empty());
+    assert_eq!(
+        reload.restart_required,
+        vec![
+            "buffer_bound".to_string(),
+            "TCP listener on 0.0.0.0:8080".to_string(),
+            "new writer 'errors'".to_string(),
+            "file location of writer 'default'".to_string(),
+        ]
+    );
+}
+
+#[test]
+fn test_reload_rejects_invalid_writer_configuration() {
+    let settings = settings_template();
+
+    let mut new = settings.clone();
+    new.filewriter.formatting.prefix = Some("{nope}".to_string());
+    assert!(Reload::plan(&settings, new).is_err());
+
+    let mut new = settings.clone();
+    new.filewriter.rotation.policy = RotationPolicyType::BySize;
+    new.filewriter.rotation.max_size = None;
+    assert!(Reload::plan(&settings, new).is_err());
+}
+
+#[test]
+fn test_reload_requires_restart_to_switch_to_external_rotation() {
+    let settings = settings_template();
+    let mut new = settings.clone();
+    new.filewriter.rotation.policy = RotationPolicyType::External;
+    new.filewriter.formatting.prefix = Some("{peer_ip} ".to_string());
+
+    let reload = Reload::plan(&settings, new.clone()).unwrap();
+
+    assert_eq!(
+        reload.restart_required,
+        vec!["External rotation of writer 'default'".to_string()]
+    );
+    assert_eq!(reload.writers, vec![DEFAULT_WRITER.to_string()]);
+    assert_eq!(
+        reload.settings.filewriter.rotation,
+        settings.filewriter.rotation
+    );
+    assert_eq!(
+        reload.settings.filewriter.formatting,
+        new.filewriter.formatting
+    );
+}
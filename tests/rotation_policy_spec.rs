@@ -1,5 +1,7 @@
 use chrono::prelude::*;
-use jon_listen::writer::rotation_policy::{RotationByDay, RotationByDuration, RotationPolicy};
+use jon_listen::writer::rotation_policy::{
+    RotationByDay, RotationByDuration, RotationBySize, RotationByTimeOrSize, RotationPolicy,
+};
 use std::time::Duration;
 
 #[test]
@@ -10,7 +12,7 @@ fn test_rotation_by_duration_new() {
         .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
         .single()
         .unwrap();
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 1, 13, 0, 0)
         .single()
@@ -29,7 +31,7 @@ fn test_rotation_by_duration_next_rotation() {
         .single()
         .unwrap();
 
-    let next = policy.next_rotation(last_rotation);
+    let next = policy.next_rotation(last_rotation).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 1, 13, 0, 0)
         .single()
@@ -46,7 +48,7 @@ fn test_rotation_by_duration_various_durations() {
         .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
         .single()
         .unwrap();
-    let next = policy_30min.next_rotation(last);
+    let next = policy_30min.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 1, 12, 30, 0)
         .single()
@@ -55,7 +57,7 @@ fn test_rotation_by_duration_various_durations() {
 
     // Test 24 hours
     let policy_24h = RotationByDuration::new(Duration::from_secs(86400));
-    let next = policy_24h.next_rotation(last);
+    let next = policy_24h.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 2, 12, 0, 0)
         .single()
@@ -64,7 +66,7 @@ fn test_rotation_by_duration_various_durations() {
 
     // Test 1 second
     let policy_1s = RotationByDuration::new(Duration::from_secs(1));
-    let next = policy_1s.next_rotation(last);
+    let next = policy_1s.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 1, 12, 0, 1)
         .single()
@@ -80,7 +82,7 @@ fn test_rotation_by_duration_day_boundary() {
         .single()
         .unwrap();
 
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 2, 0, 30, 0)
         .single()
@@ -97,7 +99,7 @@ fn test_rotation_by_day_new() {
         .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
         .single()
         .unwrap();
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 2, 0, 0, 0)
         .single()
@@ -116,7 +118,7 @@ fn test_rotation_by_day_default() {
         .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
         .single()
         .unwrap();
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 2, 0, 0, 0)
         .single()
@@ -136,7 +138,7 @@ fn test_rotation_by_day_next_rotation_midday() {
         .single()
         .unwrap();
 
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 2, 0, 0, 0)
         .single()
@@ -154,7 +156,7 @@ fn test_rotation_by_day_next_rotation_just_before_midnight() {
         .single()
         .unwrap();
 
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 2, 0, 0, 0)
         .single()
@@ -172,7 +174,7 @@ fn test_rotation_by_day_next_rotation_at_midnight() {
         .single()
         .unwrap();
 
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 2, 0, 0, 0)
         .single()
@@ -190,7 +192,7 @@ fn test_rotation_by_day_next_rotation_just_after_midnight() {
         .single()
         .unwrap();
 
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 3, 0, 0, 0)
         .single()
@@ -208,7 +210,7 @@ fn test_rotation_by_day_month_boundary() {
         .single()
         .unwrap();
 
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 2, 1, 0, 0, 0)
         .single()
@@ -226,7 +228,7 @@ fn test_rotation_by_day_year_boundary() {
         .single()
         .unwrap();
 
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 1, 0, 0, 0)
         .single()
@@ -244,7 +246,7 @@ fn test_rotation_by_day_leap_year() {
         .single()
         .unwrap();
 
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 2, 29, 0, 0, 0)
         .single()
@@ -257,7 +259,7 @@ fn test_rotation_by_day_leap_year() {
         .with_ymd_and_hms(2024, 2, 29, 12, 0, 0)
         .single()
         .unwrap();
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 3, 1, 0, 0, 0)
         .single()
@@ -274,7 +276,7 @@ fn test_rotation_by_day_non_leap_year() {
         .single()
         .unwrap();
 
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2023, 3, 1, 0, 0, 0)
         .single()
@@ -291,7 +293,7 @@ fn test_rotation_policy_trait_rotation_by_duration() {
         .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
         .single()
         .unwrap();
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 1, 13, 0, 0)
         .single()
@@ -306,10 +308,47 @@ fn test_rotation_policy_trait_rotation_by_day() {
         .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
         .single()
         .unwrap();
-    let next = policy.next_rotation(last);
+    let next = policy.next_rotation(last).unwrap();
     let expected = Local
         .with_ymd_and_hms(2024, 1, 2, 0, 0, 0)
         .single()
         .unwrap();
     assert_eq!(next, expected);
 }
+
+#[test]
+fn test_rotation_by_size_never_rotates_by_time() {
+    let policy = RotationBySize::new(1024);
+    let last = Local
+        .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
+        .single()
+        .unwrap();
+
+    assert_eq!(policy.next_rotation(last), None);
+    assert_eq!(policy.max_size(), Some(1024));
+}
+
+#[test]
+fn test_time_policies_have_no_max_size() {
+    assert_eq!(
+        RotationByDuration::new(Duration::from_secs(60)).max_size(),
+        None
+    );
+    assert_eq!(RotationByDay::new().max_size(), None);
+}
+
+#[test]
+fn test_rotation_by_time_or_size_combines_both_triggers() {
+    let policy = RotationByTimeOrSize::new(Box::new(RotationByDay::new()), 1024);
+    let last = Local
+        .with_ymd_and_hms(2024, 1, 1, 12, 0, 0)
+        .single()
+        .unwrap();
+    let expected = Local
+        .with_ymd_and_hms(2024, 1, 2, 0, 0, 0)
+        .single()
+        .unwrap();
+
+    assert_eq!(policy.next_rotation(last), Some(expected));
+    assert_eq!(policy.max_size(), Some(1024));
+}
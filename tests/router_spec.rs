@@ -0,0 +1,290 @@
+use bytes::Bytes;
+use chrono::prelude::*;
+use jon_listen::error::RoutingError;
+use jon_listen::listener::syslog::SyslogMessage;
+use jon_listen::router::{Router, DEFAULT_WRITER};
+use jon_listen::settings::{BackpressurePolicy, ProtocolType, RouteConfig};
+use jon_listen::writer::backpressure::BackpressureAwareSender;
+use jon_listen::writer::file_writer::{FileWriterCommand, Message};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tokio::sync::mpsc;
+
+fn route(writer: &str) -> RouteConfig {
+    RouteConfig {
+        writer: writer.to_string(),
+        peer_cidr: None,
+        listener_port: None,
+        app_name: None,
+        pattern: None,
+    }
+}
+
+fn sender() -> (BackpressureAwareSender, mpsc::Receiver<FileWriterCommand>) {
+    let (tx, rx) = mpsc::channel(10);
+    (
+        BackpressureAwareSender::new(tx, BackpressurePolicy::Block),
+        rx,
+    )
+}
+
+/// Router with a default writer and the named writers "a" and "b"
+fn router(routes: &[RouteConfig]) -> Router {
+    let (default, _) = sender();
+    let writers = HashMap::from([("a".to_string(), sender().0), ("b".to_string(), sender().0)]);
+    Router::new(default, &writers, routes).unwrap()
+}
+
+fn message(peer: &str, listener_port: u16, payload: &str) -> Message {
+    Message {
+        payload: Bytes::copy_from_slice(payload.as_bytes()),
+        peer: peer.parse::<SocketAddr>().unwrap(),
+        received_at: Utc.timestamp_opt(0, 0).unwrap(),
+        listener: SocketAddr::from(([0, 0, 0, 0], listener_port)),
+        protocol: ProtocolType::UDP,
+        syslog: None,
+        enqueued_at: None,
+    }
+}
+
+fn from(peer: &str, listener_port: u16, payload: &str) -> FileWriterCommand {
+    FileWriterCommand::Write(message(peer, listener_port, payload))
+}
+
+fn raw(payload: &str) -> FileWriterCommand {
+    from("10.0.0.1:5000", 8080, payload)
+}
+
+fn syslog(raw: &str) -> FileWriterCommand {
+    let mut message = message("10.0.0.1:5000", 514, raw);
+    message.syslog = Some(SyslogMessage::parse(raw.as_bytes()).unwrap());
+    FileWriterCommand::Write(message)
+}
+
+#[test]
+fn test_router_without_routes_uses_default_writer() {
+    let router = router(&[]);
+    assert_eq!(
+        router.writer_for(&from("10.0.0.1:5000", 8080, "hello")),
+        DEFAULT_WRITER
+    );
+}
+
+#[test]
+fn test_router_matches_peer_cidr_and_single_address() {
+    let router = router(&[
+        RouteConfig {
+            peer_cidr: Some("10.0.0.0/8".to_string()),
+            ..route("a")
+        },
+        RouteConfig {
+            peer_cidr: Some("192.168.1.10".to_string()),
+            ..route("b")
+        },
+    ]);
+    assert_eq!(
+        router.writer_for(&from("10.1.2.3:5000", 8080, "hello")),
+        "a"
+    );
+    assert_eq!(
+        router.writer_for(&from("192.168.1.10:5000", 8080, "hello")),
+        "b"
+    );
+    assert_eq!(
+        router.writer_for(&from("192.168.1.11:5000", 8080, "hello")),
+        DEFAULT_WRITER
+    );
+}
+
+#[test]
+fn test_router_matches_listener_port() {
+    let router = router(&[RouteConfig {
+        listener_port: Some(8081),
+        ..route("a")
+    }]);
+    assert_eq!(
+        router.writer_for(&from("10.0.0.1:5000", 8081, "hello")),
+        "a"
+    );
+    assert_eq!(
+        router.writer_for(&from("10.0.0.1:5000", 8080, "hello")),
+        DEFAULT_WRITER
+    );
+}
+
+#[test]
+fn test_router_matches_syslog_app_name() {
+    let router = router(&[RouteConfig {
+        app_name: Some("nginx".to_string()),
+        ..route("a")
+    }]);
+    let nginx = syslog("<34>1 2024-01-01T00:00:00Z host nginx - - - GET /");
+    let sshd = syslog("<34>1 2024-01-01T00:00:00Z host sshd - - - login");
+
+    assert_eq!(router.writer_for(&nginx), "a");
+    assert_eq!(router.writer_for(&sshd), DEFAULT_WRITER);
+    // Raw messages carry no app name
+    assert_eq!(router.writer_for(&raw("nginx")), DEFAULT_WRITER);
+}
+
+#[test]
+fn test_router_matches_pattern_on_raw_message() {
+    let router = router(&[RouteConfig {
+        pattern: Some("^ERROR".to_string()),
+        ..route("a")
+    }]);
+    assert_eq!(router.writer_for(&raw("ERROR disk full")), "a");
+    assert_eq!(router.writer_for(&raw("INFO all good")), DEFAULT_WRITER);
+}
+
+#[test]
+fn test_router_requires_every_criterion_and_picks_first_match() {
+    let router = router(&[
+        RouteConfig {
+            peer_cidr: Some("10.0.0.0/8".to_string()),
+            pattern: Some("ERROR".to_string()),
+            ..route("a")
+        },
+        RouteConfig {
+            peer_cidr: Some("10.0.0.0/8".to_string()),
+            ..route("b")
+        },
+    ]);
+    assert_eq!(
+        router.writer_for(&from("10.0.0.1:5000", 8080, "ERROR x")),
+        "a"
+    );
+    assert_eq!(
+        router.writer_for(&from("10.0.0.1:5000", 8080, "INFO x")),
+        "b"
+    );
+    assert_eq!(
+        router.writer_for(&from("172.16.0.1:5000", 8080, "ERROR x")),
+        DEFAULT_WRITER
+    );
+}
+
+#[test]
+fn test_router_rejects_invalid_routes() {
+    let (default, _) = sender();
+    let writers = HashMap::new();
+
+    let err = Router::new(default.clone(), &writers, &[route("missing")]).err();
+    assert_eq!(
+        err,
+        Some(RoutingError::UnknownWriter("missing".to_string()))
+    );
+
+    let err = Router::new(
+        default.clone(),
+        &writers,
+        &[RouteConfig {
+            peer_cidr: Some("10.0.0.0/33".to_string()),
+            ..route(DEFAULT_WRITER)
+        }],
+    )
+    .err();
+    assert_eq!(
+        err,
+        Some(RoutingError::InvalidCidr("10.0.0.0/33".to_string()))
+    );
+
+    let err = Router::new(
+        default,
+        &writers,
+        &[RouteConfig {
+            pattern: Some("(".to_string()),
+            ..route(DEFAULT_WRITER)
+        }],
+    )
+    .err();
+    assert!(matches!(err, Some(RoutingError::InvalidPattern { .. })));
+}
+
+#[test]
+fn test_router_rejects_named_writer_called_default() {
+    let (default, _) = sender();
+    let writers = HashMap::from([(DEFAULT_WRITER.to_string(), sender().0)]);
+
+    let err = Router::new(default, &writers, &[]).err();
+    assert_eq!(
+        err,
+        Some(RoutingError::ReservedWriter(DEFAULT_WRITER.to_string()))
+    );
+}
+
+#[tokio::test]
+async fn test_router_send_dispatches_to_selected_writer() {
+    let (default, mut default_rx) = sender();
+    let (errors, mut errors_rx) = sender();
+    let writers = HashMap::from([("errors".to_string(), errors)]);
+    let router = Router::new(
+        default,
+        &writers,
+        &[RouteConfig {
+            pattern: Some("ERROR".to_string()),
+            ..route("errors")
+        }],
+    )
+    .unwrap();
+    router.send(raw("ERROR boom")).await.unwrap();
+    router.send(raw("INFO fine")).await.unwrap();
+
+    assert_eq!(errors_rx.recv().await, Some(raw("ERROR boom")));
+    assert_eq!(default_rx.recv().await, Some(raw("INFO fine")));
+    assert!(errors_rx.try_recv().is_err());
+    assert!(default_rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_router_splits_batches_by_writer() {
+    let (default, mut default_rx) = sender();
+    let (errors, mut errors_rx) = sender();
+    let writers = HashMap::from([("errors".to_string(), errors)]);
+    let router = Router::new(
+        default,
+        &writers,
+        &[RouteConfig {
+            pattern: Some("ERROR".to_string()),
+            ..route("errors")
+        }],
+    )
+    .unwrap();
+    let batch = |payloads: &[&str]| {
+        FileWriterCommand::WriteBatch(
+            payloads
+                .iter()
+                .map(|payload| message("10.0.0.1:5000", 8080, payload))
+                .collect(),
+        )
+    };
+    router
+        .send(batch(&[
+            "ERROR one",
+            "INFO two",
+            "ERROR three",
+            "INFO four",
+        ]))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        errors_rx.recv().await,
+        Some(batch(&["ERROR one", "ERROR three"]))
+    );
+    assert_eq!(
+        default_rx.recv().await,
+        Some(batch(&["INFO two", "INFO four"]))
+    );
+    assert!(errors_rx.try_recv().is_err());
+    assert!(default_rx.try_recv().is_err());
+}
+
+#[test]
+fn test_router_sends_rename_to_default_writer() {
+    let router = router(&[route("a")]);
+    assert_eq!(
+        router.writer_for(&FileWriterCommand::Rename("/tmp/x.log.1".into())),
+        DEFAULT_WRITER
+    );
+}
@@ -1,5 +1,9 @@
-use jon_listen::settings::{BackpressurePolicy, ProtocolType, RotationPolicyType, Settings};
+use jon_listen::settings::{
+    BackpressurePolicy, BufferConfig, Compression, Durability, MessageFormat, OutputFormat,
+    ProtocolType, RotationPolicyType, Settings, Timezone, TlsConfig,
+};
 use std::env;
+use std::path::PathBuf;
 use tempfile::TempDir;
 
 /// Helper to create a temporary config directory for testing
@@ -24,6 +28,7 @@ fn test_rotation_policy_type_equality() {
     );
     assert_eq!(RotationPolicyType::ByDay, RotationPolicyType::ByDay);
     assert_ne!(RotationPolicyType::ByDuration, RotationPolicyType::ByDay);
+    assert_ne!(RotationPolicyType::External, RotationPolicyType::BySize);
 }
 
 #[test]
@@ -34,6 +39,42 @@ fn test_backpressure_policy_equality() {
     assert_ne!(BackpressurePolicy::Block, BackpressurePolicy::Discard);
 }
 
+#[test]
+fn test_rate_limit_action_deserialization() {
+    use jon_listen::settings::{DeserializeWith, RateLimitAction};
+
+    let parse = |s: &str| RateLimitAction::deserialize_with(serde_json::Value::from(s));
+
+    assert_eq!(parse("Drop").unwrap(), RateLimitAction::Drop);
+    assert_eq!(parse("Delay").unwrap(), RateLimitAction::Delay);
+    assert!(parse("Wait").is_err());
+}
+
+#[test]
+fn test_oversized_policy_deserialization() {
+    use jon_listen::settings::{DeserializeWith, OversizedPolicy};
+
+    let parse = |s: &str| OversizedPolicy::deserialize_with(serde_json::Value::from(s));
+
+    assert_eq!(parse("Truncate").unwrap(), OversizedPolicy::Truncate);
+    assert_eq!(parse("Split").unwrap(), OversizedPolicy::Split);
+    assert_eq!(parse("Drop").unwrap(), OversizedPolicy::Drop);
+    assert!(parse("Reject").is_err());
+}
+
+#[test]
+fn test_backpressure_policy_deserialization() {
+    use jon_listen::settings::DeserializeWith;
+
+    let parse = |s: &str| BackpressurePolicy::deserialize_with(serde_json::Value::from(s));
+
+    assert_eq!(parse("DropOldest").unwrap(), BackpressurePolicy::DropOldest);
+    assert_eq!(parse("Sample(10)").unwrap(), BackpressurePolicy::Sample(10));
+    assert!(parse("Sample(0)").is_err());
+    assert!(parse("Sample").is_err());
+    assert!(parse("Unknown").is_err());
+}
+
 #[test]
 fn test_settings_load_from_default_config() {
     // This test requires the actual config/default.toml file to exist
@@ -190,3 +231,253 @@ endingmsg = true
     // The deserialization error tests above cover the enum validation logic
     let _ = invalid_config;
 }
+
+#[test]
+fn test_settings_all_listeners_includes_server_and_extra_listeners() {
+    let settings = Settings::load();
+
+    // Skip test if config file doesn't exist
+    let mut settings = match settings {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+
+    assert_eq!(
+        settings.all_listeners().len(),
+        1,
+        "Only the primary server should be started by default"
+    );
+
+    let mut extra = settings.server.clone();
+    extra.protocol = ProtocolType::TCP;
+    extra.port = 8081;
+    settings.listeners.push(extra);
+
+    let listeners = settings.all_listeners();
+    assert_eq!(listeners.len(), 2);
+    assert_eq!(listeners[0].port, settings.server.port);
+    assert_eq!(listeners[1].protocol, ProtocolType::TCP);
+    assert_eq!(listeners[1].port, 8081);
+}
+
+#[test]
+fn test_message_format_defaults_to_raw() {
+    let settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+
+    assert_eq!(settings.server.format, MessageFormat::Raw);
+}
+
+#[test]
+fn test_rotation_compression_defaults_to_none() {
+    let settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+
+    assert_eq!(settings.filewriter.rotation.compression, Compression::None);
+}
+
+#[test]
+fn test_output_format_defaults_to_plain() {
+    let settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+
+    assert_eq!(settings.filewriter.formatting.output, OutputFormat::Plain);
+}
+
+#[test]
+fn test_prefix_defaults_to_none_with_local_timezone() {
+    let settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+
+    assert_eq!(settings.filewriter.formatting.prefix, None);
+    assert_eq!(settings.filewriter.formatting.timezone, Timezone::Local);
+}
+
+#[test]
+fn test_buffer_defaults_to_64k_flushed_when_queue_drains() {
+    let settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+
+    assert_eq!(settings.filewriter.buffer, BufferConfig::default());
+    assert_eq!(settings.filewriter.buffer.size, 65536);
+    assert_eq!(settings.filewriter.buffer.flush_interval_ms, 0);
+}
+
+#[test]
+fn test_durability_defaults_to_none() {
+    let settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+
+    assert_eq!(settings.filewriter.durability.mode, Durability::None);
+    assert_eq!(settings.filewriter.durability.interval_ms, 1000);
+}
+
+#[test]
+fn test_drain_timeout_defaults_to_five_seconds() {
+    let settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+
+    assert_eq!(settings.drain_timeout_ms, 5000);
+}
+
+#[test]
+fn test_spill_max_size_defaults_to_1gib() {
+    let settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+
+    assert_eq!(settings.filewriter.spill_max_size, 1024 * 1024 * 1024);
+}
+
+#[test]
+fn test_max_message_size_defaults_to_64k_truncated() {
+    use jon_listen::settings::OversizedPolicy;
+
+    let settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+
+    assert_eq!(settings.server.max_message_size, 64 * 1024);
+    assert_eq!(settings.server.oversized_policy, OversizedPolicy::Truncate);
+}
+
+#[test]
+fn test_settings_validate_rejects_zero_rotation_max_size() {
+    let mut settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+    assert!(settings.validate().is_ok());
+
+    settings.filewriter.rotation.policy = RotationPolicyType::BySize;
+    settings.filewriter.rotation.max_size = Some(0);
+    let err = settings.validate().unwrap_err().to_string();
+    assert!(err.contains("max_size must be greater than 0"), "{}", err);
+
+    settings.filewriter.rotation.policy = RotationPolicyType::ByDay;
+    assert!(settings.validate().is_err());
+    settings.filewriter.rotation.max_size = Some(1024);
+    assert!(settings.validate().is_ok());
+}
+
+#[test]
+fn test_settings_validate_rejects_tls_on_udp_listener() {
+    let mut settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+    let tls = TlsConfig {
+        cert_path: PathBuf::from("/etc/jon-listen/server.pem"),
+        key_path: PathBuf::from("/etc/jon-listen/server.key"),
+        client_ca_path: None,
+    };
+
+    settings.server.protocol = ProtocolType::TCP;
+    settings.server.tls = Some(tls.clone());
+    assert!(settings.validate().is_ok());
+
+    settings.server.protocol = ProtocolType::UDP;
+    let err = settings.validate().unwrap_err().to_string();
+    assert!(
+        err.contains("TLS can't be used with the UDP listener"),
+        "{}",
+        err
+    );
+
+    settings.server.tls = None;
+    let mut udp = settings.server.clone();
+    udp.tls = Some(tls);
+    settings.listeners.push(udp);
+    assert!(settings.validate().is_err());
+}
+
+#[test]
+fn test_settings_validate_rejects_named_writer_called_default() {
+    let mut settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+    let mut named = settings.filewriter.clone();
+    named.filename = "named.log".to_string();
+    settings.writers.insert("default".to_string(), named);
+
+    let err = settings.validate().unwrap_err().to_string();
+    assert!(err.contains("'default' is reserved"), "{}", err);
+}
+
+#[test]
+fn test_settings_validate_rejects_prefix_with_json_output() {
+    let mut settings = match Settings::load() {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Skipping test: config/default.toml not found");
+            return;
+        }
+    };
+    settings.filewriter.formatting.prefix = Some("{peer_ip} ".to_string());
+    settings.filewriter.formatting.output = OutputFormat::Plain;
+    assert!(settings.validate().is_ok());
+
+    settings.filewriter.formatting.output = OutputFormat::Json;
+    let err = settings.validate().unwrap_err().to_string();
+    assert!(
+        err.contains("prefix can't be used with the Json output"),
+        "{}",
+        err
+    );
+}
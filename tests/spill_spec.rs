@@ -0,0 +1,102 @@
+use bytes::Bytes;
+use chrono::prelude::*;
+use jon_listen::listener::syslog::SyslogMessage;
+use jon_listen::settings::ProtocolType;
+use jon_listen::writer::file_writer::Message;
+use jon_listen::writer::spill::{spill_path, SpillQueue};
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+fn message(payload: &str) -> Message {
+    Message {
+        payload: Bytes::copy_from_slice(payload.as_bytes()),
+        peer: "10.0.0.1:5000".parse().unwrap(),
+        received_at: Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap()
+            + chrono::Duration::microseconds(123456),
+        listener: "[::]:8080".parse().unwrap(),
+        protocol: ProtocolType::TCP,
+        syslog: None,
+        enqueued_at: None,
+    }
+}
+
+fn queue_path(temp_dir: &TempDir) -> PathBuf {
+    spill_path(&temp_dir.path().join("test.log"))
+}
+
+#[test]
+fn test_spill_path_is_hidden_next_to_the_log_file() {
+    assert_eq!(
+        spill_path(&PathBuf::from("/var/log/app.log")),
+        PathBuf::from("/var/log/.app.log.spill")
+    );
+}
+
+#[tokio::test]
+async fn test_spill_queue_replays_messages_in_order() {
+    let temp_dir = TempDir::new().unwrap();
+    let queue = SpillQueue::open(queue_path(&temp_dir), 1024 * 1024)
+        .await
+        .unwrap();
+    assert!(!queue.has_pending());
+
+    let syslog_raw = "<34>1 2024-01-01T00:00:00Z host nginx - - - GET /";
+    let mut syslog = message(syslog_raw);
+    syslog.protocol = ProtocolType::UDP;
+    syslog.syslog = Some(SyslogMessage::parse(syslog_raw.as_bytes()).unwrap());
+
+    assert!(queue.push(&message("first")).await.unwrap());
+    assert!(queue.push(&syslog).await.unwrap());
+    assert!(queue.push(&message("third")).await.unwrap());
+    assert!(queue.has_pending());
+
+    // A small batch still returns at least one message
+    let first = queue.pop_batch(1).await.unwrap();
+    assert_eq!(first, vec![message("first")]);
+    let rest = queue.pop_batch(1024).await.unwrap();
+    assert_eq!(rest, vec![syslog, message("third")]);
+    assert!(!queue.has_pending());
+
+    // A fully replayed segment is truncated
+    let len = std::fs::metadata(queue_path(&temp_dir)).unwrap().len();
+    assert_eq!(len, 0);
+}
+
+#[tokio::test]
+async fn test_spill_queue_rejects_messages_beyond_max_size() {
+    let temp_dir = TempDir::new().unwrap();
+    let queue = SpillQueue::open(queue_path(&temp_dir), 100).await.unwrap();
+
+    assert!(queue.push(&message("fits in the queue")).await.unwrap());
+    assert!(!queue.push(&message(&"x".repeat(100))).await.unwrap());
+
+    queue.set_max_size(1024);
+    assert!(queue.push(&message(&"x".repeat(100))).await.unwrap());
+    assert_eq!(queue.pop_batch(1024).await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_spill_queue_keeps_messages_across_restarts() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let queue = SpillQueue::open(queue_path(&temp_dir), 1024 * 1024)
+            .await
+            .unwrap();
+        queue.push(&message("first")).await.unwrap();
+        queue.push(&message("second")).await.unwrap();
+    }
+    // Simulate a crash in the middle of writing a record
+    let mut content = std::fs::read(queue_path(&temp_dir)).unwrap();
+    content.extend_from_slice(&[0, 0, 0, 50, 1, 2, 3]);
+    std::fs::write(queue_path(&temp_dir), content).unwrap();
+
+    let queue = SpillQueue::open(queue_path(&temp_dir), 1024 * 1024)
+        .await
+        .unwrap();
+    assert!(queue.has_pending());
+    assert_eq!(
+        queue.pop_batch(1024).await.unwrap(),
+        vec![message("first"), message("second")]
+    );
+    assert!(!queue.has_pending());
+}
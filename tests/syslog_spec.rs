@@ -0,0 +1,462 @@
+use bytes::{Bytes, BytesMut};
+use chrono::Utc;
+use jon_listen::error::SyslogError;
+use jon_listen::listener::syslog::{
+    into_command, StructuredDataElement, SyslogCodec, SyslogMessage,
+};
+use jon_listen::listener::ListenerControls;
+use jon_listen::settings::{BackpressurePolicy, *};
+use jon_listen::writer::backpressure::BackpressureAwareSender;
+use jon_listen::writer::file_writer::{FileWriterCommand, Message};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::timeout;
+use tokio_util::codec::Decoder;
+
+fn settings_template(protocol: ProtocolType, port: i32) -> Settings {
+    let server = ServerConfig {
+        protocol,
+        host: "127.0.0.1".to_string(),
+        port,
+        max_connections: 1000,
+        format: MessageFormat::Syslog,
+        tls: None,
+        rate_limit: None,
+        allow: vec![],
+        deny: vec![],
+        idle_timeout_ms: None,
+        read_timeout_ms: None,
+        max_connection_lifetime_ms: None,
+        max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        oversized_policy: OversizedPolicy::Truncate,
+        udp_sockets: None,
+        udp_recv_buffer_size: None,
+        udp_batch_size: None,
+    };
+    let file_config = FileWriterConfig {
+        filedir: PathBuf::from(r"/tmp/"),
+        filename: "syslog_test.log".to_string(),
+        rotation: RotationPolicyConfig {
+            count: 10,
+            policy: RotationPolicyType::ByDuration,
+            duration: Option::default(),
+            max_size: None,
+            compression: Compression::None,
+        },
+        formatting: FormattingConfig {
+            startingmsg: false,
+            endingmsg: false,
+            output: OutputFormat::Plain,
+            prefix: None,
+            timezone: Timezone::Local,
+        },
+        backpressure_policy: BackpressurePolicy::Block,
+        buffer: BufferConfig::default(),
+        durability: DurabilityConfig::default(),
+        spill_max_size: 1024 * 1024,
+    };
+    Settings {
+        debug: false,
+        threads: 1,
+        buffer_bound: 20,
+        server,
+        listeners: vec![],
+        filewriter: file_config,
+        writers: HashMap::new(),
+        routes: vec![],
+        metrics_port: 9090,
+        drain_timeout_ms: 5000,
+    }
+}
+
+#[test]
+fn test_parse_rfc5424_with_structured_data() {
+    let raw = br#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="Application" eventID="1011"] An application event log entry"#;
+
+    let message = SyslogMessage::parse(raw).unwrap();
+
+    assert_eq!(message.facility, 20);
+    assert_eq!(message.severity, 5);
+    assert_eq!(message.version, Some(1));
+    assert_eq!(
+        message.timestamp.as_deref(),
+        Some("2003-10-11T22:14:15.003Z")
+    );
+    assert_eq!(message.hostname.as_deref(), Some("mymachine.example.com"));
+    assert_eq!(message.app_name.as_deref(), Some("evntslog"));
+    assert_eq!(message.proc_id, None);
+    assert_eq!(message.msg_id.as_deref(), Some("ID47"));
+    assert_eq!(
+        message.structured_data,
+        vec![StructuredDataElement {
+            id: "exampleSDID@32473".to_string(),
+            params: vec![
+                ("iut".to_string(), "3".to_string()),
+                ("eventSource".to_string(), "Application".to_string()),
+                ("eventID".to_string(), "1011".to_string()),
+            ],
+        }]
+    );
+    assert_eq!(message.message, "An application event log entry");
+}
+
+#[test]
+fn test_parse_rfc5424_nil_values_bom_and_escapes() {
+    let raw = "<34>1 - - su - - [a@1 x=\"q\\\"uo\\]te\"][b@2] \u{feff}'su root' failed".as_bytes();
+
+    let message = SyslogMessage::parse(raw).unwrap();
+
+    assert_eq!(message.facility, 4);
+    assert_eq!(message.severity, 2);
+    assert_eq!(message.timestamp, None);
+    assert_eq!(message.hostname, None);
+    assert_eq!(message.app_name.as_deref(), Some("su"));
+    assert_eq!(message.structured_data.len(), 2);
+    assert_eq!(
+        message.structured_data[0].params,
+        vec![("x".to_string(), "q\"uo]te".to_string())]
+    );
+    assert_eq!(message.structured_data[1].id, "b@2");
+    assert_eq!(message.message, "'su root' failed");
+}
+
+#[test]
+fn test_parse_rfc3164() {
+    let raw = b"<34>Oct 11 22:14:15 mymachine su: 'su root' failed for lonvick on /dev/pts/8\n";
+
+    let message = SyslogMessage::parse(raw).unwrap();
+
+    assert_eq!(message.facility, 4);
+    assert_eq!(message.severity, 2);
+    assert_eq!(message.version, None);
+    assert_eq!(message.timestamp.as_deref(), Some("Oct 11 22:14:15"));
+    assert_eq!(message.hostname.as_deref(), Some("mymachine"));
+    assert_eq!(message.app_name.as_deref(), Some("su"));
+    assert_eq!(
+        message.message,
+        "'su root' failed for lonvick on /dev/pts/8"
+    );
+    assert!(message.structured_data.is_empty());
+}
+
+#[test]
+fn test_parse_rfc3164_with_pid_and_single_digit_day() {
+    let raw = b"<13>Feb  5 17:32:18 10.0.0.99 sshd[4242]: Accepted publickey";
+
+    let message = SyslogMessage::parse(raw).unwrap();
+
+    assert_eq!(message.facility, 1);
+    assert_eq!(message.severity, 5);
+    assert_eq!(message.timestamp.as_deref(), Some("Feb  5 17:32:18"));
+    assert_eq!(message.hostname.as_deref(), Some("10.0.0.99"));
+    assert_eq!(message.app_name.as_deref(), Some("sshd"));
+    assert_eq!(message.proc_id.as_deref(), Some("4242"));
+    assert_eq!(message.message, "Accepted publickey");
+}
+
+#[test]
+fn test_parse_rfc3164_without_header_keeps_content() {
+    let raw = b"<0>Use the BFG!";
+
+    let message = SyslogMessage::parse(raw).unwrap();
+
+    assert_eq!(message.facility, 0);
+    assert_eq!(message.severity, 0);
+    assert_eq!(message.timestamp, None);
+    assert_eq!(message.hostname, None);
+    assert_eq!(message.app_name, None);
+    assert_eq!(message.message, "Use the BFG!");
+}
+
+#[test]
+fn test_parse_invalid_messages() {
+    assert_eq!(
+        SyslogMessage::parse(b"no pri here"),
+        Err(SyslogError::InvalidPri)
+    );
+    assert_eq!(
+        SyslogMessage::parse(b"<abc>msg"),
+        Err(SyslogError::InvalidPri)
+    );
+    assert_eq!(
+        SyslogMessage::parse(b"<192>msg"),
+        Err(SyslogError::PriOutOfRange(192))
+    );
+    assert!(matches!(
+        SyslogMessage::parse(b"<14>1 2003-10-11T22:14:15Z host"),
+        Err(SyslogError::InvalidHeader(_))
+    ));
+    assert!(matches!(
+        SyslogMessage::parse(b"<14>1 - - - - - [id x=\"unterminated] msg"),
+        Err(SyslogError::InvalidStructuredData(_))
+    ));
+}
+
+fn message(payload: &[u8]) -> Message {
+    Message {
+        payload: Bytes::copy_from_slice(payload),
+        peer: "127.0.0.1:5140".parse().unwrap(),
+        received_at: Utc::now(),
+        listener: "0.0.0.0:514".parse().unwrap(),
+        protocol: ProtocolType::UDP,
+        syslog: None,
+        enqueued_at: None,
+    }
+}
+
+#[test]
+fn test_into_command_falls_back_to_raw_write() {
+    let parsed = into_command(message(b"<14>Oct 11 22:14:15 host app: hi"));
+    assert!(matches!(
+        parsed,
+        FileWriterCommand::Write(Message { syslog: Some(ref m), .. }) if m.message == "hi"
+    ));
+
+    let unparsed = message(b"plain line");
+    assert_eq!(
+        into_command(unparsed.clone()),
+        FileWriterCommand::Write(unparsed)
+    );
+}
+
+#[test]
+fn test_codec_octet_counting_frames() {
+    let mut codec = SyslogCodec::new();
+    let mut buf = BytesMut::from(&b"10 <14>first\n10 <14>se"[..]);
+
+    // A frame may contain LF when octet counting is used
+    assert_eq!(
+        codec.decode(&mut buf).unwrap(),
+        Some(Bytes::from("<14>first\n"))
+    );
+    // Second frame is incomplete
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+    buf.extend_from_slice(b"cond\n");
+    assert_eq!(
+        codec.decode(&mut buf).unwrap(),
+        Some(Bytes::from("<14>second"))
+    );
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_codec_non_transparent_framing() {
+    let mut codec = SyslogCodec::new();
+    let mut buf = BytesMut::from(&b"<14>one\r\n<14>tw"[..]);
+
+    assert_eq!(
+        codec.decode(&mut buf).unwrap(),
+        Some(Bytes::from("<14>one"))
+    );
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+    buf.extend_from_slice(b"o\n");
+    assert_eq!(
+        codec.decode(&mut buf).unwrap(),
+        Some(Bytes::from("<14>two"))
+    );
+
+    // Last line without trailer is flushed at EOF
+    buf.extend_from_slice(b"<14>three");
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    assert_eq!(
+        codec.decode_eof(&mut buf).unwrap(),
+        Some(Bytes::from("<14>three"))
+    );
+}
+
+#[test]
+fn test_codec_applies_size_limit_to_octet_counted_frames() {
+    use jon_listen::listener::message_size::{SizeLimit, TRUNCATED_MARKER};
+
+    let decode_all = |policy: OversizedPolicy| {
+        let mut codec = SyslogCodec::with_limit(SizeLimit { max: 8, policy });
+        let mut buf = BytesMut::from(&b"20 <14>0123456789abcdef6 <14>ok"[..]);
+        let mut frames = vec![];
+        while let Some(frame) = codec.decode(&mut buf).unwrap() {
+            frames.push(frame);
+        }
+        assert!(buf.is_empty());
+        frames
+    };
+
+    let marker = String::from_utf8_lossy(TRUNCATED_MARKER);
+    assert_eq!(
+        decode_all(OversizedPolicy::Truncate),
+        vec![format!("<14>0123{}", marker), "<14>ok".to_string()]
+    );
+    assert_eq!(
+        decode_all(OversizedPolicy::Split),
+        vec!["<14>0123", "456789ab", "cdef", "<14>ok"]
+    );
+    assert_eq!(decode_all(OversizedPolicy::Drop), vec!["<14>ok"]);
+}
+
+#[test]
+fn test_codec_buffers_no_more_than_the_size_limit_for_a_huge_octet_count() {
+    use jon_listen::listener::message_size::SizeLimit;
+
+    for policy in [
+        OversizedPolicy::Truncate,
+        OversizedPolicy::Split,
+        OversizedPolicy::Drop,
+    ] {
+        let mut codec = SyslogCodec::with_limit(SizeLimit {
+            max: 1024,
+            policy: policy.clone(),
+        });
+        // Close to 1 GB announced, only a few bytes sent
+        let mut buf = BytesMut::from(&b"999999999 <14>hello"[..]);
+
+        let _ = codec.decode(&mut buf).unwrap();
+        assert!(
+            buf.capacity() <= 4 * 1024,
+            "{:?} reserved {} bytes",
+            policy,
+            buf.capacity()
+        );
+    }
+}
+
+#[test]
+fn test_codec_drops_octet_counted_frame_cut_short_at_eof() {
+    let mut codec = SyslogCodec::new();
+    let mut buf = BytesMut::from(&b"20 <14>short"[..]);
+
+    assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_codec_rejects_invalid_octet_count() {
+    let mut codec = SyslogCodec::new();
+
+    let mut buf = BytesMut::from(&b"12x <14>msg"[..]);
+    assert!(codec.decode(&mut buf).is_err());
+
+    let mut buf = BytesMut::from(&b"12345678901 <14>msg"[..]);
+    assert!(codec.decode(&mut buf).is_err());
+}
+
+#[tokio::test]
+async fn test_tcp_server_parses_syslog_frames() {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let settings = Arc::new(settings_template(ProtocolType::TCP, port as i32));
+    let server = settings.server.clone();
+    let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(10);
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+
+    let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
+    let server_handle = tokio::spawn(async move {
+        jon_listen::listener::tcp_server::TcpServer::start(
+            settings,
+            server,
+            sender.into(),
+            controls,
+        )
+        .await
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream
+        .write_all(b"26 <165>1 - host app - - - hi<13>Oct 11 22:14:15 host2 cron: run\n")
+        .await
+        .unwrap();
+
+    let first = timeout(Duration::from_secs(1), rx.recv()).await.unwrap();
+    match first {
+        Some(FileWriterCommand::Write(Message {
+            syslog: Some(message),
+            ..
+        })) => {
+            assert_eq!(message.version, Some(1));
+            assert_eq!(message.hostname.as_deref(), Some("host"));
+            assert_eq!(message.app_name.as_deref(), Some("app"));
+            assert_eq!(message.message, "hi");
+        }
+        other => panic!("Expected a parsed syslog message, got {:?}", other),
+    }
+    let second = timeout(Duration::from_secs(1), rx.recv()).await.unwrap();
+    match second {
+        Some(FileWriterCommand::Write(Message {
+            syslog: Some(message),
+            ..
+        })) => {
+            assert_eq!(message.version, None);
+            assert_eq!(message.hostname.as_deref(), Some("host2"));
+            assert_eq!(message.app_name.as_deref(), Some("cron"));
+            assert_eq!(message.message, "run");
+        }
+        other => panic!("Expected a parsed syslog message, got {:?}", other),
+    }
+
+    shutdown_tx.send(()).unwrap();
+    let _ = timeout(Duration::from_secs(1), server_handle).await;
+}
+
+#[tokio::test]
+async fn test_udp_service_parses_syslog_datagrams() {
+    let settings = Arc::new(settings_template(ProtocolType::UDP, 0));
+    let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(10);
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+
+    let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let server_addr = server_socket.local_addr().unwrap();
+    let mut service = jon_listen::listener::udp_server::UdpService::new(
+        server_socket,
+        sender.into(),
+        0,
+        settings.clone(),
+        settings.server.clone(),
+    );
+    let service_handle = tokio::spawn(async move { service.run(shutdown_rx).await });
+
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client
+        .send_to(
+            b"<11>1 2024-01-01T00:00:00Z web nginx 12 - - upstream down",
+            server_addr,
+        )
+        .await
+        .unwrap();
+    client.send_to(b"not syslog", server_addr).await.unwrap();
+
+    let first = timeout(Duration::from_secs(1), rx.recv()).await.unwrap();
+    match first {
+        Some(FileWriterCommand::Write(Message {
+            syslog: Some(message),
+            ..
+        })) => {
+            assert_eq!(message.facility, 1);
+            assert_eq!(message.severity, 3);
+            assert_eq!(message.app_name.as_deref(), Some("nginx"));
+            assert_eq!(message.proc_id.as_deref(), Some("12"));
+            assert_eq!(message.message, "upstream down");
+        }
+        other => panic!("Expected a parsed syslog message, got {:?}", other),
+    }
+    let second = timeout(Duration::from_secs(1), rx.recv()).await.unwrap();
+    assert!(matches!(
+        second,
+        Some(FileWriterCommand::Write(ref m))
+            if m.payload == b"not syslog"[..] && m.protocol == ProtocolType::UDP && m.syslog.is_none()
+    ));
+
+    shutdown_tx.send(()).unwrap();
+    let _ = timeout(Duration::from_secs(1), service_handle).await;
+}
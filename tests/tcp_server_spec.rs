@@ -1,8 +1,10 @@
 use log::{debug, info};
 
+use jon_listen::listener::ListenerControls;
 use jon_listen::settings::{BackpressurePolicy, *};
-use jon_listen::writer::file_writer::FileWriterCommand;
+use jon_listen::writer::file_writer::{FileWriterCommand, Message};
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -15,6 +17,15 @@ use tokio::net::TcpListener;
 use tokio::sync::mpsc;
 use tokio_util::codec::{FramedRead, LinesCodec};
 
+fn message(payload: Vec<u8>) -> Message {
+    Message::new(
+        payload,
+        "127.0.0.1:40000".parse().unwrap(),
+        "127.0.0.1:8080".parse().unwrap(),
+        ProtocolType::TCP,
+    )
+}
+
 fn settings_template() -> Settings {
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -25,15 +36,33 @@ fn settings_template() -> Settings {
         host: "0.0.0.0".to_string(),
         port: 9999,
         max_connections: 1000,
+        format: MessageFormat::Raw,
+        tls: None,
+        rate_limit: None,
+        allow: vec![],
+        deny: vec![],
+        idle_timeout_ms: None,
+        read_timeout_ms: None,
+        max_connection_lifetime_ms: None,
+        max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        oversized_policy: OversizedPolicy::Truncate,
+        udp_sockets: None,
+        udp_recv_buffer_size: None,
+        udp_batch_size: None,
     };
     let rotation_policy_config = RotationPolicyConfig {
         count: 10,
         policy: RotationPolicyType::ByDuration,
         duration: Option::default(),
+        max_size: None,
+        compression: Compression::None,
     };
     let formatting_config = FormattingConfig {
         startingmsg: false,
         endingmsg: false,
+        output: OutputFormat::Plain,
+        prefix: None,
+        timezone: Timezone::Local,
     };
     let file_config = FileWriterConfig {
         filedir: PathBuf::from(r"/tmp/"),
@@ -41,14 +70,21 @@ fn settings_template() -> Settings {
         rotation: rotation_policy_config,
         formatting: formatting_config,
         backpressure_policy: BackpressurePolicy::Block,
+        buffer: BufferConfig::default(),
+        durability: DurabilityConfig::default(),
+        spill_max_size: 1024 * 1024,
     };
     Settings {
         debug: false,
         threads: 1,
         buffer_bound: 20,
         server,
+        listeners: vec![],
         filewriter: file_config,
+        writers: HashMap::new(),
+        routes: vec![],
         metrics_port: 9090,
+        drain_timeout_ms: 5000,
     }
 }
 
@@ -72,9 +108,10 @@ fn it_receives_multiple_messages() {
             .unwrap();
         rt.block_on(async move {
             let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-            addr_tx.send(listener.local_addr().unwrap()).unwrap();
+            let local_addr = listener.local_addr().unwrap();
+            addr_tx.send(local_addr).unwrap();
             loop {
-                let (stream, _) = listener.accept().await.unwrap();
+                let (stream, peer) = listener.accept().await.unwrap();
                 let tx = file_writer_tx.clone();
                 tokio::spawn(async move {
                     let mut reader = FramedRead::new(stream, LinesCodec::new());
@@ -83,7 +120,8 @@ fn it_receives_multiple_messages() {
                             Ok(l) => {
                                 let mut v = l.into_bytes();
                                 v.push(b'\n');
-                                let _ = tx.send(FileWriterCommand::Write(v)).await;
+                                let message = Message::new(v, peer, local_addr, ProtocolType::TCP);
+                                let _ = tx.send(FileWriterCommand::Write(message)).await;
                             }
                             Err(_) => break,
                         }
@@ -126,7 +164,7 @@ fn it_receives_multiple_messages() {
             msg.to_ascii_lowercase()
         );
         assert!(received_msg.is_ok());
-        assert!(matches!(received_msg, Ok(FileWriterCommand::Write(ref v)) if v.as_slice() == msg));
+        assert!(matches!(received_msg, Ok(FileWriterCommand::Write(ref m)) if m.payload == msg));
     }
 
     info!("Received {} messages successfully", msgs.len());
@@ -163,7 +201,9 @@ mod unit_tests {
 
         // Send message through sender directly to test the flow
         sender
-            .send(FileWriterCommand::Write(test_message.clone().into_bytes()))
+            .send(FileWriterCommand::Write(message(
+                test_message.clone().into_bytes(),
+            )))
             .await
             .unwrap();
 
@@ -172,8 +212,8 @@ mod unit_tests {
         assert!(received.is_ok());
         let command = received.unwrap().unwrap();
         match command {
-            FileWriterCommand::Write(data) => {
-                assert_eq!(data, test_message.into_bytes());
+            FileWriterCommand::Write(message) => {
+                assert_eq!(message.payload, test_message.into_bytes());
             }
             _ => panic!("Expected Write command"),
         }
@@ -192,7 +232,7 @@ mod unit_tests {
         tokio::time::sleep(Duration::from_millis(10)).await;
 
         let result = sender
-            .send(FileWriterCommand::Write(test_message.into_bytes()))
+            .send(FileWriterCommand::Write(message(test_message.into_bytes())))
             .await;
 
         // Should return error when channel is closed
@@ -210,12 +250,19 @@ mod unit_tests {
         // We'll use port 0 to get any available port
         let mut test_settings = (*settings).clone();
         test_settings.server.port = 0;
+        let server = test_settings.server.clone();
         let test_settings = Arc::new(test_settings);
 
         // Spawn server task
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
         let server_handle = tokio::spawn(async move {
-            jon_listen::listener::tcp_server::TcpServer::start(test_settings, sender, shutdown_rx)
-                .await
+            jon_listen::listener::tcp_server::TcpServer::start(
+                test_settings,
+                server,
+                sender.into(),
+                controls,
+            )
+            .await
         });
 
         // Give it time to bind
@@ -237,11 +284,18 @@ mod unit_tests {
         // Start server on a random port
         let mut test_settings = (*settings).clone();
         test_settings.server.port = 0;
+        let server = test_settings.server.clone();
         let test_settings = Arc::new(test_settings);
 
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
         let server_handle = tokio::spawn(async move {
-            jon_listen::listener::tcp_server::TcpServer::start(test_settings, sender, shutdown_rx)
-                .await
+            jon_listen::listener::tcp_server::TcpServer::start(
+                test_settings,
+                server,
+                sender.into(),
+                controls,
+            )
+            .await
         });
 
         // Give server time to start
@@ -258,6 +312,233 @@ mod unit_tests {
         let _ = timeout(Duration::from_secs(1), server_handle).await;
     }
 
+    #[tokio::test]
+    async fn test_tcp_server_applies_connection_limit_changed_at_runtime() {
+        use jon_listen::listener::tcp_server::{ConnectionLimit, TcpServer};
+        use tokio::io::AsyncReadExt;
+
+        let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut test_settings = settings_template();
+        test_settings.server.host = "127.0.0.1".to_string();
+        test_settings.server.port = port as i32;
+        let server = test_settings.server.clone();
+        let connection_limit = ConnectionLimit::new(0);
+        let controls = ListenerControls {
+            connection_limit: connection_limit.clone(),
+            ..ListenerControls::for_server(&server, shutdown_rx).unwrap()
+        };
+
+        let server_handle = tokio::spawn(async move {
+            TcpServer::start(Arc::new(test_settings), server, sender.into(), controls).await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // No connection is allowed yet, the server closes it straight away
+        let mut rejected = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let mut buf = [0u8; 1];
+        let read = timeout(Duration::from_secs(1), rejected.read(&mut buf))
+            .await
+            .expect("Rejected connection should be closed");
+        assert!(matches!(read, Ok(0) | Err(_)));
+
+        connection_limit.set(1);
+        let mut accepted = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        accepted.write_all(b"hello\n").await.unwrap();
+        let received = timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .expect("Message should be forwarded once the limit is raised");
+        assert!(matches!(
+            received,
+            Some(FileWriterCommand::Write(message)) if message.payload == b"hello"[..]
+        ));
+
+        shutdown_tx.send(()).unwrap();
+        let _ = timeout(Duration::from_secs(1), server_handle).await;
+    }
+
+    #[tokio::test]
+    async fn test_tcp_server_applies_access_list_changed_at_runtime() {
+        use jon_listen::listener::access_list::AccessList;
+        use jon_listen::listener::tcp_server::TcpServer;
+        use tokio::io::AsyncReadExt;
+
+        let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut test_settings = settings_template();
+        test_settings.server.host = "127.0.0.1".to_string();
+        test_settings.server.port = port as i32;
+        let server = test_settings.server.clone();
+        let access_list = AccessList::new(&[], &["127.0.0.0/8".to_string()]).unwrap();
+        let controls = ListenerControls {
+            access_list: access_list.clone(),
+            ..ListenerControls::for_server(&server, shutdown_rx).unwrap()
+        };
+
+        let server_handle = tokio::spawn(async move {
+            TcpServer::start(Arc::new(test_settings), server, sender.into(), controls).await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Denied peers are disconnected straight away
+        let mut denied = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let mut buf = [0u8; 1];
+        let read = timeout(Duration::from_secs(1), denied.read(&mut buf))
+            .await
+            .expect("Denied connection should be closed");
+        assert!(matches!(read, Ok(0) | Err(_)));
+
+        access_list.set(&["127.0.0.1".to_string()], &[]).unwrap();
+        let mut accepted = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        accepted.write_all(b"hello\n").await.unwrap();
+        let received = timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .expect("Message should be forwarded once the peer is allowed");
+        assert!(matches!(
+            received,
+            Some(FileWriterCommand::Write(message)) if message.payload == b"hello"[..]
+        ));
+
+        shutdown_tx.send(()).unwrap();
+        let _ = timeout(Duration::from_secs(1), server_handle).await;
+    }
+
+    /// Start a TCP server on a free port with the given timeouts
+    async fn start_server_with_timeouts(
+        configure: impl FnOnce(&mut ServerConfig),
+    ) -> (
+        u16,
+        mpsc::Receiver<FileWriterCommand>,
+        broadcast::Sender<()>,
+        tokio::task::JoinHandle<std::io::Result<()>>,
+    ) {
+        use jon_listen::listener::tcp_server::TcpServer;
+
+        let (tx, rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+        let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut test_settings = settings_template();
+        test_settings.server.host = "127.0.0.1".to_string();
+        test_settings.server.port = port as i32;
+        configure(&mut test_settings.server);
+        let server = test_settings.server.clone();
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
+        let handle = tokio::spawn(async move {
+            TcpServer::start(Arc::new(test_settings), server, sender.into(), controls).await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        (port, rx, shutdown_tx, handle)
+    }
+
+    /// Time it takes the server to close the connection, reading whatever it sends
+    async fn time_until_closed(stream: &mut TcpStream) -> Duration {
+        use tokio::io::AsyncReadExt;
+
+        let start = std::time::Instant::now();
+        let mut buf = [0u8; 16];
+        loop {
+            match timeout(Duration::from_secs(2), stream.read(&mut buf)).await {
+                Ok(Ok(0)) | Ok(Err(_)) => return start.elapsed(),
+                Ok(Ok(_)) => continue,
+                Err(_) => panic!("Connection should have been closed"),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tcp_server_closes_idle_connections() {
+        let (port, mut rx, shutdown_tx, handle) =
+            start_server_with_timeouts(|server| server.idle_timeout_ms = Some(200)).await;
+
+        let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        // Sending keeps the connection open past the idle timeout
+        for _ in 0..3 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            client.write_all(b"ping\n").await.unwrap();
+        }
+        let closed_after = time_until_closed(&mut client).await;
+        assert!(closed_after >= Duration::from_millis(150));
+        for _ in 0..3 {
+            assert!(matches!(rx.recv().await, Some(FileWriterCommand::Write(_))));
+        }
+
+        shutdown_tx.send(()).unwrap();
+        let _ = timeout(Duration::from_secs(1), handle).await;
+    }
+
+    #[tokio::test]
+    async fn test_tcp_server_closes_connections_slow_to_complete_a_line() {
+        let (port, mut rx, shutdown_tx, handle) = start_server_with_timeouts(|server| {
+            server.idle_timeout_ms = Some(5000);
+            server.read_timeout_ms = Some(200);
+        })
+        .await;
+
+        let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        client.write_all(b"complete\npart").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        client.write_all(b"ial line").await.unwrap();
+        let closed_after = time_until_closed(&mut client).await;
+        assert!(closed_after < Duration::from_millis(1000));
+        assert!(matches!(
+            rx.recv().await,
+            Some(FileWriterCommand::Write(message)) if message.payload == b"complete"[..]
+        ));
+
+        shutdown_tx.send(()).unwrap();
+        let _ = timeout(Duration::from_secs(1), handle).await;
+    }
+
+    #[tokio::test]
+    async fn test_tcp_server_closes_connections_at_max_lifetime() {
+        let (port, mut rx, shutdown_tx, handle) =
+            start_server_with_timeouts(|server| server.max_connection_lifetime_ms = Some(300))
+                .await;
+        tokio::spawn(async move { while rx.recv().await.is_some() {} });
+
+        let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let writer = tokio::spawn(async move {
+            let start = std::time::Instant::now();
+            while client.write_all(b"busy\n").await.is_ok() {
+                if start.elapsed() > Duration::from_secs(2) {
+                    return Err(client);
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            Ok(())
+        });
+        let result = timeout(Duration::from_secs(3), writer)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(
+            result.is_ok(),
+            "A busy connection should be closed at its max lifetime"
+        );
+
+        shutdown_tx.send(()).unwrap();
+        let _ = timeout(Duration::from_secs(1), handle).await;
+    }
+
     #[test]
     fn test_tcp_server_rejects_when_max_connections_reached() {
         // Test the connection count logic
@@ -302,11 +583,18 @@ mod unit_tests {
 
         let mut test_settings = (*settings).clone();
         test_settings.server.port = 0;
+        let server = test_settings.server.clone();
         let test_settings = Arc::new(test_settings);
 
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
         let server_handle = tokio::spawn(async move {
-            jon_listen::listener::tcp_server::TcpServer::start(test_settings, sender, shutdown_rx)
-                .await
+            jon_listen::listener::tcp_server::TcpServer::start(
+                test_settings,
+                server,
+                sender.into(),
+                controls,
+            )
+            .await
         });
 
         // Give server time to start
@@ -322,6 +610,37 @@ mod unit_tests {
         assert!(server_result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_tcp_server_waits_for_clients_on_shutdown() {
+        let (port, mut rx, shutdown_tx, handle) = start_server_with_timeouts(|_| {}).await;
+
+        // One line more than the channel holds, its client blocks on sending it
+        let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        for i in 0..11 {
+            client
+                .write_all(format!("line {}\n", i).as_bytes())
+                .await
+                .unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown_tx.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!handle.is_finished(), "Server should wait for its clients");
+
+        assert!(rx.recv().await.is_some());
+        timeout(Duration::from_secs(1), handle)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        // Everything read was sent by the time the server returned
+        let mut received = 1;
+        while rx.try_recv().is_ok() {
+            received += 1;
+        }
+        assert_eq!(received, 11);
+    }
+
     #[tokio::test]
     async fn test_tcp_server_handle_client_reads_lines() {
         let settings = Arc::new(settings_template());
@@ -331,11 +650,18 @@ mod unit_tests {
 
         let mut test_settings = (*settings).clone();
         test_settings.server.port = 0;
+        let server = test_settings.server.clone();
         let test_settings = Arc::new(test_settings);
 
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
         let server_handle = tokio::spawn(async move {
-            jon_listen::listener::tcp_server::TcpServer::start(test_settings, sender, shutdown_rx)
-                .await
+            jon_listen::listener::tcp_server::TcpServer::start(
+                test_settings,
+                server,
+                sender.into(),
+                controls,
+            )
+            .await
         });
 
         tokio::time::sleep(Duration::from_millis(100)).await;
@@ -364,9 +690,12 @@ mod unit_tests {
             let command = received.unwrap();
             assert!(command.is_some(), "Should receive a command");
             match command.unwrap() {
-                FileWriterCommand::Write(data) => {
-                    let received_str = String::from_utf8_lossy(&data);
+                FileWriterCommand::Write(message) => {
+                    let received_str = String::from_utf8_lossy(&message.payload);
                     assert_eq!(received_str.trim(), expected_line.trim());
+                    assert_eq!(message.peer, stream.local_addr().unwrap());
+                    assert_eq!(message.listener.port(), 9999);
+                    assert_eq!(message.protocol, ProtocolType::TCP);
                 }
                 _ => panic!("Expected Write command"),
             }
@@ -385,11 +714,18 @@ mod unit_tests {
 
         let mut test_settings = (*settings).clone();
         test_settings.server.port = 0;
+        let server = test_settings.server.clone();
         let test_settings = Arc::new(test_settings);
 
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
         let server_handle = tokio::spawn(async move {
-            jon_listen::listener::tcp_server::TcpServer::start(test_settings, sender, shutdown_rx)
-                .await
+            jon_listen::listener::tcp_server::TcpServer::start(
+                test_settings,
+                server,
+                sender.into(),
+                controls,
+            )
+            .await
         });
 
         tokio::time::sleep(Duration::from_millis(100)).await;
@@ -432,11 +768,18 @@ mod unit_tests {
 
         let mut test_settings = (*settings).clone();
         test_settings.server.port = 0;
+        let server = test_settings.server.clone();
         let test_settings = Arc::new(test_settings);
 
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
         let server_handle = tokio::spawn(async move {
-            jon_listen::listener::tcp_server::TcpServer::start(test_settings, sender, shutdown_rx)
-                .await
+            jon_listen::listener::tcp_server::TcpServer::start(
+                test_settings,
+                server,
+                sender.into(),
+                controls,
+            )
+            .await
         });
 
         tokio::time::sleep(Duration::from_millis(100)).await;
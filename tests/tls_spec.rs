@@ -0,0 +1,321 @@
+use jon_listen::listener::tcp_server::TcpServer;
+use jon_listen::listener::ListenerControls;
+use jon_listen::settings::{BackpressurePolicy, *};
+use jon_listen::writer::backpressure::BackpressureAwareSender;
+use jon_listen::writer::file_writer::FileWriterCommand;
+use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::timeout;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+/// PEM-encoded certificates generated for a test run
+struct TestPki {
+    dir: TempDir,
+    ca_cert: rcgen::Certificate,
+    ca_key: KeyPair,
+}
+
+impl TestPki {
+    fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let mut params = CertificateParams::new(vec![]).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca_key = KeyPair::generate().unwrap();
+        let ca_cert = params.self_signed(&ca_key).unwrap();
+        std::fs::write(dir.path().join("ca.pem"), ca_cert.pem()).unwrap();
+        TestPki {
+            dir,
+            ca_cert,
+            ca_key,
+        }
+    }
+
+    /// Issue a certificate signed by the test CA, writing `<name>.pem` and `<name>.key`
+    fn issue(&self, name: &str, subject_alt_names: Vec<String>) -> (PathBuf, PathBuf) {
+        let key = KeyPair::generate().unwrap();
+        let cert = CertificateParams::new(subject_alt_names)
+            .unwrap()
+            .signed_by(&key, &self.ca_cert, &self.ca_key)
+            .unwrap();
+        let cert_path = self.dir.path().join(format!("{}.pem", name));
+        let key_path = self.dir.path().join(format!("{}.key", name));
+        std::fs::write(&cert_path, cert.pem()).unwrap();
+        std::fs::write(&key_path, key.serialize_pem()).unwrap();
+        (cert_path, key_path)
+    }
+
+    fn ca_path(&self) -> PathBuf {
+        self.dir.path().join("ca.pem")
+    }
+}
+
+fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+fn settings_template(port: u16, tls: TlsConfig) -> Settings {
+    let server = ServerConfig {
+        protocol: ProtocolType::TCP,
+        host: "127.0.0.1".to_string(),
+        port: port as i32,
+        max_connections: 1000,
+        format: MessageFormat::Raw,
+        tls: Some(tls),
+        rate_limit: None,
+        allow: vec![],
+        deny: vec![],
+        idle_timeout_ms: None,
+        read_timeout_ms: None,
+        max_connection_lifetime_ms: None,
+        max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        oversized_policy: OversizedPolicy::Truncate,
+        udp_sockets: None,
+        udp_recv_buffer_size: None,
+        udp_batch_size: None,
+    };
+    let file_config = FileWriterConfig {
+        filedir: PathBuf::from(r"/tmp/"),
+        filename: "tls_test.log".to_string(),
+        rotation: RotationPolicyConfig {
+            count: 10,
+            policy: RotationPolicyType::ByDuration,
+            duration: Option::default(),
+            max_size: None,
+            compression: Compression::None,
+        },
+        formatting: FormattingConfig {
+            startingmsg: false,
+            endingmsg: false,
+            output: OutputFormat::Plain,
+            prefix: None,
+            timezone: Timezone::Local,
+        },
+        backpressure_policy: BackpressurePolicy::Block,
+        buffer: BufferConfig::default(),
+        durability: DurabilityConfig::default(),
+        spill_max_size: 1024 * 1024,
+    };
+    Settings {
+        debug: false,
+        threads: 1,
+        buffer_bound: 20,
+        server,
+        listeners: vec![],
+        filewriter: file_config,
+        writers: HashMap::new(),
+        routes: vec![],
+        metrics_port: 9090,
+        drain_timeout_ms: 5000,
+    }
+}
+
+fn load_pem_certs(path: &Path) -> Vec<CertificateDer<'static>> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+    rustls_pemfile::certs(&mut reader)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap()
+}
+
+fn load_pem_key(path: &Path) -> PrivateKeyDer<'static> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(path).unwrap());
+    rustls_pemfile::private_key(&mut reader).unwrap().unwrap()
+}
+
+fn connector(pki: &TestPki, client_identity: Option<(PathBuf, PathBuf)>) -> TlsConnector {
+    let mut roots = RootCertStore::empty();
+    for cert in load_pem_certs(&pki.ca_path()) {
+        roots.add(cert).unwrap();
+    }
+    let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    let config = match client_identity {
+        Some((cert_path, key_path)) => builder
+            .with_client_auth_cert(load_pem_certs(&cert_path), load_pem_key(&key_path))
+            .unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    TlsConnector::from(Arc::new(config))
+}
+
+async fn start_server(
+    settings: Settings,
+) -> (
+    mpsc::Receiver<FileWriterCommand>,
+    broadcast::Sender<()>,
+    tokio::task::JoinHandle<Result<(), std::io::Error>>,
+) {
+    let settings = Arc::new(settings);
+    let server = settings.server.clone();
+    let (tx, rx) = mpsc::channel::<FileWriterCommand>(10);
+    let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+    let (shutdown_tx, shutdown_rx) = broadcast::channel(1);
+    let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
+    let handle =
+        tokio::spawn(
+            async move { TcpServer::start(settings, server, sender.into(), controls).await },
+        );
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    (rx, shutdown_tx, handle)
+}
+
+#[tokio::test]
+async fn test_tls_listener_receives_lines() {
+    let pki = TestPki::new();
+    let (cert_path, key_path) = pki.issue("server", vec!["localhost".to_string()]);
+    let port = free_port();
+    let settings = settings_template(
+        port,
+        TlsConfig {
+            cert_path,
+            key_path,
+            client_ca_path: None,
+        },
+    );
+    let (mut rx, shutdown_tx, handle) = start_server(settings).await;
+
+    let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let mut tls_stream = connector(&pki, None)
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+        .expect("TLS handshake should succeed");
+    tls_stream
+        .write_all(b"secret line 1\nsecret line 2\n")
+        .await
+        .unwrap();
+    tls_stream.flush().await.unwrap();
+
+    for expected in ["secret line 1", "secret line 2"] {
+        let command = timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .expect("Should receive message within timeout");
+        assert!(matches!(
+            command,
+            Some(FileWriterCommand::Write(ref m)) if m.payload == expected.as_bytes()
+        ));
+    }
+
+    shutdown_tx.send(()).unwrap();
+    let result = timeout(Duration::from_secs(1), handle).await.unwrap();
+    assert!(result.unwrap().is_ok());
+}
+
+#[tokio::test]
+async fn test_tls_listener_rejects_plaintext_clients() {
+    let pki = TestPki::new();
+    let (cert_path, key_path) = pki.issue("server", vec!["localhost".to_string()]);
+    let port = free_port();
+    let settings = settings_template(
+        port,
+        TlsConfig {
+            cert_path,
+            key_path,
+            client_ca_path: None,
+        },
+    );
+    let (mut rx, shutdown_tx, handle) = start_server(settings).await;
+
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    stream.write_all(b"plaintext line\n").await.unwrap();
+
+    let received = timeout(Duration::from_millis(300), rx.recv()).await;
+    assert!(
+        received.is_err(),
+        "Plaintext data must not be forwarded, got: {:?}",
+        received
+    );
+
+    shutdown_tx.send(()).unwrap();
+    let _ = timeout(Duration::from_secs(1), handle).await;
+}
+
+#[tokio::test]
+async fn test_mtls_listener_requires_client_certificate() {
+    let pki = TestPki::new();
+    let (cert_path, key_path) = pki.issue("server", vec!["localhost".to_string()]);
+    let client_identity = pki.issue("client", vec!["client".to_string()]);
+    let port = free_port();
+    let settings = settings_template(
+        port,
+        TlsConfig {
+            cert_path,
+            key_path,
+            client_ca_path: Some(pki.ca_path()),
+        },
+    );
+    let (mut rx, shutdown_tx, handle) = start_server(settings).await;
+
+    // Without a client certificate the server aborts the handshake
+    let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    if let Ok(mut anonymous) = connector(&pki, None)
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+    {
+        // TLS 1.3 reports the client certificate rejection after the handshake
+        let _ = anonymous.write_all(b"anonymous line\n").await;
+        let _ = anonymous.flush().await;
+    }
+    let received = timeout(Duration::from_millis(300), rx.recv()).await;
+    assert!(
+        received.is_err(),
+        "Clients without certificate must be rejected, got: {:?}",
+        received
+    );
+
+    // With a certificate signed by the configured CA the line is forwarded
+    let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let mut authenticated = connector(&pki, Some(client_identity))
+        .connect(ServerName::try_from("localhost").unwrap(), stream)
+        .await
+        .expect("mTLS handshake should succeed");
+    authenticated.write_all(b"trusted line\n").await.unwrap();
+    authenticated.flush().await.unwrap();
+
+    let command = timeout(Duration::from_secs(1), rx.recv()).await.unwrap();
+    assert!(matches!(
+        command,
+        Some(FileWriterCommand::Write(ref m)) if m.payload == b"trusted line"[..]
+    ));
+
+    shutdown_tx.send(()).unwrap();
+    let _ = timeout(Duration::from_secs(1), handle).await;
+}
+
+#[tokio::test]
+async fn test_tls_listener_fails_to_start_with_missing_certificate() {
+    let temp_dir = TempDir::new().unwrap();
+    let settings = settings_template(
+        free_port(),
+        TlsConfig {
+            cert_path: temp_dir.path().join("missing.pem"),
+            key_path: temp_dir.path().join("missing.key"),
+            client_ca_path: None,
+        },
+    );
+    let (_rx, _shutdown_tx, handle) = start_server(settings).await;
+
+    let result = timeout(Duration::from_secs(1), handle).await.unwrap();
+    let err = result
+        .unwrap()
+        .expect_err("Server should fail without cert");
+    assert!(
+        err.to_string().contains("missing.pem"),
+        "Error should mention the certificate path, got: {}",
+        err
+    );
+}
@@ -1,9 +1,11 @@
 use log::info;
 
 use jon_listen::listener::udp_server::UdpService;
+use jon_listen::listener::ListenerControls;
 use jon_listen::settings::{BackpressurePolicy, *};
 use jon_listen::writer::file_writer::FileWriterCommand;
 
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -24,15 +26,33 @@ fn settings_template() -> Settings {
         host: "0.0.0.0".to_string(),
         port: 0,
         max_connections: 1000,
+        format: MessageFormat::Raw,
+        tls: None,
+        rate_limit: None,
+        allow: vec![],
+        deny: vec![],
+        idle_timeout_ms: None,
+        read_timeout_ms: None,
+        max_connection_lifetime_ms: None,
+        max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        oversized_policy: OversizedPolicy::Truncate,
+        udp_sockets: None,
+        udp_recv_buffer_size: None,
+        udp_batch_size: None,
     };
     let rotation_policy_config = RotationPolicyConfig {
         count: 10,
         policy: RotationPolicyType::ByDuration,
         duration: Option::default(),
+        max_size: None,
+        compression: Compression::None,
     };
     let formatting_config = FormattingConfig {
         startingmsg: false,
         endingmsg: false,
+        output: OutputFormat::Plain,
+        prefix: None,
+        timezone: Timezone::Local,
     };
     let file_config = FileWriterConfig {
         filedir: PathBuf::from(r"/tmp/"),
@@ -40,14 +60,21 @@ fn settings_template() -> Settings {
         rotation: rotation_policy_config,
         formatting: formatting_config,
         backpressure_policy: BackpressurePolicy::Block,
+        buffer: BufferConfig::default(),
+        durability: DurabilityConfig::default(),
+        spill_max_size: 1024 * 1024,
     };
     Settings {
         debug: false,
         threads: 1,
         buffer_bound: 20,
         server,
+        listeners: vec![],
         filewriter: file_config,
+        writers: HashMap::new(),
+        routes: vec![],
         metrics_port: 9090,
+        drain_timeout_ms: 5000,
     }
 }
 
@@ -82,7 +109,13 @@ fn it_receives_multiple_messages() {
             use jon_listen::writer::backpressure::BackpressureAwareSender;
             let backpressure_sender =
                 BackpressureAwareSender::new(file_writer_tx, BackpressurePolicy::Block);
-            let mut service = UdpService::new(socket, backpressure_sender, 1, settings_ref);
+            let mut service = UdpService::new(
+                socket,
+                backpressure_sender.into(),
+                1,
+                settings_ref.clone(),
+                settings_ref.server.clone(),
+            );
             // Run until shutdown signal is received
             let _ = service.run(shutdown_rx).await;
         });
@@ -118,7 +151,7 @@ fn it_receives_multiple_messages() {
         let msg: &[u8] = msg.as_ref();
         let received_msg = sync_rx.recv_timeout(Duration::from_secs(4));
         assert!(received_msg.is_ok());
-        assert!(matches!(received_msg, Ok(FileWriterCommand::Write(ref v)) if v.as_slice() == msg));
+        assert!(matches!(received_msg, Ok(FileWriterCommand::Write(ref m)) if m.payload == msg));
     }
 
     info!("Received {} messages successfully", msgs.len());
@@ -153,8 +186,13 @@ mod unit_tests {
         let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
 
         let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
-        let service =
-            jon_listen::listener::udp_server::UdpService::new(socket, sender, 42, settings);
+        let service = jon_listen::listener::udp_server::UdpService::new(
+            socket,
+            sender.into(),
+            42,
+            settings.clone(),
+            settings.server.clone(),
+        );
 
         assert_eq!(service.id, 42);
         assert_eq!(service.name, "server-udp-42");
@@ -169,8 +207,13 @@ mod unit_tests {
         let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
         let server_addr = server_socket.local_addr().unwrap();
 
-        let mut service =
-            jon_listen::listener::udp_server::UdpService::new(server_socket, sender, 0, settings);
+        let mut service = jon_listen::listener::udp_server::UdpService::new(
+            server_socket,
+            sender.into(),
+            0,
+            settings.clone(),
+            settings.server.clone(),
+        );
 
         let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
 
@@ -193,8 +236,11 @@ mod unit_tests {
         assert!(received.is_ok());
         let command = received.unwrap().unwrap();
         match command {
-            FileWriterCommand::Write(data) => {
-                assert_eq!(data, test_message.to_vec());
+            FileWriterCommand::Write(message) => {
+                assert_eq!(message.payload, test_message.to_vec());
+                assert_eq!(message.peer, client_socket.local_addr().unwrap());
+                assert_eq!(message.listener, server_addr);
+                assert_eq!(message.protocol, ProtocolType::UDP);
             }
             _ => panic!("Expected Write command"),
         }
@@ -204,6 +250,184 @@ mod unit_tests {
         let _ = timeout(Duration::from_secs(1), service_handle).await;
     }
 
+    #[tokio::test]
+    async fn test_udp_service_reports_ipv4_peers_of_a_dual_stack_socket_as_ipv4() {
+        let settings = Arc::new(settings_template());
+        let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+
+        // No IPv6 on this host, nothing to check
+        let Ok(server_socket) = UdpSocket::bind("[::]:0").await else {
+            return;
+        };
+        let port = server_socket.local_addr().unwrap().port();
+
+        let mut service = jon_listen::listener::udp_server::UdpService::new(
+            server_socket,
+            sender.into(),
+            0,
+            settings.clone(),
+            settings.server.clone(),
+        );
+        let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let service_handle = tokio::spawn(async move { service.run(shutdown_rx).await });
+
+        let client_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        if client_socket
+            .send_to(b"test message\n", ("127.0.0.1", port))
+            .await
+            .is_err()
+        {
+            // IPv6-only socket, IPv4 peers can't reach it
+            return;
+        }
+
+        let Ok(Some(FileWriterCommand::Write(message))) =
+            timeout(Duration::from_millis(500), rx.recv()).await
+        else {
+            // IPv6-only socket, the datagram never arrived
+            return;
+        };
+        assert_eq!(message.peer, client_socket.local_addr().unwrap());
+        assert!(message.peer.is_ipv4());
+
+        shutdown_tx.send(()).unwrap();
+        let _ = timeout(Duration::from_secs(1), service_handle).await;
+    }
+
+    #[tokio::test]
+    async fn test_udp_service_drops_datagrams_above_rate_limit() {
+        use jon_listen::listener::rate_limit::RateLimiter;
+
+        let settings = Arc::new(settings_template());
+        let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+        let rate_limiter = RateLimiter::new(
+            "test",
+            &RateLimitConfig {
+                rate: 1.0,
+                burst: Some(2.0),
+                action: RateLimitAction::Drop,
+                overrides: vec![],
+            },
+        )
+        .unwrap();
+
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server_socket.local_addr().unwrap();
+        let mut service = jon_listen::listener::udp_server::UdpService::new(
+            server_socket,
+            sender.into(),
+            0,
+            settings.clone(),
+            settings.server.clone(),
+        )
+        .with_rate_limiter(rate_limiter);
+
+        let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let service_handle = tokio::spawn(async move { service.run(shutdown_rx).await });
+
+        let client_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for payload in [b"one", b"two", b"333"] {
+            client_socket.send_to(payload, server_addr).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut received = vec![];
+        while let Ok(FileWriterCommand::Write(message)) = rx.try_recv() {
+            received.push(message.payload);
+        }
+        assert_eq!(received, vec![b"one".to_vec(), b"two".to_vec()]);
+
+        shutdown_tx.send(()).unwrap();
+        let _ = timeout(Duration::from_secs(1), service_handle).await;
+    }
+
+    #[tokio::test]
+    async fn test_udp_service_splits_oversized_datagrams() {
+        let mut settings = settings_template();
+        settings.server.max_message_size = 4;
+        settings.server.oversized_policy = OversizedPolicy::Split;
+        let settings = Arc::new(settings);
+        let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server_socket.local_addr().unwrap();
+        let mut service = jon_listen::listener::udp_server::UdpService::new(
+            server_socket,
+            sender.into(),
+            0,
+            settings.clone(),
+            settings.server.clone(),
+        );
+
+        let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let service_handle = tokio::spawn(async move { service.run(shutdown_rx).await });
+
+        let client_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client_socket
+            .send_to(b"0123456789", server_addr)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let mut received = vec![];
+        while let Ok(FileWriterCommand::Write(message)) = rx.try_recv() {
+            received.push(message.payload);
+        }
+        assert_eq!(
+            received,
+            vec![b"0123".to_vec(), b"4567".to_vec(), b"89".to_vec()]
+        );
+
+        shutdown_tx.send(()).unwrap();
+        let _ = timeout(Duration::from_secs(1), service_handle).await;
+    }
+
+    #[tokio::test]
+    async fn test_udp_service_drops_datagrams_from_denied_peers() {
+        use jon_listen::listener::access_list::AccessList;
+
+        let settings = Arc::new(settings_template());
+        let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+        let access_list = AccessList::new(&["10.0.0.0/8".to_string()], &[]).unwrap();
+
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server_socket.local_addr().unwrap();
+        let mut service = jon_listen::listener::udp_server::UdpService::new(
+            server_socket,
+            sender.into(),
+            0,
+            settings.clone(),
+            settings.server.clone(),
+        )
+        .with_access_list(access_list.clone());
+
+        let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let service_handle = tokio::spawn(async move { service.run(shutdown_rx).await });
+
+        let client_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client_socket.send_to(b"denied", server_addr).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(rx.try_recv().is_err());
+
+        access_list.set(&["127.0.0.1".to_string()], &[]).unwrap();
+        client_socket
+            .send_to(b"allowed", server_addr)
+            .await
+            .unwrap();
+        let received = timeout(Duration::from_secs(1), rx.recv()).await.unwrap();
+        assert!(matches!(
+            received,
+            Some(FileWriterCommand::Write(message)) if message.payload == b"allowed"[..]
+        ));
+
+        shutdown_tx.send(()).unwrap();
+        let _ = timeout(Duration::from_secs(1), service_handle).await;
+    }
+
     #[tokio::test]
     async fn test_udp_service_handles_debug_mode() {
         let mut settings = settings_template();
@@ -216,8 +440,13 @@ mod unit_tests {
         let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
         let server_addr = server_socket.local_addr().unwrap();
 
-        let mut service =
-            jon_listen::listener::udp_server::UdpService::new(server_socket, sender, 0, settings);
+        let mut service = jon_listen::listener::udp_server::UdpService::new(
+            server_socket,
+            sender.into(),
+            0,
+            settings.clone(),
+            settings.server.clone(),
+        );
 
         let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
 
@@ -238,8 +467,8 @@ mod unit_tests {
         assert!(received.is_ok());
         let command = received.unwrap().unwrap();
         match command {
-            FileWriterCommand::WriteDebug(id, data, count) => {
-                assert_eq!(data, test_message.to_vec());
+            FileWriterCommand::WriteDebug(id, message, count) => {
+                assert_eq!(message.payload, test_message.to_vec());
                 assert_eq!(count, 1);
                 assert!(id.contains("server-udp"));
             }
@@ -257,8 +486,13 @@ mod unit_tests {
         let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
 
         let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
-        let mut service =
-            jon_listen::listener::udp_server::UdpService::new(server_socket, sender, 0, settings);
+        let mut service = jon_listen::listener::udp_server::UdpService::new(
+            server_socket,
+            sender.into(),
+            0,
+            settings.clone(),
+            settings.server.clone(),
+        );
 
         let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
 
@@ -287,11 +521,18 @@ mod unit_tests {
         // Test that server can bind to address
         let mut test_settings = (*settings).clone();
         test_settings.server.port = 0;
+        let server = test_settings.server.clone();
         let test_settings = Arc::new(test_settings);
 
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
         let server_handle = tokio::spawn(async move {
-            jon_listen::listener::udp_server::UdpServer::start(test_settings, sender, shutdown_rx)
-                .await
+            jon_listen::listener::udp_server::UdpServer::start(
+                test_settings,
+                server,
+                sender.into(),
+                controls,
+            )
+            .await
         });
 
         // Give it time to bind
@@ -310,11 +551,18 @@ mod unit_tests {
 
         let mut test_settings = (*settings).clone();
         test_settings.server.port = 0;
+        let server = test_settings.server.clone();
         let test_settings = Arc::new(test_settings);
 
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
         let server_handle = tokio::spawn(async move {
-            jon_listen::listener::udp_server::UdpServer::start(test_settings, sender, shutdown_rx)
-                .await
+            jon_listen::listener::udp_server::UdpServer::start(
+                test_settings,
+                server,
+                sender.into(),
+                controls,
+            )
+            .await
         });
 
         tokio::time::sleep(Duration::from_millis(50)).await;
@@ -327,6 +575,270 @@ mod unit_tests {
         assert!(server_result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_udp_server_spreads_datagrams_over_reuseport_sockets() {
+        let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(100);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+        let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+
+        // Sockets sharing a port need it known up front
+        let port = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut test_settings = settings_template();
+        test_settings.server.host = "127.0.0.1".to_string();
+        test_settings.server.port = port as i32;
+        test_settings.server.udp_sockets = Some(4);
+        test_settings.server.udp_recv_buffer_size = Some(256 * 1024);
+        let server = test_settings.server.clone();
+        let test_settings = Arc::new(test_settings);
+
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
+        let server_handle = tokio::spawn(async move {
+            jon_listen::listener::udp_server::UdpServer::start(
+                test_settings,
+                server,
+                sender.into(),
+                controls,
+            )
+            .await
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Each client hashes to one of the sockets, all of them are read
+        for i in 0..20 {
+            let client_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+            client_socket
+                .send_to(format!("message {}", i).as_bytes(), ("127.0.0.1", port))
+                .await
+                .unwrap();
+        }
+        let mut received = 0;
+        while let Ok(Some(command)) = timeout(Duration::from_millis(500), rx.recv()).await {
+            assert!(matches!(command, FileWriterCommand::Write(_)));
+            received += 1;
+            if received == 20 {
+                break;
+            }
+        }
+        assert_eq!(received, 20);
+
+        shutdown_tx.send(()).unwrap();
+        let result = timeout(Duration::from_secs(1), server_handle).await;
+        assert!(result.unwrap().unwrap().is_ok());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_udp_service_receives_datagrams_in_batches() {
+        let mut settings = settings_template();
+        settings.server.udp_batch_size = Some(4);
+        let settings = Arc::new(settings);
+        let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server_socket.local_addr().unwrap();
+        let client_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let client_addr = client_socket.local_addr().unwrap();
+        // Queued before the service starts, so that they are read together
+        for i in 0..6 {
+            client_socket
+                .send_to(format!("message {}", i).as_bytes(), server_addr)
+                .await
+                .unwrap();
+        }
+
+        let mut service = jon_listen::listener::udp_server::UdpService::new(
+            server_socket,
+            sender.into(),
+            0,
+            settings.clone(),
+            settings.server.clone(),
+        );
+        let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let service_handle = tokio::spawn(async move { service.run(shutdown_rx).await });
+
+        let mut batches = vec![];
+        while let Ok(Some(command)) = timeout(Duration::from_millis(200), rx.recv()).await {
+            match command {
+                FileWriterCommand::WriteBatch(messages) => batches.push(messages),
+                other => panic!("Expected WriteBatch command, got {:?}", other),
+            }
+        }
+        assert_eq!(batches.iter().map(Vec::len).collect::<Vec<_>>(), vec![4, 2]);
+        let messages: Vec<_> = batches.into_iter().flatten().collect();
+        assert!(messages.iter().all(|m| m.peer == client_addr));
+        let payloads: Vec<_> = messages.into_iter().map(|m| m.payload).collect();
+        let expected: Vec<_> = (0..6)
+            .map(|i| format!("message {}", i).into_bytes())
+            .collect();
+        assert_eq!(payloads, expected);
+
+        shutdown_tx.send(()).unwrap();
+        let _ = timeout(Duration::from_secs(1), service_handle).await;
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_udp_service_keeps_batched_datagrams_across_receive_buffers() {
+        let mut settings = settings_template();
+        settings.server.udp_batch_size = Some(4);
+        let settings = Arc::new(settings);
+        let (tx, mut rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+
+        let server_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let server_addr = server_socket.local_addr().unwrap();
+        let client_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for i in 0..3 {
+            client_socket
+                .send_to(format!("message {}", i).as_bytes(), server_addr)
+                .await
+                .unwrap();
+        }
+
+        let mut service = jon_listen::listener::udp_server::UdpService::new(
+            server_socket,
+            sender.into(),
+            0,
+            settings.clone(),
+            settings.server.clone(),
+        );
+        let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+        let service_handle = tokio::spawn(async move { service.run(shutdown_rx).await });
+
+        let mut batches = vec![];
+        let first = timeout(Duration::from_secs(1), rx.recv()).await.unwrap();
+        batches.push(first.unwrap());
+        // Only one slot is left in the first buffer, the rest go to a new one
+        for i in 3..6 {
+            client_socket
+                .send_to(format!("message {}", i).as_bytes(), server_addr)
+                .await
+                .unwrap();
+        }
+        while let Ok(Some(command)) = timeout(Duration::from_millis(200), rx.recv()).await {
+            batches.push(command);
+        }
+        let batches: Vec<_> = batches
+            .into_iter()
+            .map(|command| match command {
+                FileWriterCommand::WriteBatch(messages) => messages,
+                other => panic!("Expected WriteBatch command, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(batches[0].len(), 3);
+        assert_eq!(batches[1].len(), 1);
+        let payloads: Vec<_> = batches.into_iter().flatten().map(|m| m.payload).collect();
+        let expected: Vec<_> = (0..6)
+            .map(|i| format!("message {}", i).into_bytes())
+            .collect();
+        assert_eq!(payloads, expected);
+
+        shutdown_tx.send(()).unwrap();
+        let _ = timeout(Duration::from_secs(1), service_handle).await;
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_udp_server_rejects_invalid_batch_size() {
+        let (tx, _rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+
+        let mut test_settings = settings_template();
+        test_settings.server.port = 0;
+        test_settings.server.udp_batch_size = Some(0);
+        let server = test_settings.server.clone();
+
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
+        let result = jon_listen::listener::udp_server::UdpServer::start(
+            Arc::new(test_settings),
+            server,
+            sender.into(),
+            controls,
+        )
+        .await;
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn test_udp_server_rejects_zero_sockets() {
+        let (tx, _rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+        let (_shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+
+        let mut test_settings = settings_template();
+        test_settings.server.port = 0;
+        test_settings.server.udp_sockets = Some(0);
+        let server = test_settings.server.clone();
+
+        let controls = ListenerControls::for_server(&server, shutdown_rx).unwrap();
+        let result = jon_listen::listener::udp_server::UdpServer::start(
+            Arc::new(test_settings),
+            server,
+            sender.into(),
+            controls,
+        )
+        .await;
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_udp_servers_report_kernel_stats_as_separate_series() {
+        let _ = metrics::init(9113);
+        let (tx, _rx) = mpsc::channel::<FileWriterCommand>(10);
+        let sender = BackpressureAwareSender::new(tx, BackpressurePolicy::Block);
+        let (shutdown_tx, shutdown_rx) = broadcast::channel::<()>(1);
+
+        let mut labels = vec![];
+        let mut servers = vec![];
+        for _ in 0..2 {
+            // A free port, released for the server to bind
+            let port = std::net::UdpSocket::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            let mut test_settings = settings_template();
+            test_settings.server.host = "127.0.0.1".to_string();
+            test_settings.server.port = port as i32;
+            test_settings.server.udp_sockets = Some(1);
+            let server = test_settings.server.clone();
+            labels.push(format!("127.0.0.1:{}#0", port));
+            let controls =
+                ListenerControls::for_server(&server, shutdown_rx.resubscribe()).unwrap();
+            servers.push(tokio::spawn(
+                jon_listen::listener::udp_server::UdpServer::start(
+                    Arc::new(test_settings),
+                    server,
+                    sender.clone().into(),
+                    controls,
+                ),
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(200)).await;
+
+        let output = metrics::get_handle().unwrap().render();
+        for label in &labels {
+            assert!(
+                output.contains(&format!("udp_recv_buffer_bytes{{socket=\"{}\"}}", label)),
+                "Metrics output should have a series for {}, got: {}",
+                label,
+                output
+            );
+        }
+
+        shutdown_tx.send(()).unwrap();
+        for server in servers {
+            server.await.unwrap().unwrap();
+        }
+    }
+
     #[tokio::test]
     async fn test_udp_server_metrics_datagram_received() {
         let _ = metrics::init(9111);
@@ -0,0 +1,64 @@
+#![cfg(target_os = "linux")]
+
+use jon_listen::listener::udp_stats::{parse_table, KernelStats, SocketStats};
+use socket2::SockRef;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+const TABLE: &str = "\
+   sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode ref pointer drops
+  102: 0100007F:A1C3 00000000:0000 07 00000000:00000A40 00:00000000 00000000  1000        0 81234 2 0000000000000000 17
+  571: 00000000:0202 00000000:0000 07 00000000:00000000 00:00000000 00000000     0        0 4567 2 0000000000000000 0
+  not a socket line
+";
+
+#[test]
+fn test_parse_table_reads_queue_and_drops() {
+    let stats: Vec<SocketStats> = parse_table(TABLE).collect();
+
+    assert_eq!(
+        stats,
+        vec![
+            SocketStats {
+                inode: 81234,
+                rx_queue: 0xA40,
+                drops: 17,
+            },
+            SocketStats {
+                inode: 4567,
+                rx_queue: 0,
+                drops: 0,
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_kernel_stats_reports_drops_of_a_full_receive_buffer() {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    // The kernel rounds it up to its minimum, a few datagrams at most
+    SockRef::from(&socket).set_recv_buffer_size(1024).unwrap();
+    let addr = socket.local_addr().unwrap();
+    let label = format!("{}#0", addr);
+    let mut stats = KernelStats::new([(label.clone(), &socket)]).unwrap();
+
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    for _ in 0..100 {
+        client.send_to(&[b'x'; 1000], addr).await.unwrap();
+    }
+
+    let collected = stats.collect().await.unwrap();
+    let socket_stats = collected[&label];
+    assert!(socket_stats.rx_queue > 0);
+    assert!(socket_stats.drops > 0);
+
+    // Reading the queue empties it, the drops stay counted
+    let mut buf = [0u8; 1024];
+    while let Ok(received) = timeout(Duration::from_millis(100), socket.recv(&mut buf)).await {
+        received.unwrap();
+    }
+    let collected = stats.collect().await.unwrap();
+    assert_eq!(collected[&label].rx_queue, 0);
+    assert_eq!(collected[&label].drops, socket_stats.drops);
+}
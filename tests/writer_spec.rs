@@ -4,6 +4,7 @@ use jon_listen::settings::{BackpressurePolicy, *};
 use jon_listen::writer::file_writer::FileWriterCommand;
 use jon_listen::writer::file_writer::*;
 
+use std::collections::HashMap;
 use std::fs::{self, File};
 
 use std::io::prelude::*;
@@ -23,15 +24,33 @@ fn settings_template() -> Settings {
         host: "0.0.0.0".to_string(),
         port: 0,
         max_connections: 1000,
+        format: MessageFormat::Raw,
+        tls: None,
+        rate_limit: None,
+        allow: vec![],
+        deny: vec![],
+        idle_timeout_ms: None,
+        read_timeout_ms: None,
+        max_connection_lifetime_ms: None,
+        max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        oversized_policy: OversizedPolicy::Truncate,
+        udp_sockets: None,
+        udp_recv_buffer_size: None,
+        udp_batch_size: None,
     };
     let rotation_policy_config = RotationPolicyConfig {
         count: 10,
         policy: RotationPolicyType::ByDuration,
         duration: Option::Some(9999999),
+        max_size: None,
+        compression: Compression::None,
     };
     let formatting_config = FormattingConfig {
         startingmsg: false,
         endingmsg: false,
+        output: OutputFormat::Plain,
+        prefix: None,
+        timezone: Timezone::Local,
     };
     let file_config = FileWriterConfig {
         filedir: PathBuf::from(r"/tmp/"),
@@ -39,14 +58,21 @@ fn settings_template() -> Settings {
         rotation: rotation_policy_config,
         formatting: formatting_config,
         backpressure_policy: BackpressurePolicy::Block,
+        buffer: BufferConfig::default(),
+        durability: DurabilityConfig::default(),
+        spill_max_size: 1024 * 1024,
     };
     Settings {
         debug: false,
         threads: 1,
         buffer_bound: 20,
         server,
+        listeners: vec![],
         filewriter: file_config,
+        writers: HashMap::new(),
+        routes: vec![],
         metrics_port: 9090,
+        drain_timeout_ms: 5000,
     }
 }
 
@@ -82,7 +108,12 @@ async fn it_writes_multiple_messages() {
     info!("Sending {} messages", msgs.len());
     for msg in &msgs {
         let _ = file_writer_tx
-            .send(FileWriterCommand::Write(msg.as_bytes().to_vec()))
+            .send(FileWriterCommand::Write(Message::new(
+                msg.as_bytes().to_vec(),
+                "127.0.0.1:40000".parse().unwrap(),
+                "127.0.0.1:8080".parse().unwrap(),
+                ProtocolType::UDP,
+            )))
             .await;
     }
 